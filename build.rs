@@ -0,0 +1,11 @@
+// The Postgres integration tests need a database to create their own from, like the query macros
+// they are only compiled when 'DATABASE_URL' is set
+fn main() {
+    // Rebuild when a migration is added as 'sqlx::migrate!' embeds them
+    println!("cargo:rerun-if-changed=migrations");
+    println!("cargo:rerun-if-env-changed=DATABASE_URL");
+    println!("cargo:rustc-check-cfg=cfg(postgres_tests)");
+    if std::env::var_os("DATABASE_URL").is_some() {
+        println!("cargo:rustc-cfg=postgres_tests");
+    }
+}
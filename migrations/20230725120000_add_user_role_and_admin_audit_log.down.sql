@@ -0,0 +1,11 @@
+DROP TABLE IF EXISTS admin_audit_log;
+
+ALTER TABLE "user"
+  DROP COLUMN IF EXISTS tokens_revoked_at,
+  DROP COLUMN IF EXISTS restriction_expires_at,
+  DROP COLUMN IF EXISTS restriction_reason,
+  DROP COLUMN IF EXISTS restriction,
+  DROP COLUMN IF EXISTS role;
+
+DROP TYPE IF EXISTS user_restriction;
+DROP TYPE IF EXISTS user_role;
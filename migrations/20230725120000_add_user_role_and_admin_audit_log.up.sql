@@ -0,0 +1,22 @@
+CREATE TYPE user_role AS ENUM ('user', 'moderator', 'admin');
+CREATE TYPE user_restriction AS ENUM ('suspended', 'banned');
+
+ALTER TABLE "user"
+  ADD COLUMN role user_role NOT NULL DEFAULT 'user',
+  -- restriction_expires_at being NULL means the restriction never lifts by itself
+  ADD COLUMN restriction user_restriction,
+  ADD COLUMN restriction_reason TEXT,
+  ADD COLUMN restriction_expires_at TIMESTAMPTZ,
+  -- access tokens issued at or before this moment are rejected (used for force logout)
+  ADD COLUMN tokens_revoked_at TIMESTAMPTZ;
+
+CREATE TABLE IF NOT EXISTS admin_audit_log (
+  id UUID DEFAULT gen_random_uuid() PRIMARY KEY,
+  actor_id UUID NOT NULL REFERENCES "user" (id),
+  action VARCHAR(50) NOT NULL,
+  target_user_id UUID REFERENCES "user" (id),
+  details JSONB NOT NULL DEFAULT '{}'::jsonb,
+  created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS admin_audit_log_target_user_id_idx ON admin_audit_log (target_user_id);
@@ -0,0 +1,6 @@
+DROP TABLE IF EXISTS user_warning;
+DROP TABLE IF EXISTS report;
+
+DROP TYPE IF EXISTS report_action;
+DROP TYPE IF EXISTS report_status;
+DROP TYPE IF EXISTS report_category;
@@ -0,0 +1,29 @@
+CREATE TYPE report_category AS ENUM ('spam', 'harassment', 'hate_speech', 'inappropriate_content', 'impersonation', 'other');
+CREATE TYPE report_status AS ENUM ('pending', 'dismissed', 'actioned');
+CREATE TYPE report_action AS ENUM ('dismiss', 'warn', 'suspend');
+
+CREATE TABLE IF NOT EXISTS report (
+  id UUID DEFAULT gen_random_uuid() PRIMARY KEY,
+  reporter_id UUID NOT NULL REFERENCES "user" (id),
+  target_user_id UUID NOT NULL REFERENCES "user" (id),
+  category report_category NOT NULL,
+  description TEXT,
+  status report_status NOT NULL DEFAULT 'pending',
+  action report_action,
+  moderator_id UUID REFERENCES "user" (id),
+  moderator_note TEXT,
+  created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+  resolved_at TIMESTAMPTZ
+);
+
+-- moderation queue always reads pending reports from the oldest one
+CREATE INDEX IF NOT EXISTS report_status_created_at_idx ON report (status, created_at);
+
+CREATE TABLE IF NOT EXISTS user_warning (
+  id UUID DEFAULT gen_random_uuid() PRIMARY KEY,
+  user_id UUID NOT NULL REFERENCES "user" (id),
+  moderator_id UUID NOT NULL REFERENCES "user" (id),
+  report_id UUID REFERENCES report (id),
+  reason TEXT NOT NULL,
+  created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
@@ -0,0 +1,2 @@
+DROP INDEX IF EXISTS user_email_lower_key;
+ALTER TABLE "user" ADD CONSTRAINT user_email_key UNIQUE (email);
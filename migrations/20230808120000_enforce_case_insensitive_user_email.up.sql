@@ -0,0 +1,27 @@
+-- Emails that only differ by letter case or surrounding whitespace belong to the same person.
+-- Stop here and list them instead of silently picking one account, they have to be merged by hand first.
+-- scripts/report_duplicate_emails.sql prints the affected accounts in detail.
+DO $$
+DECLARE
+  duplicate_emails TEXT;
+BEGIN
+  SELECT string_agg(format('%s (%s accounts)', normalized_email, account_count), ', ')
+  INTO duplicate_emails
+  FROM (
+    SELECT lower(trim(email)) AS normalized_email, count(*) AS account_count
+    FROM "user"
+    GROUP BY lower(trim(email))
+    HAVING count(*) > 1
+  ) AS duplicates;
+
+  IF duplicate_emails IS NOT NULL THEN
+    RAISE EXCEPTION 'duplicate user emails found: %', duplicate_emails
+      USING HINT = 'Run scripts/report_duplicate_emails.sql and resolve the duplicate accounts before migrating.';
+  END IF;
+END $$;
+
+-- Store every email in its normalized form from now on
+UPDATE "user" SET email = lower(trim(email)) WHERE email <> lower(trim(email));
+
+ALTER TABLE "user" DROP CONSTRAINT IF EXISTS user_email_key;
+CREATE UNIQUE INDEX IF NOT EXISTS user_email_lower_key ON "user" (lower(email));
@@ -0,0 +1,22 @@
+-- a row only exists while enrollment is pending or after two-factor authentication is enabled
+CREATE TABLE IF NOT EXISTS user_two_factor (
+  user_id UUID PRIMARY KEY REFERENCES "user" (id),
+  -- base32 encoded TOTP secret, it has to be readable to verify codes
+  secret VARCHAR(64) NOT NULL,
+  enabled BOOLEAN NOT NULL DEFAULT FALSE,
+  -- time step of the last accepted code so that a code cannot be replayed
+  last_used_step BIGINT,
+  created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+  enabled_at TIMESTAMPTZ
+);
+
+CREATE TABLE IF NOT EXISTS user_recovery_code (
+  id UUID DEFAULT gen_random_uuid() PRIMARY KEY,
+  user_id UUID NOT NULL REFERENCES "user" (id),
+  -- SHA-256 hex digest, recovery codes are random so a slow hash is not needed
+  code_hash VARCHAR(64) NOT NULL,
+  used_at TIMESTAMPTZ,
+  created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS user_recovery_code_user_id_idx ON user_recovery_code (user_id);
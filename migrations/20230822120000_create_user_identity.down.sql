@@ -0,0 +1,5 @@
+DROP TABLE IF EXISTS user_identity;
+
+-- users without password cannot be kept once the column is required again
+DELETE FROM "user" WHERE password IS NULL;
+ALTER TABLE "user" ALTER COLUMN password SET NOT NULL;
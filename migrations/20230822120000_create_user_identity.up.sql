@@ -0,0 +1,18 @@
+-- users signing in through an identity provider only may have no password
+ALTER TABLE "user" ALTER COLUMN password DROP NOT NULL;
+
+-- account at an external OpenID Connect provider linked to a user
+CREATE TABLE IF NOT EXISTS user_identity (
+  id UUID DEFAULT gen_random_uuid() PRIMARY KEY,
+  user_id UUID NOT NULL REFERENCES "user" (id),
+  -- provider name as configured in OIDC_PROVIDERS
+  provider VARCHAR(50) NOT NULL,
+  -- 'sub' claim of the id token, only unique within the provider
+  subject VARCHAR(255) NOT NULL,
+  -- email reported by the provider when the account was linked
+  email VARCHAR(100),
+  created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+  UNIQUE (provider, subject),
+  -- a user can link one account per provider
+  UNIQUE (user_id, provider)
+);
@@ -0,0 +1,4 @@
+DELETE FROM user_verification;
+ALTER TABLE user_verification DROP COLUMN expires_at;
+ALTER TABLE user_verification DROP COLUMN token_hash;
+ALTER TABLE user_verification ADD COLUMN secret VARCHAR(64) NOT NULL;
@@ -0,0 +1,6 @@
+-- pending tokens were signed with the dropped secrets and cannot be converted, those users have to be verified by an admin
+DELETE FROM user_verification;
+ALTER TABLE user_verification DROP COLUMN secret;
+-- SHA-256 hex digest of the opaque token sent to the user, the token itself is never stored
+ALTER TABLE user_verification ADD COLUMN token_hash VARCHAR(64) NOT NULL UNIQUE;
+ALTER TABLE user_verification ADD COLUMN expires_at TIMESTAMPTZ NOT NULL;
@@ -0,0 +1,2 @@
+-- fails on hashes longer than 128 characters, those users have to reset their password first
+ALTER TABLE "user" ALTER COLUMN password TYPE VARCHAR(128);
@@ -0,0 +1,2 @@
+-- Argon2id hashes carry their parameters, larger memory or iteration settings make them longer than bcrypt hashes
+ALTER TABLE "user" ALTER COLUMN password TYPE VARCHAR(255);
@@ -0,0 +1,3 @@
+DROP TABLE IF EXISTS user_login_history;
+DROP TABLE IF EXISTS user_login_lockout;
+DROP TYPE IF EXISTS login_failure_reason;
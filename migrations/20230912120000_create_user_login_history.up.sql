@@ -0,0 +1,24 @@
+CREATE TYPE login_failure_reason AS ENUM ('invalid_password', 'locked', 'invalid_two_factor_code');
+
+-- consecutive failed password logins, the row is removed once the user logs in successfully
+CREATE TABLE IF NOT EXISTS user_login_lockout (
+  user_id UUID PRIMARY KEY REFERENCES "user" (id),
+  failed_count INTEGER NOT NULL,
+  last_failed_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+  locked_until TIMESTAMPTZ
+);
+
+CREATE TABLE IF NOT EXISTS user_login_history (
+  id UUID DEFAULT gen_random_uuid() PRIMARY KEY,
+  user_id UUID NOT NULL REFERENCES "user" (id),
+  success BOOLEAN NOT NULL,
+  -- why the attempt failed, NULL for successful logins
+  failure_reason login_failure_reason,
+  ip VARCHAR(45) NOT NULL,
+  user_agent VARCHAR(512),
+  -- successful login from an ip or device the user never logged in from before, the user was notified
+  is_new_device BOOLEAN NOT NULL DEFAULT FALSE,
+  created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS user_login_history_user_id_created_at_idx ON user_login_history (user_id, created_at DESC);
@@ -0,0 +1,27 @@
+DROP TABLE IF EXISTS user_data_export;
+DROP TYPE IF EXISTS user_data_export_status;
+
+ALTER TABLE user_login_history
+  DROP CONSTRAINT user_login_history_user_id_fkey,
+  ADD CONSTRAINT user_login_history_user_id_fkey FOREIGN KEY (user_id) REFERENCES "user" (id);
+ALTER TABLE user_login_lockout
+  DROP CONSTRAINT user_login_lockout_user_id_fkey,
+  ADD CONSTRAINT user_login_lockout_user_id_fkey FOREIGN KEY (user_id) REFERENCES "user" (id);
+ALTER TABLE user_identity
+  DROP CONSTRAINT user_identity_user_id_fkey,
+  ADD CONSTRAINT user_identity_user_id_fkey FOREIGN KEY (user_id) REFERENCES "user" (id);
+ALTER TABLE user_recovery_code
+  DROP CONSTRAINT user_recovery_code_user_id_fkey,
+  ADD CONSTRAINT user_recovery_code_user_id_fkey FOREIGN KEY (user_id) REFERENCES "user" (id);
+ALTER TABLE user_two_factor
+  DROP CONSTRAINT user_two_factor_user_id_fkey,
+  ADD CONSTRAINT user_two_factor_user_id_fkey FOREIGN KEY (user_id) REFERENCES "user" (id);
+ALTER TABLE user_verification
+  DROP CONSTRAINT user_verification_user_id_fkey,
+  ADD CONSTRAINT user_verification_user_id_fkey FOREIGN KEY (user_id) REFERENCES "user" (id);
+
+DROP INDEX IF EXISTS user_deletion_scheduled_at_idx;
+
+ALTER TABLE "user"
+  DROP COLUMN IF EXISTS deleted_at,
+  DROP COLUMN IF EXISTS deletion_scheduled_at;
@@ -0,0 +1,46 @@
+ALTER TABLE "user"
+  -- the account is purged once this moment has passed, NULL while no deletion is requested
+  ADD COLUMN deletion_scheduled_at TIMESTAMPTZ,
+  -- purged accounts stay as anonymous placeholders so that moderation records keep their references
+  ADD COLUMN deleted_at TIMESTAMPTZ;
+
+CREATE INDEX IF NOT EXISTS user_deletion_scheduled_at_idx ON "user" (deletion_scheduled_at)
+  WHERE deletion_scheduled_at IS NOT NULL;
+
+-- rows owned by a user go along with it, moderation records keep referencing the user.
+-- the purge never deletes user rows, it anonymizes them and deletes these rows explicitly,
+-- so the cascades only guard against user rows being deleted by hand
+ALTER TABLE user_verification
+  DROP CONSTRAINT user_verification_user_id_fkey,
+  ADD CONSTRAINT user_verification_user_id_fkey FOREIGN KEY (user_id) REFERENCES "user" (id) ON DELETE CASCADE;
+ALTER TABLE user_two_factor
+  DROP CONSTRAINT user_two_factor_user_id_fkey,
+  ADD CONSTRAINT user_two_factor_user_id_fkey FOREIGN KEY (user_id) REFERENCES "user" (id) ON DELETE CASCADE;
+ALTER TABLE user_recovery_code
+  DROP CONSTRAINT user_recovery_code_user_id_fkey,
+  ADD CONSTRAINT user_recovery_code_user_id_fkey FOREIGN KEY (user_id) REFERENCES "user" (id) ON DELETE CASCADE;
+ALTER TABLE user_identity
+  DROP CONSTRAINT user_identity_user_id_fkey,
+  ADD CONSTRAINT user_identity_user_id_fkey FOREIGN KEY (user_id) REFERENCES "user" (id) ON DELETE CASCADE;
+ALTER TABLE user_login_lockout
+  DROP CONSTRAINT user_login_lockout_user_id_fkey,
+  ADD CONSTRAINT user_login_lockout_user_id_fkey FOREIGN KEY (user_id) REFERENCES "user" (id) ON DELETE CASCADE;
+ALTER TABLE user_login_history
+  DROP CONSTRAINT user_login_history_user_id_fkey,
+  ADD CONSTRAINT user_login_history_user_id_fkey FOREIGN KEY (user_id) REFERENCES "user" (id) ON DELETE CASCADE;
+
+CREATE TYPE user_data_export_status AS ENUM ('pending', 'completed', 'failed');
+
+CREATE TABLE IF NOT EXISTS user_data_export (
+  id UUID DEFAULT gen_random_uuid() PRIMARY KEY,
+  user_id UUID NOT NULL REFERENCES "user" (id) ON DELETE CASCADE,
+  status user_data_export_status NOT NULL DEFAULT 'pending',
+  -- JSON archive of everything stored about the user, only set once completed
+  archive JSONB,
+  created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+  completed_at TIMESTAMPTZ,
+  -- archives hold personal data, so they are only kept for a limited time
+  expires_at TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS user_data_export_user_id_idx ON user_data_export (user_id);
@@ -0,0 +1 @@
+DROP INDEX IF EXISTS report_pending_reporter_target_idx;
@@ -0,0 +1,3 @@
+-- a reporter can only have one open report per user, resolved reports don't block a new one
+CREATE UNIQUE INDEX IF NOT EXISTS report_pending_reporter_target_idx ON report (reporter_id, target_user_id)
+  WHERE status = 'pending';
@@ -0,0 +1,16 @@
+-- One-off report of accounts whose emails only differ by letter case or surrounding whitespace
+-- Usage: psql <database-url> -f scripts/report_duplicate_emails.sql
+SELECT
+  lower(trim(email)) AS normalized_email,
+  id,
+  email,
+  verified,
+  created_at
+FROM "user"
+WHERE lower(trim(email)) IN (
+  SELECT lower(trim(email))
+  FROM "user"
+  GROUP BY lower(trim(email))
+  HAVING count(*) > 1
+)
+ORDER BY normalized_email, created_at;
@@ -1,13 +1,14 @@
-use dotenvy::dotenv;
+use dotenvy::{dotenv, var};
 use time::format_description::well_known::Rfc3339;
 use time::OffsetDateTime;
 
 // Read environment variables from '.env' file
+// The log format is only known afterwards, so this line is always printed as single-line JSON
 pub fn load_env_vars() {
     match dotenv() {
         Ok(_) => println!(
             "{}",
-            serde_json::to_string_pretty(&serde_json::json!({
+            serde_json::to_string(&serde_json::json!({
               "function": "load_env_vars",
               "level": "info",
               "message": "environment variables successfully loaded",
@@ -19,3 +20,26 @@ pub fn load_env_vars() {
         Err(_) => panic!("Failed to load environment variables from '.env' file"),
     }
 }
+
+pub(crate) fn invalid_config(name: &str, expected: &str) -> anyhow::Error {
+    anyhow::anyhow!(
+        "Invalid config for environment variable {}. Expected {}.",
+        name,
+        expected
+    )
+}
+
+// Parse an optional environment variable, falling back to the default when it is not set
+pub(crate) fn parse_env<T: std::str::FromStr>(
+    name: &str,
+    default: T,
+    expected: &str,
+) -> Result<T, anyhow::Error> {
+    match var(name) {
+        Ok(value) => value
+            .trim()
+            .parse()
+            .map_err(|_| invalid_config(name, expected)),
+        Err(_) => Ok(default),
+    }
+}
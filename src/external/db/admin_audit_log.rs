@@ -0,0 +1,64 @@
+use super::internal_server_error;
+use super::models::AdminAuditLog;
+use crate::server::handlers::ErrorResponse;
+use axum::{http::StatusCode, Json};
+use sqlx::PgExecutor;
+use tracing::error;
+use uuid::Uuid;
+
+#[derive(Debug)]
+pub struct NewAdminAuditLog {
+    pub actor_id: Uuid,
+    pub action: String,
+    pub target_user_id: Option<Uuid>,
+    pub details: serde_json::Value,
+}
+
+#[tracing::instrument(skip(db_client))]
+pub async fn insert_admin_audit_log(
+    db_client: impl PgExecutor<'_>,
+    audit_log: NewAdminAuditLog,
+) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+    sqlx::query!(
+        "INSERT INTO admin_audit_log (actor_id, action, target_user_id, details) VALUES ($1, $2, $3, $4)",
+        audit_log.actor_id,
+        audit_log.action,
+        audit_log.target_user_id,
+        audit_log.details
+    )
+    .execute(db_client)
+    .await
+    .map_err(|error| {
+        error!(
+            "failed to insert new admin audit log record into database. {}",
+            error
+        );
+        internal_server_error()
+    })?;
+    Ok(())
+}
+
+#[tracing::instrument(skip(db_client))]
+pub async fn list_admin_audit_logs(
+    db_client: impl PgExecutor<'_>,
+    target_user_id: Option<Uuid>,
+    limit: i64,
+    offset: i64,
+) -> Result<Vec<AdminAuditLog>, (StatusCode, Json<ErrorResponse>)> {
+    sqlx::query_as!(
+        AdminAuditLog,
+        "SELECT id, actor_id, action, target_user_id, details, created_at FROM admin_audit_log
+         WHERE ($1::UUID IS NULL OR target_user_id = $1)
+         ORDER BY created_at DESC
+         LIMIT $2 OFFSET $3",
+        target_user_id,
+        limit,
+        offset
+    )
+    .fetch_all(db_client)
+    .await
+    .map_err(|error| {
+        error!("failed to list admin audit logs from database. {}", error);
+        internal_server_error()
+    })
+}
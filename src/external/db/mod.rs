@@ -1,11 +1,21 @@
+use crate::server::handlers::{internal_server_error, ErrorResponse};
+use axum::{http::StatusCode, Json};
 use dotenvy::var;
 use sqlx::postgres::PgPoolOptions;
-use sqlx::{Pool, Postgres};
-use tracing::info;
+use sqlx::{Pool, Postgres, Transaction};
+use tracing::{error, info};
 
+pub mod admin_audit_log;
 pub mod models;
+pub mod report;
+pub mod repository;
 pub mod user;
+pub mod user_data_export;
+pub mod user_identity;
+pub mod user_login;
+pub mod user_two_factor;
 pub mod user_verification;
+pub mod user_warning;
 
 // Initialize database client connection
 #[tracing::instrument]
@@ -40,3 +50,29 @@ pub async fn migrate(pool: &Pool<Postgres>) {
         Err(e) => panic!("Database migration failed. {}", e),
     };
 }
+
+// Used by the readiness probe to make sure a connection can be acquired and queried
+pub async fn ping(pool: &Pool<Postgres>) -> Result<(), sqlx::Error> {
+    sqlx::query("SELECT 1").execute(pool).await.map(|_| ())
+}
+
+// Start a transaction for flows that touch several tables, it is rolled back when dropped without commit
+pub async fn begin_transaction(
+    pool: &Pool<Postgres>,
+) -> Result<Transaction<'static, Postgres>, (StatusCode, Json<ErrorResponse>)> {
+    pool.begin().await.map_err(|error| {
+        error!("failed to begin database transaction. {}", error);
+        internal_server_error()
+    })
+}
+
+pub async fn commit_transaction(
+    transaction: Transaction<'static, Postgres>,
+) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+    transaction.commit().await.map_err(|error| {
+        error!("failed to commit database transaction. {}", error);
+        internal_server_error()
+    })
+}
+
+// Database errors are logged where they happen and never leak to the client
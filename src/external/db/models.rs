@@ -1,25 +1,229 @@
+use crate::logger::Secret;
 use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
-use time::Date;
+use time::OffsetDateTime;
 use uuid::Uuid;
 
+#[derive(
+    Clone, Copy, Debug, Deserialize, PartialEq, Eq, PartialOrd, Ord, Serialize, sqlx::Type,
+)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "user_role", rename_all = "lowercase")]
+pub enum UserRole {
+    User,
+    Moderator,
+    Admin,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq, Serialize, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "user_restriction", rename_all = "lowercase")]
+pub enum UserRestriction {
+    Suspended,
+    Banned,
+}
+
 #[derive(Clone, Debug, Deserialize, FromRow, Serialize)]
 pub struct User {
     pub id: Uuid,
     pub email: String,
-    pub password: String,
+    // Never expose the password hash in any response body
+    // Users who only sign in through an identity provider have no password
+    #[serde(skip_serializing)]
+    pub password: Option<Secret<String>>,
     pub verified: bool,
-    pub name: String,
-    pub avatar: String,
-    pub created_at: Date,
-    pub updated_at: Date,
+    pub name: Option<String>,
+    pub avatar: Option<String>,
+    pub role: UserRole,
+    pub restriction: Option<UserRestriction>,
+    pub restriction_reason: Option<String>,
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub restriction_expires_at: Option<OffsetDateTime>,
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub tokens_revoked_at: Option<OffsetDateTime>,
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub deletion_scheduled_at: Option<OffsetDateTime>,
+    // Deleted users are kept as anonymous placeholders
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub deleted_at: Option<OffsetDateTime>,
+    #[serde(with = "time::serde::rfc3339")]
+    pub created_at: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339")]
+    pub updated_at: OffsetDateTime,
+}
+
+impl User {
+    // A restriction without expiry stays in place until an admin lifts it
+    pub fn active_restriction(&self) -> Option<UserRestriction> {
+        match (self.restriction, self.restriction_expires_at) {
+            (Some(restriction), Some(expires_at)) if expires_at > OffsetDateTime::now_utc() => {
+                Some(restriction)
+            }
+            (Some(restriction), None) => Some(restriction),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, FromRow, Serialize)]
+pub struct AdminAuditLog {
+    pub id: Uuid,
+    pub actor_id: Uuid,
+    pub action: String,
+    pub target_user_id: Option<Uuid>,
+    pub details: serde_json::Value,
+    #[serde(with = "time::serde::rfc3339")]
+    pub created_at: OffsetDateTime,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq, Serialize, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "report_category", rename_all = "snake_case")]
+pub enum ReportCategory {
+    Spam,
+    Harassment,
+    HateSpeech,
+    InappropriateContent,
+    Impersonation,
+    Other,
 }
 
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq, Serialize, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "report_status", rename_all = "lowercase")]
+pub enum ReportStatus {
+    Pending,
+    Dismissed,
+    Actioned,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq, Serialize, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "report_action", rename_all = "lowercase")]
+pub enum ReportAction {
+    Dismiss,
+    Warn,
+    Suspend,
+}
+
+#[derive(Clone, Debug, Deserialize, FromRow, Serialize)]
+pub struct Report {
+    pub id: Uuid,
+    pub reporter_id: Uuid,
+    pub target_user_id: Uuid,
+    pub category: ReportCategory,
+    pub description: Option<String>,
+    pub status: ReportStatus,
+    pub action: Option<ReportAction>,
+    pub moderator_id: Option<Uuid>,
+    pub moderator_note: Option<String>,
+    #[serde(with = "time::serde::rfc3339")]
+    pub created_at: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub resolved_at: Option<OffsetDateTime>,
+}
+
+// Two-factor authentication is only turned on once the user confirmed the enrollment with a valid code
+#[derive(Clone, Debug, FromRow)]
+pub struct UserTwoFactor {
+    pub user_id: Uuid,
+    // Base32 encoded TOTP secret
+    pub secret: Secret<String>,
+    pub enabled: bool,
+    pub last_used_step: Option<i64>,
+}
+
+// Account at an external identity provider that can be used to sign in as the user
 #[derive(Clone, Debug, Deserialize, FromRow, Serialize)]
-pub struct UserVerification {
+pub struct UserIdentity {
     pub id: Uuid,
     pub user_id: Uuid,
-    pub secret: String,
-    pub created_at: Date,
-    pub updated_at: Date,
+    pub provider: String,
+    pub subject: String,
+    pub email: Option<String>,
+    #[serde(with = "time::serde::rfc3339")]
+    pub created_at: OffsetDateTime,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq, Serialize, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "login_failure_reason", rename_all = "snake_case")]
+pub enum LoginFailureReason {
+    InvalidPassword,
+    // Rejected without checking the password while the account is locked
+    Locked,
+    InvalidTwoFactorCode,
+}
+
+// Login attempt of a user, failures are only recorded once the email matched a user
+#[derive(Clone, Debug, Deserialize, FromRow, Serialize)]
+pub struct UserLoginHistory {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub success: bool,
+    pub failure_reason: Option<LoginFailureReason>,
+    pub ip: String,
+    pub user_agent: Option<String>,
+    pub is_new_device: bool,
+    #[serde(with = "time::serde::rfc3339")]
+    pub created_at: OffsetDateTime,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq, Serialize, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "user_data_export_status", rename_all = "lowercase")]
+pub enum UserDataExportStatus {
+    Pending,
+    Completed,
+    Failed,
+}
+
+// Archive requested by a user, the archive itself is only handed out by the download
+#[derive(Clone, Debug, Deserialize, FromRow, Serialize)]
+pub struct UserDataExport {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub status: UserDataExportStatus,
+    #[serde(with = "time::serde::rfc3339")]
+    pub created_at: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub completed_at: Option<OffsetDateTime>,
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub expires_at: Option<OffsetDateTime>,
+}
+
+// Report filed by the user, notes of the moderators are not part of the user's data
+#[derive(Clone, Debug, Deserialize, FromRow, Serialize)]
+pub struct ExportedReport {
+    pub id: Uuid,
+    pub target_user_id: Uuid,
+    pub category: ReportCategory,
+    pub description: Option<String>,
+    pub status: ReportStatus,
+    #[serde(with = "time::serde::rfc3339")]
+    pub created_at: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub resolved_at: Option<OffsetDateTime>,
+}
+
+// Warning the user received from a moderator
+#[derive(Clone, Debug, Deserialize, FromRow, Serialize)]
+pub struct ExportedWarning {
+    pub id: Uuid,
+    pub reason: String,
+    #[serde(with = "time::serde::rfc3339")]
+    pub created_at: OffsetDateTime,
+}
+
+// Everything stored about a user, as handed out by a data export
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct UserDataArchive {
+    #[serde(with = "time::serde::rfc3339")]
+    pub exported_at: OffsetDateTime,
+    pub profile: User,
+    pub identities: Vec<UserIdentity>,
+    pub two_factor_enabled: bool,
+    pub login_history: Vec<UserLoginHistory>,
+    pub reports: Vec<ExportedReport>,
+    pub warnings: Vec<ExportedWarning>,
 }
@@ -0,0 +1,168 @@
+use super::admin_audit_log::NewAdminAuditLog;
+use super::internal_server_error;
+use super::models::{ExportedReport, Report, ReportAction, ReportCategory, ReportStatus};
+use super::user::UserRestrictionUpdate;
+use super::user_warning::NewUserWarning;
+use crate::server::handlers::ErrorResponse;
+use axum::{http::StatusCode, Json};
+use sqlx::PgExecutor;
+use tracing::{debug, error};
+use uuid::Uuid;
+
+#[derive(Debug)]
+pub struct NewReport {
+    pub reporter_id: Uuid,
+    pub target_user_id: Uuid,
+    pub category: ReportCategory,
+    pub description: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct ReportResolution {
+    pub status: ReportStatus,
+    pub action: ReportAction,
+    pub moderator_id: Uuid,
+    pub moderator_note: Option<String>,
+}
+
+// Action taken against the reported user when the report is resolved
+#[derive(Debug)]
+pub enum ReportFollowUp {
+    None,
+    Warn(NewUserWarning),
+    // Restricting a user is audited like the same action taken through the admin API
+    Suspend {
+        user_id: Uuid,
+        update: UserRestrictionUpdate,
+        audit_log: NewAdminAuditLog,
+    },
+}
+
+#[tracing::instrument(skip(db_client))]
+pub async fn insert_new_report(
+    db_client: impl PgExecutor<'_>,
+    new_report: NewReport,
+) -> Result<Uuid, (StatusCode, Json<ErrorResponse>)> {
+    sqlx::query_scalar!(
+        "INSERT INTO report (reporter_id, target_user_id, category, description) VALUES ($1, $2, $3, $4) RETURNING id",
+        new_report.reporter_id,
+        new_report.target_user_id,
+        new_report.category as ReportCategory,
+        new_report.description
+    )
+    .fetch_one(db_client)
+    .await
+    .map_err(|error| match error {
+        // The reporter already has a pending report against the same user
+        sqlx::Error::Database(database_error) if database_error.is_unique_violation() => {
+            debug!("pending report already exists. {}", database_error);
+            (
+                StatusCode::CONFLICT,
+                Json(ErrorResponse {
+                    success: false,
+                    error: "User has already been reported.".to_string(),
+                }),
+            )
+        }
+        error => {
+            error!("failed to insert new report record into database. {}", error);
+            internal_server_error()
+        }
+    })
+}
+
+#[tracing::instrument(skip(db_client))]
+pub async fn get_report_by_id(
+    db_client: impl PgExecutor<'_>,
+    report_id: &Uuid,
+) -> Result<Option<Report>, (StatusCode, Json<ErrorResponse>)> {
+    sqlx::query_as!(
+        Report,
+        r#"SELECT id, reporter_id, target_user_id, category AS "category: ReportCategory",
+           description, status AS "status: ReportStatus", action AS "action: ReportAction",
+           moderator_id, moderator_note, created_at, resolved_at
+           FROM report WHERE id = $1"#,
+        report_id
+    )
+    .fetch_optional(db_client)
+    .await
+    .map_err(|error| {
+        error!("failed to get report by id from database. {}", error);
+        internal_server_error()
+    })
+}
+
+#[tracing::instrument(skip(db_client))]
+pub async fn list_reports(
+    db_client: impl PgExecutor<'_>,
+    status: ReportStatus,
+    limit: i64,
+    offset: i64,
+) -> Result<Vec<Report>, (StatusCode, Json<ErrorResponse>)> {
+    sqlx::query_as!(
+        Report,
+        r#"SELECT id, reporter_id, target_user_id, category AS "category: ReportCategory",
+           description, status AS "status: ReportStatus", action AS "action: ReportAction",
+           moderator_id, moderator_note, created_at, resolved_at
+           FROM report WHERE status = $1
+           ORDER BY created_at ASC
+           LIMIT $2 OFFSET $3"#,
+        status as ReportStatus,
+        limit,
+        offset
+    )
+    .fetch_all(db_client)
+    .await
+    .map_err(|error| {
+        error!("failed to list reports from database. {}", error);
+        internal_server_error()
+    })
+}
+
+// Only pending reports can be resolved, returns false if someone else resolved it first
+#[tracing::instrument(skip(db_client))]
+pub async fn resolve_report(
+    db_client: impl PgExecutor<'_>,
+    report_id: &Uuid,
+    resolution: ReportResolution,
+) -> Result<bool, (StatusCode, Json<ErrorResponse>)> {
+    let result = sqlx::query!(
+        "UPDATE report SET status = $1, action = $2, moderator_id = $3, moderator_note = $4, resolved_at = now() WHERE id = $5 AND status = 'pending'",
+        resolution.status as ReportStatus,
+        resolution.action as ReportAction,
+        resolution.moderator_id,
+        resolution.moderator_note,
+        report_id
+    )
+    .execute(db_client)
+    .await
+    .map_err(|error| {
+        error!("failed to resolve report in database. {}", error);
+        internal_server_error()
+    })?;
+    Ok(result.rows_affected() == 1)
+}
+
+#[tracing::instrument(skip(db_client))]
+pub async fn list_reports_by_reporter(
+    db_client: impl PgExecutor<'_>,
+    reporter_id: &Uuid,
+) -> Result<Vec<ExportedReport>, (StatusCode, Json<ErrorResponse>)> {
+    sqlx::query_as!(
+        ExportedReport,
+        r#"SELECT id, target_user_id, category AS "category: ReportCategory", description,
+           status AS "status: ReportStatus", created_at, resolved_at
+           FROM report WHERE reporter_id = $1
+           ORDER BY created_at DESC"#,
+        reporter_id
+    )
+    .fetch_all(db_client)
+    .await
+    .map_err(|error| {
+        error!(
+            "failed to list reports by reporter from database. {}",
+            error
+        );
+        internal_server_error()
+    })
+}
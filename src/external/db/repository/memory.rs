@@ -0,0 +1,1082 @@
+use super::{
+    AccountRepository, AdminRepository, IdentityRepository, LoginSecurityRepository,
+    ReportRepository, TwoFactorRepository, UserRepository, VerificationRepository,
+    EXPORTED_LOGIN_HISTORY_LIMIT,
+};
+use crate::external::db::admin_audit_log::NewAdminAuditLog;
+use crate::external::db::internal_server_error;
+use crate::external::db::models::{
+    AdminAuditLog, ExportedReport, ExportedWarning, Report, ReportStatus, User, UserDataArchive,
+    UserDataExport, UserDataExportStatus, UserIdentity, UserLoginHistory, UserRole, UserTwoFactor,
+};
+use crate::external::db::report::{NewReport, ReportFollowUp, ReportResolution};
+use crate::external::db::user::{NewUser, UserFilter, UserRestrictionUpdate};
+use crate::external::db::user_identity::NewUserIdentity;
+use crate::external::db::user_login::NewUserLoginHistory;
+use crate::external::db::user_warning::NewUserWarning;
+use crate::logger::Secret;
+use crate::server::handlers::ErrorResponse;
+use axum::async_trait;
+use axum::{http::StatusCode, Json};
+use std::collections::HashMap;
+use std::sync::{Mutex, MutexGuard};
+use time::OffsetDateTime;
+use tracing::error;
+use uuid::Uuid;
+
+#[derive(Debug, Default)]
+struct MemoryData {
+    users: HashMap<Uuid, User>,
+    // Keyed by token hash
+    verifications: HashMap<String, MemoryVerification>,
+    two_factors: HashMap<Uuid, UserTwoFactor>,
+    recovery_codes: HashMap<Uuid, Vec<MemoryRecoveryCode>>,
+    identities: Vec<UserIdentity>,
+    lockouts: HashMap<Uuid, MemoryLockout>,
+    // In insertion order, oldest first
+    login_history: Vec<UserLoginHistory>,
+    exports: HashMap<Uuid, MemoryDataExport>,
+    // In insertion order, oldest first
+    audit_logs: Vec<AdminAuditLog>,
+    reports: Vec<Report>,
+    warnings: Vec<MemoryWarning>,
+}
+
+#[derive(Debug)]
+struct MemoryVerification {
+    user_id: Uuid,
+    expires_at: OffsetDateTime,
+}
+
+#[derive(Debug)]
+struct MemoryLockout {
+    failed_count: i32,
+    last_failed_at: OffsetDateTime,
+    locked_until: Option<OffsetDateTime>,
+}
+
+#[derive(Debug)]
+struct MemoryDataExport {
+    export: UserDataExport,
+    archive: Option<serde_json::Value>,
+}
+
+#[derive(Debug)]
+struct MemoryWarning {
+    id: Uuid,
+    warning: NewUserWarning,
+    created_at: OffsetDateTime,
+}
+
+#[derive(Debug)]
+struct MemoryRecoveryCode {
+    code_hash: String,
+    is_used: bool,
+}
+
+// Repository kept in process memory, all data is lost once it is dropped
+#[derive(Debug, Default)]
+pub struct MemoryRepository {
+    data: Mutex<MemoryData>,
+}
+
+impl MemoryRepository {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn lock(&self) -> Result<MutexGuard<'_, MemoryData>, (StatusCode, Json<ErrorResponse>)> {
+        self.data.lock().map_err(|_| {
+            error!("memory repository lock poisoned");
+            internal_server_error()
+        })
+    }
+}
+
+// Same constraints as the unique (provider, subject) and (user_id, provider) pairs in Postgres
+fn insert_identity(data: &mut MemoryData, identity: NewUserIdentity) -> bool {
+    let is_linked = data.identities.iter().any(|linked| {
+        linked.provider == identity.provider
+            && (linked.subject == identity.subject || linked.user_id == identity.user_id)
+    });
+    if is_linked {
+        return false;
+    }
+    data.identities.push(UserIdentity {
+        id: Uuid::new_v4(),
+        user_id: identity.user_id,
+        provider: identity.provider,
+        subject: identity.subject,
+        email: identity.email,
+        created_at: OffsetDateTime::now_utc(),
+    });
+    true
+}
+
+fn insert_audit_log(data: &mut MemoryData, audit_log: NewAdminAuditLog) {
+    data.audit_logs.push(AdminAuditLog {
+        id: Uuid::new_v4(),
+        actor_id: audit_log.actor_id,
+        action: audit_log.action,
+        target_user_id: audit_log.target_user_id,
+        details: audit_log.details,
+        created_at: OffsetDateTime::now_utc(),
+    });
+}
+
+// Tokens are revoked as well, as every restriction in Postgres does on the same transaction
+fn restrict(data: &mut MemoryData, user_id: &Uuid, update: UserRestrictionUpdate) {
+    if let Some(user) = data.users.get_mut(user_id) {
+        let now = OffsetDateTime::now_utc();
+        user.restriction = Some(update.restriction);
+        user.restriction_reason = Some(update.reason);
+        user.restriction_expires_at = update.expires_at;
+        user.tokens_revoked_at = Some(now);
+        user.updated_at = now;
+    }
+}
+
+// Same comparison as the unique index on lower(email) in Postgres
+fn find_by_email<'a>(data: &'a MemoryData, email: &str) -> Option<&'a User> {
+    let email = email.to_lowercase();
+    data.users
+        .values()
+        .find(|user| user.email.to_lowercase() == email)
+}
+
+#[async_trait]
+impl UserRepository for MemoryRepository {
+    async fn is_user_exists(&self, email: &str) -> Result<bool, (StatusCode, Json<ErrorResponse>)> {
+        let data = self.lock()?;
+        Ok(find_by_email(&data, email).is_some())
+    }
+
+    async fn register_user(
+        &self,
+        new_user: NewUser,
+        verification_token_hash: String,
+        verification_expires_at: OffsetDateTime,
+    ) -> Result<Uuid, (StatusCode, Json<ErrorResponse>)> {
+        let mut data = self.lock()?;
+        if find_by_email(&data, &new_user.email).is_some() {
+            return Err((
+                StatusCode::CONFLICT,
+                Json(ErrorResponse {
+                    success: false,
+                    error: "User already exists.".to_string(),
+                }),
+            ));
+        }
+
+        let now = OffsetDateTime::now_utc();
+        let user = User {
+            id: Uuid::new_v4(),
+            email: new_user.email,
+            password: Some(new_user.password),
+            verified: false,
+            name: None,
+            avatar: None,
+            role: UserRole::User,
+            restriction: None,
+            restriction_reason: None,
+            restriction_expires_at: None,
+            tokens_revoked_at: None,
+            deletion_scheduled_at: None,
+            deleted_at: None,
+            created_at: now,
+            updated_at: now,
+        };
+        let user_id = user.id;
+        data.users.insert(user_id, user);
+        data.verifications.insert(
+            verification_token_hash,
+            MemoryVerification {
+                user_id,
+                expires_at: verification_expires_at,
+            },
+        );
+        Ok(user_id)
+    }
+
+    async fn get_user_by_email(
+        &self,
+        email: &str,
+    ) -> Result<Option<User>, (StatusCode, Json<ErrorResponse>)> {
+        let data = self.lock()?;
+        Ok(find_by_email(&data, email).cloned())
+    }
+
+    async fn get_user_by_id(
+        &self,
+        user_id: &Uuid,
+    ) -> Result<Option<User>, (StatusCode, Json<ErrorResponse>)> {
+        let data = self.lock()?;
+        Ok(data.users.get(user_id).cloned())
+    }
+
+    async fn update_verified_status(
+        &self,
+        user_id: &Uuid,
+        status: bool,
+    ) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+        let mut data = self.lock()?;
+        // Updating a missing row is not an error in Postgres either
+        if let Some(user) = data.users.get_mut(user_id) {
+            user.verified = status;
+            user.updated_at = OffsetDateTime::now_utc();
+        }
+        Ok(())
+    }
+
+    async fn update_password_hash(
+        &self,
+        user_id: &Uuid,
+        previous_password: &Secret<String>,
+        password: &Secret<String>,
+    ) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+        let mut data = self.lock()?;
+        if let Some(user) = data.users.get_mut(user_id) {
+            if user.password.as_ref() == Some(previous_password) {
+                user.password = Some(password.clone());
+                user.updated_at = OffsetDateTime::now_utc();
+            }
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl VerificationRepository for MemoryRepository {
+    async fn activate_user(
+        &self,
+        token_hash: &str,
+    ) -> Result<bool, (StatusCode, Json<ErrorResponse>)> {
+        let mut data = self.lock()?;
+        let now = OffsetDateTime::now_utc();
+        let user_id = match data.verifications.remove(token_hash) {
+            Some(verification) if verification.expires_at > now => verification.user_id,
+            _ => return Ok(false),
+        };
+        if let Some(user) = data.users.get_mut(&user_id) {
+            user.verified = true;
+            user.updated_at = now;
+        }
+        Ok(true)
+    }
+}
+
+#[async_trait]
+impl TwoFactorRepository for MemoryRepository {
+    async fn get_two_factor(
+        &self,
+        user_id: &Uuid,
+    ) -> Result<Option<UserTwoFactor>, (StatusCode, Json<ErrorResponse>)> {
+        let data = self.lock()?;
+        Ok(data.two_factors.get(user_id).cloned())
+    }
+
+    async fn start_enrollment(
+        &self,
+        user_id: &Uuid,
+        secret: Secret<String>,
+    ) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+        let mut data = self.lock()?;
+        match data.two_factors.get_mut(user_id) {
+            Some(two_factor) if two_factor.enabled => {}
+            Some(two_factor) => two_factor.secret = secret,
+            None => {
+                data.two_factors.insert(
+                    *user_id,
+                    UserTwoFactor {
+                        user_id: *user_id,
+                        secret,
+                        enabled: false,
+                        last_used_step: None,
+                    },
+                );
+            }
+        }
+        Ok(())
+    }
+
+    async fn enable_two_factor(
+        &self,
+        user_id: &Uuid,
+        step: i64,
+        recovery_code_hashes: Vec<String>,
+    ) -> Result<bool, (StatusCode, Json<ErrorResponse>)> {
+        let mut data = self.lock()?;
+        match data.two_factors.get_mut(user_id) {
+            Some(two_factor) if !two_factor.enabled => {
+                two_factor.enabled = true;
+                two_factor.last_used_step = Some(step);
+            }
+            _ => return Ok(false),
+        }
+        let recovery_codes = recovery_code_hashes
+            .into_iter()
+            .map(|code_hash| MemoryRecoveryCode {
+                code_hash,
+                is_used: false,
+            })
+            .collect();
+        data.recovery_codes.insert(*user_id, recovery_codes);
+        Ok(true)
+    }
+
+    async fn disable_two_factor(
+        &self,
+        user_id: &Uuid,
+    ) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+        let mut data = self.lock()?;
+        data.two_factors.remove(user_id);
+        data.recovery_codes.remove(user_id);
+        Ok(())
+    }
+
+    async fn use_code_step(
+        &self,
+        user_id: &Uuid,
+        step: i64,
+    ) -> Result<bool, (StatusCode, Json<ErrorResponse>)> {
+        let mut data = self.lock()?;
+        match data.two_factors.get_mut(user_id) {
+            Some(two_factor) if two_factor.last_used_step.is_none_or(|last| last < step) => {
+                two_factor.last_used_step = Some(step);
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    async fn use_recovery_code(
+        &self,
+        user_id: &Uuid,
+        code_hash: &str,
+    ) -> Result<bool, (StatusCode, Json<ErrorResponse>)> {
+        let mut data = self.lock()?;
+        let recovery_code = data.recovery_codes.get_mut(user_id).and_then(|codes| {
+            codes
+                .iter_mut()
+                .find(|code| !code.is_used && code.code_hash == code_hash)
+        });
+        match recovery_code {
+            Some(recovery_code) => {
+                recovery_code.is_used = true;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    async fn count_unused_recovery_codes(
+        &self,
+        user_id: &Uuid,
+    ) -> Result<i64, (StatusCode, Json<ErrorResponse>)> {
+        let data = self.lock()?;
+        Ok(data.recovery_codes.get(user_id).map_or(0, |codes| {
+            codes.iter().filter(|code| !code.is_used).count() as i64
+        }))
+    }
+}
+
+#[async_trait]
+impl IdentityRepository for MemoryRepository {
+    async fn get_identity(
+        &self,
+        provider: &str,
+        subject: &str,
+    ) -> Result<Option<UserIdentity>, (StatusCode, Json<ErrorResponse>)> {
+        let data = self.lock()?;
+        Ok(data
+            .identities
+            .iter()
+            .find(|identity| identity.provider == provider && identity.subject == subject)
+            .cloned())
+    }
+
+    async fn list_identities(
+        &self,
+        user_id: &Uuid,
+    ) -> Result<Vec<UserIdentity>, (StatusCode, Json<ErrorResponse>)> {
+        let data = self.lock()?;
+        Ok(data
+            .identities
+            .iter()
+            .filter(|identity| identity.user_id == *user_id)
+            .cloned()
+            .collect())
+    }
+
+    async fn register_identity_user(
+        &self,
+        email: String,
+        name: Option<String>,
+        provider: String,
+        subject: String,
+    ) -> Result<Uuid, (StatusCode, Json<ErrorResponse>)> {
+        let mut data = self.lock()?;
+        if find_by_email(&data, &email).is_some() {
+            return Err((
+                StatusCode::CONFLICT,
+                Json(ErrorResponse {
+                    success: false,
+                    error: "User already exists.".to_string(),
+                }),
+            ));
+        }
+
+        let now = OffsetDateTime::now_utc();
+        let user_id = Uuid::new_v4();
+        let identity = NewUserIdentity {
+            user_id,
+            provider,
+            subject,
+            email: Some(email.clone()),
+        };
+        if !insert_identity(&mut data, identity) {
+            return Err((
+                StatusCode::CONFLICT,
+                Json(ErrorResponse {
+                    success: false,
+                    error: "Account is already linked to another user.".to_string(),
+                }),
+            ));
+        }
+        data.users.insert(
+            user_id,
+            User {
+                id: user_id,
+                email,
+                password: None,
+                verified: true,
+                name,
+                avatar: None,
+                role: UserRole::User,
+                restriction: None,
+                restriction_reason: None,
+                restriction_expires_at: None,
+                tokens_revoked_at: None,
+                deletion_scheduled_at: None,
+                deleted_at: None,
+                created_at: now,
+                updated_at: now,
+            },
+        );
+        Ok(user_id)
+    }
+
+    async fn link_identity(
+        &self,
+        identity: NewUserIdentity,
+        claim_unverified_user: bool,
+    ) -> Result<bool, (StatusCode, Json<ErrorResponse>)> {
+        let mut data = self.lock()?;
+        let user_id = identity.user_id;
+        if !insert_identity(&mut data, identity) {
+            return Ok(false);
+        }
+        if claim_unverified_user {
+            if let Some(user) = data.users.get_mut(&user_id) {
+                user.verified = true;
+                user.password = None;
+                user.updated_at = OffsetDateTime::now_utc();
+            }
+        }
+        Ok(true)
+    }
+
+    async fn unlink_identity(
+        &self,
+        user_id: &Uuid,
+        provider: &str,
+    ) -> Result<bool, (StatusCode, Json<ErrorResponse>)> {
+        let mut data = self.lock()?;
+        let count = data.identities.len();
+        data.identities
+            .retain(|identity| identity.user_id != *user_id || identity.provider != provider);
+        Ok(data.identities.len() < count)
+    }
+}
+
+#[async_trait]
+impl LoginSecurityRepository for MemoryRepository {
+    async fn get_locked_until(
+        &self,
+        user_id: &Uuid,
+    ) -> Result<Option<OffsetDateTime>, (StatusCode, Json<ErrorResponse>)> {
+        Ok(self
+            .lock()?
+            .lockouts
+            .get(user_id)
+            .and_then(|lockout| lockout.locked_until))
+    }
+
+    async fn record_failed_login(
+        &self,
+        user_id: &Uuid,
+        reset_before: OffsetDateTime,
+    ) -> Result<i32, (StatusCode, Json<ErrorResponse>)> {
+        let now = OffsetDateTime::now_utc();
+        let mut data = self.lock()?;
+        let lockout = data.lockouts.entry(*user_id).or_insert(MemoryLockout {
+            failed_count: 0,
+            last_failed_at: now,
+            locked_until: None,
+        });
+        if lockout.last_failed_at < reset_before {
+            lockout.failed_count = 0;
+        }
+        lockout.failed_count += 1;
+        lockout.last_failed_at = now;
+        Ok(lockout.failed_count)
+    }
+
+    async fn lock_login(
+        &self,
+        user_id: &Uuid,
+        locked_until: OffsetDateTime,
+    ) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+        if let Some(lockout) = self.lock()?.lockouts.get_mut(user_id) {
+            lockout.locked_until = lockout.locked_until.max(Some(locked_until));
+        }
+        Ok(())
+    }
+
+    async fn clear_failed_logins(
+        &self,
+        user_id: &Uuid,
+    ) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+        self.lock()?.lockouts.remove(user_id);
+        Ok(())
+    }
+
+    async fn is_new_device(
+        &self,
+        user_id: &Uuid,
+        ip: &str,
+        user_agent: Option<&str>,
+    ) -> Result<bool, (StatusCode, Json<ErrorResponse>)> {
+        let data = self.lock()?;
+        let mut successes = data
+            .login_history
+            .iter()
+            .filter(|login| login.user_id == *user_id && login.success)
+            .peekable();
+        if successes.peek().is_none() {
+            return Ok(false);
+        }
+        let (mut is_known_ip, mut is_known_user_agent) = (false, false);
+        for login in successes {
+            is_known_ip |= login.ip == ip;
+            is_known_user_agent |= login.user_agent.as_deref() == user_agent;
+        }
+        Ok(!is_known_ip || !is_known_user_agent)
+    }
+
+    async fn insert_login_history(
+        &self,
+        login: NewUserLoginHistory,
+    ) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+        self.lock()?.login_history.push(UserLoginHistory {
+            id: Uuid::new_v4(),
+            user_id: login.user_id,
+            success: login.success,
+            failure_reason: login.failure_reason,
+            ip: login.ip,
+            user_agent: login.user_agent,
+            is_new_device: login.is_new_device,
+            created_at: OffsetDateTime::now_utc(),
+        });
+        Ok(())
+    }
+
+    async fn list_login_history(
+        &self,
+        user_id: &Uuid,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<UserLoginHistory>, (StatusCode, Json<ErrorResponse>)> {
+        Ok(self
+            .lock()?
+            .login_history
+            .iter()
+            .rev()
+            .filter(|login| login.user_id == *user_id)
+            .skip(offset.max(0) as usize)
+            .take(limit.max(0) as usize)
+            .cloned()
+            .collect())
+    }
+}
+
+#[async_trait]
+impl AccountRepository for MemoryRepository {
+    async fn schedule_deletion(
+        &self,
+        user_id: &Uuid,
+        scheduled_at: OffsetDateTime,
+    ) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+        let mut data = self.lock()?;
+        if let Some(user) = data.users.get_mut(user_id) {
+            if user.deleted_at.is_none() {
+                let now = OffsetDateTime::now_utc();
+                user.deletion_scheduled_at = Some(scheduled_at);
+                user.tokens_revoked_at = Some(now);
+                user.updated_at = now;
+            }
+        }
+        Ok(())
+    }
+
+    async fn cancel_deletion(
+        &self,
+        user_id: &Uuid,
+    ) -> Result<bool, (StatusCode, Json<ErrorResponse>)> {
+        let mut data = self.lock()?;
+        match data.users.get_mut(user_id) {
+            Some(user) if user.deletion_scheduled_at.is_some() && user.deleted_at.is_none() => {
+                user.deletion_scheduled_at = None;
+                user.updated_at = OffsetDateTime::now_utc();
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    async fn purge_deleted_users(&self) -> Result<Vec<Uuid>, (StatusCode, Json<ErrorResponse>)> {
+        let now = OffsetDateTime::now_utc();
+        let mut data = self.lock()?;
+        let mut purged_user_ids = Vec::new();
+        for user in data.users.values_mut() {
+            let is_due = user
+                .deletion_scheduled_at
+                .is_some_and(|scheduled_at| scheduled_at <= now);
+            if !is_due || user.deleted_at.is_some() {
+                continue;
+            }
+            // Same placeholder as in Postgres
+            user.email = format!("deleted-{}@deleted.invalid", user.id);
+            user.password = None;
+            user.verified = false;
+            user.name = Some("Deleted user".to_string());
+            user.avatar = None;
+            user.tokens_revoked_at = Some(now);
+            user.deletion_scheduled_at = None;
+            user.deleted_at = Some(now);
+            user.updated_at = now;
+            purged_user_ids.push(user.id);
+        }
+        for user_id in &purged_user_ids {
+            data.verifications
+                .retain(|_, verification| verification.user_id != *user_id);
+            data.two_factors.remove(user_id);
+            data.recovery_codes.remove(user_id);
+            data.identities
+                .retain(|identity| identity.user_id != *user_id);
+            data.lockouts.remove(user_id);
+            data.login_history.retain(|login| login.user_id != *user_id);
+            data.exports
+                .retain(|_, export| export.export.user_id != *user_id);
+        }
+        Ok(purged_user_ids)
+    }
+
+    async fn export_user_data(
+        &self,
+        user_id: &Uuid,
+    ) -> Result<Option<UserDataArchive>, (StatusCode, Json<ErrorResponse>)> {
+        let data = self.lock()?;
+        let Some(profile) = data.users.get(user_id).cloned() else {
+            return Ok(None);
+        };
+        Ok(Some(UserDataArchive {
+            exported_at: OffsetDateTime::now_utc(),
+            profile,
+            identities: data
+                .identities
+                .iter()
+                .filter(|identity| identity.user_id == *user_id)
+                .cloned()
+                .collect(),
+            two_factor_enabled: data
+                .two_factors
+                .get(user_id)
+                .is_some_and(|two_factor| two_factor.enabled),
+            login_history: data
+                .login_history
+                .iter()
+                .rev()
+                .filter(|login| login.user_id == *user_id)
+                .take(EXPORTED_LOGIN_HISTORY_LIMIT as usize)
+                .cloned()
+                .collect(),
+            reports: data
+                .reports
+                .iter()
+                .rev()
+                .filter(|report| report.reporter_id == *user_id)
+                .map(|report| ExportedReport {
+                    id: report.id,
+                    target_user_id: report.target_user_id,
+                    category: report.category,
+                    description: report.description.clone(),
+                    status: report.status,
+                    created_at: report.created_at,
+                    resolved_at: report.resolved_at,
+                })
+                .collect(),
+            warnings: data
+                .warnings
+                .iter()
+                .rev()
+                .filter(|warning| warning.warning.user_id == *user_id)
+                .map(|warning| ExportedWarning {
+                    id: warning.id,
+                    reason: warning.warning.reason.clone(),
+                    created_at: warning.created_at,
+                })
+                .collect(),
+        }))
+    }
+
+    async fn create_data_export(
+        &self,
+        user_id: &Uuid,
+    ) -> Result<UserDataExport, (StatusCode, Json<ErrorResponse>)> {
+        let export = UserDataExport {
+            id: Uuid::new_v4(),
+            user_id: *user_id,
+            status: UserDataExportStatus::Pending,
+            created_at: OffsetDateTime::now_utc(),
+            completed_at: None,
+            expires_at: None,
+        };
+        self.lock()?.exports.insert(
+            export.id,
+            MemoryDataExport {
+                export: export.clone(),
+                archive: None,
+            },
+        );
+        Ok(export)
+    }
+
+    async fn complete_data_export(
+        &self,
+        export_id: &Uuid,
+        archive: serde_json::Value,
+        expires_at: OffsetDateTime,
+    ) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+        if let Some(export) = self.lock()?.exports.get_mut(export_id) {
+            export.export.status = UserDataExportStatus::Completed;
+            export.export.completed_at = Some(OffsetDateTime::now_utc());
+            export.export.expires_at = Some(expires_at);
+            export.archive = Some(archive);
+        }
+        Ok(())
+    }
+
+    async fn fail_data_export(
+        &self,
+        export_id: &Uuid,
+    ) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+        if let Some(export) = self.lock()?.exports.get_mut(export_id) {
+            let now = OffsetDateTime::now_utc();
+            export.export.status = UserDataExportStatus::Failed;
+            export.export.completed_at = Some(now);
+            export.export.expires_at = Some(now);
+        }
+        Ok(())
+    }
+
+    async fn get_data_export(
+        &self,
+        user_id: &Uuid,
+        export_id: &Uuid,
+    ) -> Result<Option<UserDataExport>, (StatusCode, Json<ErrorResponse>)> {
+        Ok(self
+            .lock()?
+            .exports
+            .get(export_id)
+            .filter(|export| export.export.user_id == *user_id)
+            .map(|export| export.export.clone()))
+    }
+
+    async fn get_pending_data_export(
+        &self,
+        user_id: &Uuid,
+        created_after: OffsetDateTime,
+    ) -> Result<Option<UserDataExport>, (StatusCode, Json<ErrorResponse>)> {
+        Ok(self
+            .lock()?
+            .exports
+            .values()
+            .map(|export| &export.export)
+            .filter(|export| {
+                export.user_id == *user_id
+                    && export.status == UserDataExportStatus::Pending
+                    && export.created_at > created_after
+            })
+            .max_by_key(|export| export.created_at)
+            .cloned())
+    }
+
+    async fn get_data_export_archive(
+        &self,
+        user_id: &Uuid,
+        export_id: &Uuid,
+    ) -> Result<Option<serde_json::Value>, (StatusCode, Json<ErrorResponse>)> {
+        let now = OffsetDateTime::now_utc();
+        Ok(self
+            .lock()?
+            .exports
+            .get(export_id)
+            .filter(|export| {
+                export.export.user_id == *user_id
+                    && export.export.status == UserDataExportStatus::Completed
+                    && export
+                        .export
+                        .expires_at
+                        .is_some_and(|expires_at| expires_at > now)
+            })
+            .and_then(|export| export.archive.clone()))
+    }
+
+    async fn delete_expired_data_exports(&self) -> Result<u64, (StatusCode, Json<ErrorResponse>)> {
+        let now = OffsetDateTime::now_utc();
+        let mut data = self.lock()?;
+        let count = data.exports.len();
+        data.exports.retain(|_, export| {
+            export
+                .export
+                .expires_at
+                .is_none_or(|expires_at| expires_at > now)
+        });
+        Ok((count - data.exports.len()) as u64)
+    }
+}
+
+#[async_trait]
+impl AdminRepository for MemoryRepository {
+    // Same filters as the Postgres query, the email filter matches case-insensitively anywhere
+    async fn list_users(
+        &self,
+        filter: UserFilter,
+    ) -> Result<Vec<User>, (StatusCode, Json<ErrorResponse>)> {
+        let data = self.lock()?;
+        let email = filter.email.map(|email| email.to_lowercase());
+        let mut users: Vec<User> = data
+            .users
+            .values()
+            .filter(|user| {
+                email
+                    .as_ref()
+                    .is_none_or(|email| user.email.to_lowercase().contains(email))
+                    && filter.role.is_none_or(|role| user.role == role)
+                    && filter
+                        .verified
+                        .is_none_or(|verified| user.verified == verified)
+            })
+            .cloned()
+            .collect();
+        users.sort_by_key(|user| std::cmp::Reverse(user.created_at));
+        Ok(users
+            .into_iter()
+            .skip(filter.offset as usize)
+            .take(filter.limit as usize)
+            .collect())
+    }
+
+    async fn verify_user(
+        &self,
+        user_id: &Uuid,
+        audit_log: NewAdminAuditLog,
+    ) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+        let mut data = self.lock()?;
+        if let Some(user) = data.users.get_mut(user_id) {
+            user.verified = true;
+            user.updated_at = OffsetDateTime::now_utc();
+        }
+        insert_audit_log(&mut data, audit_log);
+        Ok(())
+    }
+
+    async fn restrict_user(
+        &self,
+        user_id: &Uuid,
+        update: UserRestrictionUpdate,
+        audit_log: NewAdminAuditLog,
+    ) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+        let mut data = self.lock()?;
+        restrict(&mut data, user_id, update);
+        insert_audit_log(&mut data, audit_log);
+        Ok(())
+    }
+
+    async fn revoke_tokens(
+        &self,
+        user_id: &Uuid,
+        audit_log: NewAdminAuditLog,
+    ) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+        let mut data = self.lock()?;
+        if let Some(user) = data.users.get_mut(user_id) {
+            let now = OffsetDateTime::now_utc();
+            user.tokens_revoked_at = Some(now);
+            user.updated_at = now;
+        }
+        insert_audit_log(&mut data, audit_log);
+        Ok(())
+    }
+
+    async fn update_role(
+        &self,
+        user_id: &Uuid,
+        role: UserRole,
+        audit_log: NewAdminAuditLog,
+    ) -> Result<bool, (StatusCode, Json<ErrorResponse>)> {
+        let mut data = self.lock()?;
+        let mut admins = data
+            .users
+            .values()
+            .filter(|user| user.role == UserRole::Admin && user.deleted_at.is_none());
+        let is_last_admin =
+            admins.next().is_some_and(|admin| admin.id == *user_id) && admins.next().is_none();
+        if role != UserRole::Admin && is_last_admin {
+            return Ok(false);
+        }
+        if let Some(user) = data.users.get_mut(user_id) {
+            user.role = role;
+            user.updated_at = OffsetDateTime::now_utc();
+        }
+        insert_audit_log(&mut data, audit_log);
+        Ok(true)
+    }
+
+    async fn list_audit_logs(
+        &self,
+        target_user_id: Option<Uuid>,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<AdminAuditLog>, (StatusCode, Json<ErrorResponse>)> {
+        Ok(self
+            .lock()?
+            .audit_logs
+            .iter()
+            .rev()
+            .filter(|audit_log| {
+                target_user_id.is_none_or(|user_id| audit_log.target_user_id == Some(user_id))
+            })
+            .skip(offset as usize)
+            .take(limit as usize)
+            .cloned()
+            .collect())
+    }
+}
+
+#[async_trait]
+impl ReportRepository for MemoryRepository {
+    async fn insert_report(
+        &self,
+        new_report: NewReport,
+    ) -> Result<Uuid, (StatusCode, Json<ErrorResponse>)> {
+        let mut data = self.lock()?;
+        if data.reports.iter().any(|report| {
+            report.reporter_id == new_report.reporter_id
+                && report.target_user_id == new_report.target_user_id
+                && report.status == ReportStatus::Pending
+        }) {
+            return Err((
+                StatusCode::CONFLICT,
+                Json(ErrorResponse {
+                    success: false,
+                    error: "User has already been reported.".to_string(),
+                }),
+            ));
+        }
+        let report = Report {
+            id: Uuid::new_v4(),
+            reporter_id: new_report.reporter_id,
+            target_user_id: new_report.target_user_id,
+            category: new_report.category,
+            description: new_report.description,
+            status: ReportStatus::Pending,
+            action: None,
+            moderator_id: None,
+            moderator_note: None,
+            created_at: OffsetDateTime::now_utc(),
+            resolved_at: None,
+        };
+        let report_id = report.id;
+        data.reports.push(report);
+        Ok(report_id)
+    }
+
+    async fn get_report(
+        &self,
+        report_id: &Uuid,
+    ) -> Result<Option<Report>, (StatusCode, Json<ErrorResponse>)> {
+        Ok(self
+            .lock()?
+            .reports
+            .iter()
+            .find(|report| report.id == *report_id)
+            .cloned())
+    }
+
+    async fn list_reports(
+        &self,
+        status: ReportStatus,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<Report>, (StatusCode, Json<ErrorResponse>)> {
+        Ok(self
+            .lock()?
+            .reports
+            .iter()
+            .filter(|report| report.status == status)
+            .skip(offset as usize)
+            .take(limit as usize)
+            .cloned()
+            .collect())
+    }
+
+    async fn resolve_report(
+        &self,
+        report_id: &Uuid,
+        resolution: ReportResolution,
+        follow_up: ReportFollowUp,
+    ) -> Result<bool, (StatusCode, Json<ErrorResponse>)> {
+        let mut data = self.lock()?;
+        let Some(report) = data
+            .reports
+            .iter_mut()
+            .find(|report| report.id == *report_id && report.status == ReportStatus::Pending)
+        else {
+            return Ok(false);
+        };
+        report.status = resolution.status;
+        report.action = Some(resolution.action);
+        report.moderator_id = Some(resolution.moderator_id);
+        report.moderator_note = resolution.moderator_note;
+        report.resolved_at = Some(OffsetDateTime::now_utc());
+
+        match follow_up {
+            ReportFollowUp::None => {}
+            ReportFollowUp::Warn(warning) => data.warnings.push(MemoryWarning {
+                id: Uuid::new_v4(),
+                warning,
+                created_at: OffsetDateTime::now_utc(),
+            }),
+            ReportFollowUp::Suspend {
+                user_id,
+                update,
+                audit_log,
+            } => {
+                restrict(&mut data, &user_id, update);
+                insert_audit_log(&mut data, audit_log);
+            }
+        }
+        Ok(true)
+    }
+}
@@ -0,0 +1,347 @@
+mod memory;
+mod postgres;
+
+use super::admin_audit_log::NewAdminAuditLog;
+use super::models::{
+    AdminAuditLog, Report, ReportStatus, User, UserDataArchive, UserDataExport, UserIdentity,
+    UserLoginHistory, UserRole, UserTwoFactor,
+};
+use super::report::{NewReport, ReportFollowUp, ReportResolution};
+use super::user::{NewUser, UserFilter, UserRestrictionUpdate};
+use super::user_identity::NewUserIdentity;
+use super::user_login::NewUserLoginHistory;
+use crate::logger::Secret;
+use crate::server::handlers::ErrorResponse;
+use axum::async_trait;
+use axum::{http::StatusCode, Json};
+use std::fmt::Debug;
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+// Not used by the server itself, handlers are tested against it without a running database
+pub use memory::MemoryRepository;
+pub use postgres::PgRepository;
+
+// Keeps the archive of long-lived accounts at a size that can be built and downloaded in one go
+pub const EXPORTED_LOGIN_HISTORY_LIMIT: i64 = 1000;
+
+#[async_trait]
+pub trait UserRepository: Debug + Send + Sync {
+    async fn is_user_exists(&self, email: &str) -> Result<bool, (StatusCode, Json<ErrorResponse>)>;
+
+    // The user and the hash of its verification token are inserted together or not at all
+    async fn register_user(
+        &self,
+        new_user: NewUser,
+        verification_token_hash: String,
+        verification_expires_at: OffsetDateTime,
+    ) -> Result<Uuid, (StatusCode, Json<ErrorResponse>)>;
+
+    async fn get_user_by_email(
+        &self,
+        email: &str,
+    ) -> Result<Option<User>, (StatusCode, Json<ErrorResponse>)>;
+
+    async fn get_user_by_id(
+        &self,
+        user_id: &Uuid,
+    ) -> Result<Option<User>, (StatusCode, Json<ErrorResponse>)>;
+
+    async fn update_verified_status(
+        &self,
+        user_id: &Uuid,
+        status: bool,
+    ) -> Result<(), (StatusCode, Json<ErrorResponse>)>;
+
+    // Replace the hash only while it is still the previous one
+    async fn update_password_hash(
+        &self,
+        user_id: &Uuid,
+        previous_password: &Secret<String>,
+        password: &Secret<String>,
+    ) -> Result<(), (StatusCode, Json<ErrorResponse>)>;
+}
+
+#[async_trait]
+pub trait VerificationRepository: Debug + Send + Sync {
+    // Consume the token and mark its user as verified, false if the token is unknown or expired
+    async fn activate_user(
+        &self,
+        token_hash: &str,
+    ) -> Result<bool, (StatusCode, Json<ErrorResponse>)>;
+}
+
+#[async_trait]
+pub trait TwoFactorRepository: Debug + Send + Sync {
+    async fn get_two_factor(
+        &self,
+        user_id: &Uuid,
+    ) -> Result<Option<UserTwoFactor>, (StatusCode, Json<ErrorResponse>)>;
+
+    // Store a new secret waiting for confirmation, an enabled secret is left untouched
+    async fn start_enrollment(
+        &self,
+        user_id: &Uuid,
+        secret: Secret<String>,
+    ) -> Result<(), (StatusCode, Json<ErrorResponse>)>;
+
+    // Enable two-factor authentication and replace the recovery codes at once
+    // Returns false if it was already enabled
+    async fn enable_two_factor(
+        &self,
+        user_id: &Uuid,
+        step: i64,
+        recovery_code_hashes: Vec<String>,
+    ) -> Result<bool, (StatusCode, Json<ErrorResponse>)>;
+
+    async fn disable_two_factor(
+        &self,
+        user_id: &Uuid,
+    ) -> Result<(), (StatusCode, Json<ErrorResponse>)>;
+
+    // Returns false if a code of the same or a later time step was already used
+    async fn use_code_step(
+        &self,
+        user_id: &Uuid,
+        step: i64,
+    ) -> Result<bool, (StatusCode, Json<ErrorResponse>)>;
+
+    // Returns false if there is no unused recovery code with the hash
+    async fn use_recovery_code(
+        &self,
+        user_id: &Uuid,
+        code_hash: &str,
+    ) -> Result<bool, (StatusCode, Json<ErrorResponse>)>;
+
+    async fn count_unused_recovery_codes(
+        &self,
+        user_id: &Uuid,
+    ) -> Result<i64, (StatusCode, Json<ErrorResponse>)>;
+}
+
+#[async_trait]
+pub trait IdentityRepository: Debug + Send + Sync {
+    async fn get_identity(
+        &self,
+        provider: &str,
+        subject: &str,
+    ) -> Result<Option<UserIdentity>, (StatusCode, Json<ErrorResponse>)>;
+
+    async fn list_identities(
+        &self,
+        user_id: &Uuid,
+    ) -> Result<Vec<UserIdentity>, (StatusCode, Json<ErrorResponse>)>;
+
+    // The verified user without password and its identity are inserted together or not at all
+    async fn register_identity_user(
+        &self,
+        email: String,
+        name: Option<String>,
+        provider: String,
+        subject: String,
+    ) -> Result<Uuid, (StatusCode, Json<ErrorResponse>)>;
+
+    // Link the identity to its user, an unverified user is verified and loses its password at the same time
+    // Returns false if either the account or the provider is already linked
+    async fn link_identity(
+        &self,
+        identity: NewUserIdentity,
+        claim_unverified_user: bool,
+    ) -> Result<bool, (StatusCode, Json<ErrorResponse>)>;
+
+    // Returns false if the user has not linked the provider
+    async fn unlink_identity(
+        &self,
+        user_id: &Uuid,
+        provider: &str,
+    ) -> Result<bool, (StatusCode, Json<ErrorResponse>)>;
+}
+
+#[async_trait]
+pub trait LoginSecurityRepository: Debug + Send + Sync {
+    async fn get_locked_until(
+        &self,
+        user_id: &Uuid,
+    ) -> Result<Option<OffsetDateTime>, (StatusCode, Json<ErrorResponse>)>;
+
+    // Failures before 'reset_before' are forgotten, returns the number of failures in a row
+    async fn record_failed_login(
+        &self,
+        user_id: &Uuid,
+        reset_before: OffsetDateTime,
+    ) -> Result<i32, (StatusCode, Json<ErrorResponse>)>;
+
+    async fn lock_login(
+        &self,
+        user_id: &Uuid,
+        locked_until: OffsetDateTime,
+    ) -> Result<(), (StatusCode, Json<ErrorResponse>)>;
+
+    async fn clear_failed_logins(
+        &self,
+        user_id: &Uuid,
+    ) -> Result<(), (StatusCode, Json<ErrorResponse>)>;
+
+    // A device is new if no successful login came from the same IP or with the same user agent before
+    // The very first login has nothing to compare with and is never treated as new
+    async fn is_new_device(
+        &self,
+        user_id: &Uuid,
+        ip: &str,
+        user_agent: Option<&str>,
+    ) -> Result<bool, (StatusCode, Json<ErrorResponse>)>;
+
+    async fn insert_login_history(
+        &self,
+        login: NewUserLoginHistory,
+    ) -> Result<(), (StatusCode, Json<ErrorResponse>)>;
+
+    // Newest first
+    async fn list_login_history(
+        &self,
+        user_id: &Uuid,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<UserLoginHistory>, (StatusCode, Json<ErrorResponse>)>;
+}
+
+#[async_trait]
+pub trait AccountRepository: Debug + Send + Sync {
+    // Tokens are revoked at the same time, so the user is logged out everywhere
+    async fn schedule_deletion(
+        &self,
+        user_id: &Uuid,
+        scheduled_at: OffsetDateTime,
+    ) -> Result<(), (StatusCode, Json<ErrorResponse>)>;
+
+    // Returns false if no deletion was scheduled
+    async fn cancel_deletion(
+        &self,
+        user_id: &Uuid,
+    ) -> Result<bool, (StatusCode, Json<ErrorResponse>)>;
+
+    // Anonymise every user whose grace period is over and delete all rows owned by them
+    // Returns the ids of the purged users
+    async fn purge_deleted_users(&self) -> Result<Vec<Uuid>, (StatusCode, Json<ErrorResponse>)>;
+
+    // Only the latest EXPORTED_LOGIN_HISTORY_LIMIT logins are part of the archive
+    async fn export_user_data(
+        &self,
+        user_id: &Uuid,
+    ) -> Result<Option<UserDataArchive>, (StatusCode, Json<ErrorResponse>)>;
+
+    async fn create_data_export(
+        &self,
+        user_id: &Uuid,
+    ) -> Result<UserDataExport, (StatusCode, Json<ErrorResponse>)>;
+
+    async fn complete_data_export(
+        &self,
+        export_id: &Uuid,
+        archive: serde_json::Value,
+        expires_at: OffsetDateTime,
+    ) -> Result<(), (StatusCode, Json<ErrorResponse>)>;
+
+    async fn fail_data_export(
+        &self,
+        export_id: &Uuid,
+    ) -> Result<(), (StatusCode, Json<ErrorResponse>)>;
+
+    async fn get_data_export(
+        &self,
+        user_id: &Uuid,
+        export_id: &Uuid,
+    ) -> Result<Option<UserDataExport>, (StatusCode, Json<ErrorResponse>)>;
+
+    // Exports started before 'created_after' are ignored, they were lost with a restart of the server
+    async fn get_pending_data_export(
+        &self,
+        user_id: &Uuid,
+        created_after: OffsetDateTime,
+    ) -> Result<Option<UserDataExport>, (StatusCode, Json<ErrorResponse>)>;
+
+    // Only completed archives that have not expired yet are returned
+    async fn get_data_export_archive(
+        &self,
+        user_id: &Uuid,
+        export_id: &Uuid,
+    ) -> Result<Option<serde_json::Value>, (StatusCode, Json<ErrorResponse>)>;
+
+    // Returns the number of deleted exports
+    async fn delete_expired_data_exports(&self) -> Result<u64, (StatusCode, Json<ErrorResponse>)>;
+}
+
+// Every admin action is written to the audit log on the same transaction as the action itself
+#[async_trait]
+pub trait AdminRepository: Debug + Send + Sync {
+    async fn list_users(
+        &self,
+        filter: UserFilter,
+    ) -> Result<Vec<User>, (StatusCode, Json<ErrorResponse>)>;
+
+    async fn verify_user(
+        &self,
+        user_id: &Uuid,
+        audit_log: NewAdminAuditLog,
+    ) -> Result<(), (StatusCode, Json<ErrorResponse>)>;
+
+    // Tokens are revoked at the same time, so restricted users lose their sessions immediately
+    async fn restrict_user(
+        &self,
+        user_id: &Uuid,
+        update: UserRestrictionUpdate,
+        audit_log: NewAdminAuditLog,
+    ) -> Result<(), (StatusCode, Json<ErrorResponse>)>;
+
+    async fn revoke_tokens(
+        &self,
+        user_id: &Uuid,
+        audit_log: NewAdminAuditLog,
+    ) -> Result<(), (StatusCode, Json<ErrorResponse>)>;
+
+    // Returns false if the user is the last admin and would lose the role
+    async fn update_role(
+        &self,
+        user_id: &Uuid,
+        role: UserRole,
+        audit_log: NewAdminAuditLog,
+    ) -> Result<bool, (StatusCode, Json<ErrorResponse>)>;
+
+    // Newest first
+    async fn list_audit_logs(
+        &self,
+        target_user_id: Option<Uuid>,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<AdminAuditLog>, (StatusCode, Json<ErrorResponse>)>;
+}
+
+#[async_trait]
+pub trait ReportRepository: Debug + Send + Sync {
+    async fn insert_report(
+        &self,
+        new_report: NewReport,
+    ) -> Result<Uuid, (StatusCode, Json<ErrorResponse>)>;
+
+    async fn get_report(
+        &self,
+        report_id: &Uuid,
+    ) -> Result<Option<Report>, (StatusCode, Json<ErrorResponse>)>;
+
+    // Oldest first
+    async fn list_reports(
+        &self,
+        status: ReportStatus,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<Report>, (StatusCode, Json<ErrorResponse>)>;
+
+    // The resolution and its follow-up either both happen or neither does
+    // Returns false if the report is no longer pending, someone else resolved it first
+    async fn resolve_report(
+        &self,
+        report_id: &Uuid,
+        resolution: ReportResolution,
+        follow_up: ReportFollowUp,
+    ) -> Result<bool, (StatusCode, Json<ErrorResponse>)>;
+}
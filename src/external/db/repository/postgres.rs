@@ -0,0 +1,593 @@
+use super::{
+    AccountRepository, AdminRepository, IdentityRepository, LoginSecurityRepository,
+    ReportRepository, TwoFactorRepository, UserRepository, VerificationRepository,
+    EXPORTED_LOGIN_HISTORY_LIMIT,
+};
+use crate::external::db;
+use crate::external::db::admin_audit_log::NewAdminAuditLog;
+use crate::external::db::models::{
+    AdminAuditLog, Report, ReportStatus, User, UserDataArchive, UserDataExport, UserIdentity,
+    UserLoginHistory, UserRole, UserTwoFactor,
+};
+use crate::external::db::report::{NewReport, ReportFollowUp, ReportResolution};
+use crate::external::db::user::{NewUser, UserFilter, UserRestrictionUpdate};
+use crate::external::db::user_identity::NewUserIdentity;
+use crate::external::db::user_login::NewUserLoginHistory;
+use crate::external::db::user_verification::NewUserVerification;
+use crate::logger::Secret;
+use crate::server::handlers::ErrorResponse;
+use axum::async_trait;
+use axum::{http::StatusCode, Json};
+use sqlx::{Pool, Postgres};
+use time::OffsetDateTime;
+use tracing::error;
+use uuid::Uuid;
+
+// Repository backed by the Postgres queries in the db modules
+#[derive(Debug)]
+pub struct PgRepository {
+    db_client: Pool<Postgres>,
+}
+
+impl PgRepository {
+    pub fn new(db_client: Pool<Postgres>) -> Self {
+        Self { db_client }
+    }
+
+    // Every user is purged on its own transaction, returns false if the deletion was cancelled in the meantime
+    async fn purge_deleted_user(
+        &self,
+        user_id: &Uuid,
+    ) -> Result<bool, (StatusCode, Json<ErrorResponse>)> {
+        let mut transaction = db::begin_transaction(&self.db_client).await?;
+        if !db::user::anonymize_user(&mut *transaction, user_id).await? {
+            return Ok(false);
+        }
+        db::user_verification::delete_user_verifications(&mut *transaction, user_id).await?;
+        db::user_two_factor::delete_recovery_codes(&mut *transaction, user_id).await?;
+        db::user_two_factor::delete_user_two_factor(&mut *transaction, user_id).await?;
+        db::user_identity::delete_user_identities(&mut *transaction, user_id).await?;
+        db::user_login::delete_login_lockout(&mut *transaction, user_id).await?;
+        db::user_login::delete_login_history(&mut *transaction, user_id).await?;
+        db::user_data_export::delete_user_data_exports(&mut *transaction, user_id).await?;
+        db::commit_transaction(transaction).await?;
+        Ok(true)
+    }
+}
+
+#[async_trait]
+impl UserRepository for PgRepository {
+    async fn is_user_exists(&self, email: &str) -> Result<bool, (StatusCode, Json<ErrorResponse>)> {
+        db::user::is_user_exists(&self.db_client, email).await
+    }
+
+    async fn register_user(
+        &self,
+        new_user: NewUser,
+        verification_token_hash: String,
+        verification_expires_at: OffsetDateTime,
+    ) -> Result<Uuid, (StatusCode, Json<ErrorResponse>)> {
+        let mut transaction = db::begin_transaction(&self.db_client).await?;
+        let user_id = db::user::insert_new_user(&mut *transaction, new_user).await?;
+        db::user_verification::insert_new_user_verification(
+            &mut *transaction,
+            NewUserVerification {
+                user_id,
+                token_hash: verification_token_hash,
+                expires_at: verification_expires_at,
+            },
+        )
+        .await?;
+        db::commit_transaction(transaction).await?;
+        Ok(user_id)
+    }
+
+    async fn get_user_by_email(
+        &self,
+        email: &str,
+    ) -> Result<Option<User>, (StatusCode, Json<ErrorResponse>)> {
+        db::user::get_user_by_email(&self.db_client, email).await
+    }
+
+    async fn get_user_by_id(
+        &self,
+        user_id: &Uuid,
+    ) -> Result<Option<User>, (StatusCode, Json<ErrorResponse>)> {
+        db::user::get_user_by_id(&self.db_client, user_id).await
+    }
+
+    async fn update_verified_status(
+        &self,
+        user_id: &Uuid,
+        status: bool,
+    ) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+        db::user::update_verified_status(&self.db_client, user_id, status).await
+    }
+
+    async fn update_password_hash(
+        &self,
+        user_id: &Uuid,
+        previous_password: &Secret<String>,
+        password: &Secret<String>,
+    ) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+        db::user::update_password_hash(&self.db_client, user_id, previous_password, password).await
+    }
+}
+
+#[async_trait]
+impl VerificationRepository for PgRepository {
+    async fn activate_user(
+        &self,
+        token_hash: &str,
+    ) -> Result<bool, (StatusCode, Json<ErrorResponse>)> {
+        let mut transaction = db::begin_transaction(&self.db_client).await?;
+        let is_activated =
+            match db::user_verification::delete_user_verification(&mut *transaction, token_hash)
+                .await?
+            {
+                Some(verification) if verification.expires_at > OffsetDateTime::now_utc() => {
+                    db::user::update_verified_status(
+                        &mut *transaction,
+                        &verification.user_id,
+                        true,
+                    )
+                    .await?;
+                    true
+                }
+                _ => false,
+            };
+        db::commit_transaction(transaction).await?;
+        Ok(is_activated)
+    }
+}
+
+#[async_trait]
+impl TwoFactorRepository for PgRepository {
+    async fn get_two_factor(
+        &self,
+        user_id: &Uuid,
+    ) -> Result<Option<UserTwoFactor>, (StatusCode, Json<ErrorResponse>)> {
+        db::user_two_factor::get_user_two_factor(&self.db_client, user_id).await
+    }
+
+    async fn start_enrollment(
+        &self,
+        user_id: &Uuid,
+        secret: Secret<String>,
+    ) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+        db::user_two_factor::upsert_pending_user_two_factor(&self.db_client, user_id, &secret).await
+    }
+
+    async fn enable_two_factor(
+        &self,
+        user_id: &Uuid,
+        step: i64,
+        recovery_code_hashes: Vec<String>,
+    ) -> Result<bool, (StatusCode, Json<ErrorResponse>)> {
+        let mut transaction = db::begin_transaction(&self.db_client).await?;
+        if !db::user_two_factor::enable_user_two_factor(&mut *transaction, user_id, step).await? {
+            return Ok(false);
+        }
+        db::user_two_factor::delete_recovery_codes(&mut *transaction, user_id).await?;
+        db::user_two_factor::insert_recovery_codes(
+            &mut *transaction,
+            user_id,
+            &recovery_code_hashes,
+        )
+        .await?;
+        db::commit_transaction(transaction).await?;
+        Ok(true)
+    }
+
+    async fn disable_two_factor(
+        &self,
+        user_id: &Uuid,
+    ) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+        let mut transaction = db::begin_transaction(&self.db_client).await?;
+        db::user_two_factor::delete_recovery_codes(&mut *transaction, user_id).await?;
+        db::user_two_factor::delete_user_two_factor(&mut *transaction, user_id).await?;
+        db::commit_transaction(transaction).await
+    }
+
+    async fn use_code_step(
+        &self,
+        user_id: &Uuid,
+        step: i64,
+    ) -> Result<bool, (StatusCode, Json<ErrorResponse>)> {
+        db::user_two_factor::update_last_used_step(&self.db_client, user_id, step).await
+    }
+
+    async fn use_recovery_code(
+        &self,
+        user_id: &Uuid,
+        code_hash: &str,
+    ) -> Result<bool, (StatusCode, Json<ErrorResponse>)> {
+        db::user_two_factor::use_recovery_code(&self.db_client, user_id, code_hash).await
+    }
+
+    async fn count_unused_recovery_codes(
+        &self,
+        user_id: &Uuid,
+    ) -> Result<i64, (StatusCode, Json<ErrorResponse>)> {
+        db::user_two_factor::count_unused_recovery_codes(&self.db_client, user_id).await
+    }
+}
+
+#[async_trait]
+impl IdentityRepository for PgRepository {
+    async fn get_identity(
+        &self,
+        provider: &str,
+        subject: &str,
+    ) -> Result<Option<UserIdentity>, (StatusCode, Json<ErrorResponse>)> {
+        db::user_identity::get_user_identity(&self.db_client, provider, subject).await
+    }
+
+    async fn list_identities(
+        &self,
+        user_id: &Uuid,
+    ) -> Result<Vec<UserIdentity>, (StatusCode, Json<ErrorResponse>)> {
+        db::user_identity::list_user_identities(&self.db_client, user_id).await
+    }
+
+    async fn register_identity_user(
+        &self,
+        email: String,
+        name: Option<String>,
+        provider: String,
+        subject: String,
+    ) -> Result<Uuid, (StatusCode, Json<ErrorResponse>)> {
+        let mut transaction = db::begin_transaction(&self.db_client).await?;
+        let user_id =
+            db::user::insert_identity_user(&mut *transaction, &email, name.as_deref()).await?;
+        let identity = NewUserIdentity {
+            user_id,
+            provider,
+            subject,
+            email: Some(email),
+        };
+        if !db::user_identity::insert_user_identity(&mut *transaction, &identity).await? {
+            return Err((
+                StatusCode::CONFLICT,
+                Json(ErrorResponse {
+                    success: false,
+                    error: "Account is already linked to another user.".to_string(),
+                }),
+            ));
+        }
+        db::commit_transaction(transaction).await?;
+        Ok(user_id)
+    }
+
+    async fn link_identity(
+        &self,
+        identity: NewUserIdentity,
+        claim_unverified_user: bool,
+    ) -> Result<bool, (StatusCode, Json<ErrorResponse>)> {
+        let mut transaction = db::begin_transaction(&self.db_client).await?;
+        if !db::user_identity::insert_user_identity(&mut *transaction, &identity).await? {
+            return Ok(false);
+        }
+        if claim_unverified_user {
+            db::user::verify_user_and_clear_password(&mut *transaction, &identity.user_id).await?;
+        }
+        db::commit_transaction(transaction).await?;
+        Ok(true)
+    }
+
+    async fn unlink_identity(
+        &self,
+        user_id: &Uuid,
+        provider: &str,
+    ) -> Result<bool, (StatusCode, Json<ErrorResponse>)> {
+        db::user_identity::delete_user_identity(&self.db_client, user_id, provider).await
+    }
+}
+
+#[async_trait]
+impl LoginSecurityRepository for PgRepository {
+    async fn get_locked_until(
+        &self,
+        user_id: &Uuid,
+    ) -> Result<Option<OffsetDateTime>, (StatusCode, Json<ErrorResponse>)> {
+        db::user_login::get_login_locked_until(&self.db_client, user_id).await
+    }
+
+    async fn record_failed_login(
+        &self,
+        user_id: &Uuid,
+        reset_before: OffsetDateTime,
+    ) -> Result<i32, (StatusCode, Json<ErrorResponse>)> {
+        db::user_login::increment_failed_logins(&self.db_client, user_id, reset_before).await
+    }
+
+    async fn lock_login(
+        &self,
+        user_id: &Uuid,
+        locked_until: OffsetDateTime,
+    ) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+        db::user_login::lock_login(&self.db_client, user_id, locked_until).await
+    }
+
+    async fn clear_failed_logins(
+        &self,
+        user_id: &Uuid,
+    ) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+        db::user_login::delete_login_lockout(&self.db_client, user_id).await
+    }
+
+    async fn is_new_device(
+        &self,
+        user_id: &Uuid,
+        ip: &str,
+        user_agent: Option<&str>,
+    ) -> Result<bool, (StatusCode, Json<ErrorResponse>)> {
+        db::user_login::is_new_device(&self.db_client, user_id, ip, user_agent).await
+    }
+
+    async fn insert_login_history(
+        &self,
+        login: NewUserLoginHistory,
+    ) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+        db::user_login::insert_login_history(&self.db_client, login).await
+    }
+
+    async fn list_login_history(
+        &self,
+        user_id: &Uuid,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<UserLoginHistory>, (StatusCode, Json<ErrorResponse>)> {
+        db::user_login::list_login_history(&self.db_client, user_id, limit, offset).await
+    }
+}
+
+#[async_trait]
+impl AccountRepository for PgRepository {
+    async fn schedule_deletion(
+        &self,
+        user_id: &Uuid,
+        scheduled_at: OffsetDateTime,
+    ) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+        db::user::schedule_user_deletion(&self.db_client, user_id, scheduled_at).await
+    }
+
+    async fn cancel_deletion(
+        &self,
+        user_id: &Uuid,
+    ) -> Result<bool, (StatusCode, Json<ErrorResponse>)> {
+        db::user::cancel_user_deletion(&self.db_client, user_id).await
+    }
+
+    async fn purge_deleted_users(&self) -> Result<Vec<Uuid>, (StatusCode, Json<ErrorResponse>)> {
+        let mut purged_user_ids = Vec::new();
+        for user_id in db::user::list_users_due_for_deletion(&self.db_client).await? {
+            match self.purge_deleted_user(&user_id).await {
+                Ok(true) => purged_user_ids.push(user_id),
+                Ok(false) => {}
+                // One failing user must not hold up the others, it is retried on the next run
+                Err(_) => error!("failed to purge deleted user {}", user_id),
+            }
+        }
+        Ok(purged_user_ids)
+    }
+
+    async fn export_user_data(
+        &self,
+        user_id: &Uuid,
+    ) -> Result<Option<UserDataArchive>, (StatusCode, Json<ErrorResponse>)> {
+        // Read on one transaction so that the archive is a consistent snapshot
+        let mut transaction = db::begin_transaction(&self.db_client).await?;
+        let Some(profile) = db::user::get_user_by_id(&mut *transaction, user_id).await? else {
+            return Ok(None);
+        };
+        let archive = UserDataArchive {
+            exported_at: OffsetDateTime::now_utc(),
+            identities: db::user_identity::list_user_identities(&mut *transaction, user_id).await?,
+            two_factor_enabled: db::user_two_factor::get_user_two_factor(
+                &mut *transaction,
+                user_id,
+            )
+            .await?
+            .is_some_and(|two_factor| two_factor.enabled),
+            login_history: db::user_login::list_login_history(
+                &mut *transaction,
+                user_id,
+                EXPORTED_LOGIN_HISTORY_LIMIT,
+                0,
+            )
+            .await?,
+            reports: db::report::list_reports_by_reporter(&mut *transaction, user_id).await?,
+            warnings: db::user_warning::list_user_warnings(&mut *transaction, user_id).await?,
+            profile,
+        };
+        db::commit_transaction(transaction).await?;
+        Ok(Some(archive))
+    }
+
+    async fn create_data_export(
+        &self,
+        user_id: &Uuid,
+    ) -> Result<UserDataExport, (StatusCode, Json<ErrorResponse>)> {
+        db::user_data_export::insert_user_data_export(&self.db_client, user_id).await
+    }
+
+    async fn complete_data_export(
+        &self,
+        export_id: &Uuid,
+        archive: serde_json::Value,
+        expires_at: OffsetDateTime,
+    ) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+        db::user_data_export::complete_user_data_export(
+            &self.db_client,
+            export_id,
+            archive,
+            expires_at,
+        )
+        .await
+    }
+
+    async fn fail_data_export(
+        &self,
+        export_id: &Uuid,
+    ) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+        db::user_data_export::fail_user_data_export(&self.db_client, export_id).await
+    }
+
+    async fn get_data_export(
+        &self,
+        user_id: &Uuid,
+        export_id: &Uuid,
+    ) -> Result<Option<UserDataExport>, (StatusCode, Json<ErrorResponse>)> {
+        db::user_data_export::get_user_data_export(&self.db_client, user_id, export_id).await
+    }
+
+    async fn get_pending_data_export(
+        &self,
+        user_id: &Uuid,
+        created_after: OffsetDateTime,
+    ) -> Result<Option<UserDataExport>, (StatusCode, Json<ErrorResponse>)> {
+        db::user_data_export::get_pending_user_data_export(&self.db_client, user_id, created_after)
+            .await
+    }
+
+    async fn get_data_export_archive(
+        &self,
+        user_id: &Uuid,
+        export_id: &Uuid,
+    ) -> Result<Option<serde_json::Value>, (StatusCode, Json<ErrorResponse>)> {
+        db::user_data_export::get_user_data_export_archive(&self.db_client, user_id, export_id)
+            .await
+    }
+
+    async fn delete_expired_data_exports(&self) -> Result<u64, (StatusCode, Json<ErrorResponse>)> {
+        db::user_data_export::delete_expired_user_data_exports(&self.db_client).await
+    }
+}
+
+#[async_trait]
+impl AdminRepository for PgRepository {
+    async fn list_users(
+        &self,
+        filter: UserFilter,
+    ) -> Result<Vec<User>, (StatusCode, Json<ErrorResponse>)> {
+        db::user::list_users(&self.db_client, filter).await
+    }
+
+    async fn verify_user(
+        &self,
+        user_id: &Uuid,
+        audit_log: NewAdminAuditLog,
+    ) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+        let mut transaction = db::begin_transaction(&self.db_client).await?;
+        db::user::update_verified_status(&mut *transaction, user_id, true).await?;
+        db::admin_audit_log::insert_admin_audit_log(&mut *transaction, audit_log).await?;
+        db::commit_transaction(transaction).await
+    }
+
+    async fn restrict_user(
+        &self,
+        user_id: &Uuid,
+        update: UserRestrictionUpdate,
+        audit_log: NewAdminAuditLog,
+    ) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+        let mut transaction = db::begin_transaction(&self.db_client).await?;
+        db::user::update_restriction(&mut *transaction, user_id, update).await?;
+        db::user::revoke_tokens(&mut *transaction, user_id).await?;
+        db::admin_audit_log::insert_admin_audit_log(&mut *transaction, audit_log).await?;
+        db::commit_transaction(transaction).await
+    }
+
+    async fn revoke_tokens(
+        &self,
+        user_id: &Uuid,
+        audit_log: NewAdminAuditLog,
+    ) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+        let mut transaction = db::begin_transaction(&self.db_client).await?;
+        db::user::revoke_tokens(&mut *transaction, user_id).await?;
+        db::admin_audit_log::insert_admin_audit_log(&mut *transaction, audit_log).await?;
+        db::commit_transaction(transaction).await
+    }
+
+    async fn update_role(
+        &self,
+        user_id: &Uuid,
+        role: UserRole,
+        audit_log: NewAdminAuditLog,
+    ) -> Result<bool, (StatusCode, Json<ErrorResponse>)> {
+        let mut transaction = db::begin_transaction(&self.db_client).await?;
+        let admin_ids = db::user::lock_admin_ids(&mut *transaction).await?;
+        if role != UserRole::Admin && admin_ids == [*user_id] {
+            return Ok(false);
+        }
+        db::user::update_role(&mut *transaction, user_id, role).await?;
+        db::admin_audit_log::insert_admin_audit_log(&mut *transaction, audit_log).await?;
+        db::commit_transaction(transaction).await?;
+        Ok(true)
+    }
+
+    async fn list_audit_logs(
+        &self,
+        target_user_id: Option<Uuid>,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<AdminAuditLog>, (StatusCode, Json<ErrorResponse>)> {
+        db::admin_audit_log::list_admin_audit_logs(&self.db_client, target_user_id, limit, offset)
+            .await
+    }
+}
+
+#[async_trait]
+impl ReportRepository for PgRepository {
+    async fn insert_report(
+        &self,
+        new_report: NewReport,
+    ) -> Result<Uuid, (StatusCode, Json<ErrorResponse>)> {
+        db::report::insert_new_report(&self.db_client, new_report).await
+    }
+
+    async fn get_report(
+        &self,
+        report_id: &Uuid,
+    ) -> Result<Option<Report>, (StatusCode, Json<ErrorResponse>)> {
+        db::report::get_report_by_id(&self.db_client, report_id).await
+    }
+
+    async fn list_reports(
+        &self,
+        status: ReportStatus,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<Report>, (StatusCode, Json<ErrorResponse>)> {
+        db::report::list_reports(&self.db_client, status, limit, offset).await
+    }
+
+    async fn resolve_report(
+        &self,
+        report_id: &Uuid,
+        resolution: ReportResolution,
+        follow_up: ReportFollowUp,
+    ) -> Result<bool, (StatusCode, Json<ErrorResponse>)> {
+        let mut transaction = db::begin_transaction(&self.db_client).await?;
+        if !db::report::resolve_report(&mut *transaction, report_id, resolution).await? {
+            return Ok(false);
+        }
+        match follow_up {
+            ReportFollowUp::None => {}
+            ReportFollowUp::Warn(warning) => {
+                db::user_warning::insert_new_user_warning(&mut *transaction, warning).await?;
+            }
+            ReportFollowUp::Suspend {
+                user_id,
+                update,
+                audit_log,
+            } => {
+                db::user::update_restriction(&mut *transaction, &user_id, update).await?;
+                db::user::revoke_tokens(&mut *transaction, &user_id).await?;
+                db::admin_audit_log::insert_admin_audit_log(&mut *transaction, audit_log).await?;
+            }
+        }
+        db::commit_transaction(transaction).await?;
+        Ok(true)
+    }
+}
@@ -1,22 +1,43 @@
+use super::internal_server_error;
+use super::models::{User, UserRestriction, UserRole};
+use crate::logger::Secret;
 use crate::server::handlers::ErrorResponse;
 use axum::{http::StatusCode, Json};
-use sqlx::{Pool, Postgres};
-use tracing::error;
+use sqlx::PgExecutor;
+use time::OffsetDateTime;
+use tracing::{debug, error};
 use uuid::Uuid;
 
 #[derive(Debug)]
 pub struct NewUser {
     pub email: String,
-    pub password: String,
+    // Hashed password
+    pub password: Secret<String>,
 }
 
-#[tracing::instrument]
+#[derive(Debug, Default)]
+pub struct UserFilter {
+    pub email: Option<String>,
+    pub role: Option<UserRole>,
+    pub verified: Option<bool>,
+    pub limit: i64,
+    pub offset: i64,
+}
+
+#[derive(Debug)]
+pub struct UserRestrictionUpdate {
+    pub restriction: UserRestriction,
+    pub reason: String,
+    pub expires_at: Option<OffsetDateTime>,
+}
+
+#[tracing::instrument(skip(db_client))]
 pub async fn is_user_exists(
-    db_client: &Pool<Postgres>,
+    db_client: impl PgExecutor<'_>,
     email: &str,
 ) -> Result<bool, (StatusCode, Json<ErrorResponse>)> {
     let user_exists_result = sqlx::query_scalar!(
-        "SELECT EXISTS(SELECT 1 FROM \"user\" WHERE email = $1)",
+        "SELECT EXISTS(SELECT 1 FROM \"user\" WHERE lower(email) = lower($1))",
         email
     )
     .fetch_one(db_client)
@@ -29,72 +50,157 @@ pub async fn is_user_exists(
         },
         Err(e) => {
             error!("{}", e);
-            Err((
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(ErrorResponse {
-                    success: false,
-                    error: format!("Internal server error."),
-                }),
-            ))
+            Err(internal_server_error())
         }
     }
 }
 
-#[tracing::instrument]
+#[tracing::instrument(skip(db_client))]
 pub async fn insert_new_user(
-    db_client: &Pool<Postgres>,
+    db_client: impl PgExecutor<'_>,
     new_user: NewUser,
 ) -> Result<Uuid, (StatusCode, Json<ErrorResponse>)> {
-    let inserted_user_id = sqlx::query_scalar!(
+    sqlx::query_scalar!(
         "INSERT INTO \"user\" (email, password) VALUES ($1, $2) RETURNING id",
         new_user.email,
-        new_user.password,
+        new_user.password.expose(),
     )
     .fetch_one(db_client)
     .await
-    .map_err(|error| {
-        error!("{}", error);
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(ErrorResponse {
-                success: false,
-                error: format!("Internal server error."),
-            }),
-        )
-    });
-    inserted_user_id
-}
-
-#[tracing::instrument]
-pub async fn get_user_password(
-    db_client: &Pool<Postgres>,
+    .map_err(|error| match error {
+        // Another registration with the same email won the race after the existence check
+        sqlx::Error::Database(database_error) if database_error.is_unique_violation() => {
+            debug!("user email already exists. {}", database_error);
+            (
+                StatusCode::CONFLICT,
+                Json(ErrorResponse {
+                    success: false,
+                    error: "User already exists.".to_string(),
+                }),
+            )
+        }
+        error => {
+            error!("{}", error);
+            internal_server_error()
+        }
+    })
+}
+
+// Users created through an identity provider are verified by it and have no password
+#[tracing::instrument(skip(db_client))]
+pub async fn insert_identity_user(
+    db_client: impl PgExecutor<'_>,
     email: &str,
-) -> Result<String, (StatusCode, Json<ErrorResponse>)> {
-    let user_password =
-        sqlx::query_scalar!("SELECT password FROM \"user\" WHERE email = $1", email)
-            .fetch_one(db_client)
-            .await
-            .map_err(|error| {
-                error!("{}", error);
-                (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    Json(ErrorResponse {
-                        success: false,
-                        error: format!("Internal server error."),
-                    }),
-                )
-            });
-    user_password
-}
-
-#[tracing::instrument]
+    name: Option<&str>,
+) -> Result<Uuid, (StatusCode, Json<ErrorResponse>)> {
+    sqlx::query_scalar!(
+        "INSERT INTO \"user\" (email, verified, name) VALUES ($1, TRUE, $2) RETURNING id",
+        email,
+        name,
+    )
+    .fetch_one(db_client)
+    .await
+    .map_err(|error| match error {
+        sqlx::Error::Database(database_error) if database_error.is_unique_violation() => {
+            debug!("user email already exists. {}", database_error);
+            (
+                StatusCode::CONFLICT,
+                Json(ErrorResponse {
+                    success: false,
+                    error: "User already exists.".to_string(),
+                }),
+            )
+        }
+        error => {
+            error!("{}", error);
+            internal_server_error()
+        }
+    })
+}
+
+#[tracing::instrument(skip(db_client))]
+pub async fn get_user_by_email(
+    db_client: impl PgExecutor<'_>,
+    email: &str,
+) -> Result<Option<User>, (StatusCode, Json<ErrorResponse>)> {
+    sqlx::query_as!(
+        User,
+        r#"SELECT id, email, password AS "password: Secret<String>", verified, name, avatar, role AS "role: UserRole",
+           restriction AS "restriction: UserRestriction", restriction_reason,
+           restriction_expires_at, tokens_revoked_at, deletion_scheduled_at, deleted_at,
+           created_at, updated_at
+           FROM "user" WHERE lower(email) = lower($1)"#,
+        email
+    )
+    .fetch_optional(db_client)
+    .await
+    .map_err(|error| {
+        error!("failed to get user by email from database. {}", error);
+        internal_server_error()
+    })
+}
+
+#[tracing::instrument(skip(db_client))]
+pub async fn get_user_by_id(
+    db_client: impl PgExecutor<'_>,
+    user_id: &Uuid,
+) -> Result<Option<User>, (StatusCode, Json<ErrorResponse>)> {
+    sqlx::query_as!(
+        User,
+        r#"SELECT id, email, password AS "password: Secret<String>", verified, name, avatar, role AS "role: UserRole",
+           restriction AS "restriction: UserRestriction", restriction_reason,
+           restriction_expires_at, tokens_revoked_at, deletion_scheduled_at, deleted_at,
+           created_at, updated_at
+           FROM "user" WHERE id = $1"#,
+        user_id
+    )
+    .fetch_optional(db_client)
+    .await
+    .map_err(|error| {
+        error!("failed to get user by id from database. {}", error);
+        internal_server_error()
+    })
+}
+
+#[tracing::instrument(skip(db_client))]
+pub async fn list_users(
+    db_client: impl PgExecutor<'_>,
+    filter: UserFilter,
+) -> Result<Vec<User>, (StatusCode, Json<ErrorResponse>)> {
+    sqlx::query_as!(
+        User,
+        r#"SELECT id, email, password AS "password: Secret<String>", verified, name, avatar, role AS "role: UserRole",
+           restriction AS "restriction: UserRestriction", restriction_reason,
+           restriction_expires_at, tokens_revoked_at, deletion_scheduled_at, deleted_at,
+           created_at, updated_at
+           FROM "user"
+           WHERE ($1::TEXT IS NULL OR position(lower($1) in lower(email)) > 0)
+           AND ($2::user_role IS NULL OR role = $2)
+           AND ($3::BOOLEAN IS NULL OR verified = $3)
+           ORDER BY created_at DESC
+           LIMIT $4 OFFSET $5"#,
+        filter.email,
+        filter.role as Option<UserRole>,
+        filter.verified,
+        filter.limit,
+        filter.offset,
+    )
+    .fetch_all(db_client)
+    .await
+    .map_err(|error| {
+        error!("failed to list users from database. {}", error);
+        internal_server_error()
+    })
+}
+
+#[tracing::instrument(skip(db_client))]
 pub async fn update_verified_status(
-    db_client: &Pool<Postgres>,
+    db_client: impl PgExecutor<'_>,
     user_id: &Uuid,
     status: bool,
 ) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
     sqlx::query!(
-        "UPDATE \"user\" SET verified = $1 WHERE id = $2",
+        "UPDATE \"user\" SET verified = $1, updated_at = now() WHERE id = $2",
         status,
         user_id
     )
@@ -102,13 +208,209 @@ pub async fn update_verified_status(
     .await
     .map_err(|error| {
         error!("{}", error);
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(ErrorResponse {
-                success: false,
-                error: format!("Internal server error."),
-            }),
-        )
+        internal_server_error()
     })?;
     Ok(())
 }
+
+#[tracing::instrument(skip(db_client))]
+pub async fn update_restriction(
+    db_client: impl PgExecutor<'_>,
+    user_id: &Uuid,
+    update: UserRestrictionUpdate,
+) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+    sqlx::query!(
+        "UPDATE \"user\" SET restriction = $1, restriction_reason = $2, restriction_expires_at = $3, updated_at = now() WHERE id = $4",
+        update.restriction as UserRestriction,
+        update.reason,
+        update.expires_at,
+        user_id
+    )
+    .execute(db_client)
+    .await
+    .map_err(|error| {
+        error!("failed to update user restriction in database. {}", error);
+        internal_server_error()
+    })?;
+    Ok(())
+}
+
+#[tracing::instrument(skip(db_client))]
+pub async fn update_role(
+    db_client: impl PgExecutor<'_>,
+    user_id: &Uuid,
+    role: UserRole,
+) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+    sqlx::query!(
+        "UPDATE \"user\" SET role = $1, updated_at = now() WHERE id = $2",
+        role as UserRole,
+        user_id
+    )
+    .execute(db_client)
+    .await
+    .map_err(|error| {
+        error!("failed to update user role in database. {}", error);
+        internal_server_error()
+    })?;
+    Ok(())
+}
+
+// Rows stay locked until the transaction ends, so concurrent role changes cannot demote every admin
+#[tracing::instrument(skip(db_client))]
+pub async fn lock_admin_ids(
+    db_client: impl PgExecutor<'_>,
+) -> Result<Vec<Uuid>, (StatusCode, Json<ErrorResponse>)> {
+    sqlx::query_scalar!(
+        "SELECT id FROM \"user\" WHERE role = 'admin' AND deleted_at IS NULL FOR UPDATE"
+    )
+    .fetch_all(db_client)
+    .await
+    .map_err(|error| {
+        error!("failed to lock admin users in database. {}", error);
+        internal_server_error()
+    })
+}
+
+#[tracing::instrument(skip(db_client))]
+pub async fn revoke_tokens(
+    db_client: impl PgExecutor<'_>,
+    user_id: &Uuid,
+) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+    sqlx::query!(
+        "UPDATE \"user\" SET tokens_revoked_at = now(), updated_at = now() WHERE id = $1",
+        user_id
+    )
+    .execute(db_client)
+    .await
+    .map_err(|error| {
+        error!("failed to revoke user tokens in database. {}", error);
+        internal_server_error()
+    })?;
+    Ok(())
+}
+
+// The password of an unverified user was never proven to belong to the owner of the email
+// so it is removed once the owner signs in through an identity provider
+#[tracing::instrument(skip(db_client))]
+pub async fn verify_user_and_clear_password(
+    db_client: impl PgExecutor<'_>,
+    user_id: &Uuid,
+) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+    sqlx::query!(
+        "UPDATE \"user\" SET verified = TRUE, password = NULL, updated_at = now() WHERE id = $1",
+        user_id
+    )
+    .execute(db_client)
+    .await
+    .map_err(|error| {
+        error!("failed to verify user in database. {}", error);
+        internal_server_error()
+    })?;
+    Ok(())
+}
+
+// The previous hash is part of the condition so that a password changed in the meantime is never overwritten
+#[tracing::instrument(skip(db_client))]
+pub async fn update_password_hash(
+    db_client: impl PgExecutor<'_>,
+    user_id: &Uuid,
+    previous_password: &Secret<String>,
+    password: &Secret<String>,
+) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+    sqlx::query!(
+        "UPDATE \"user\" SET password = $3, updated_at = now() WHERE id = $1 AND password = $2",
+        user_id,
+        previous_password.expose(),
+        password.expose()
+    )
+    .execute(db_client)
+    .await
+    .map_err(|error| {
+        error!("failed to update user password in database. {}", error);
+        internal_server_error()
+    })?;
+    Ok(())
+}
+
+// Tokens are revoked at the same time, so the user is logged out everywhere until the deletion is cancelled
+#[tracing::instrument(skip(db_client))]
+pub async fn schedule_user_deletion(
+    db_client: impl PgExecutor<'_>,
+    user_id: &Uuid,
+    scheduled_at: OffsetDateTime,
+) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+    sqlx::query!(
+        r#"UPDATE "user" SET deletion_scheduled_at = $2, tokens_revoked_at = now(), updated_at = now()
+           WHERE id = $1 AND deleted_at IS NULL"#,
+        user_id,
+        scheduled_at
+    )
+    .execute(db_client)
+    .await
+    .map_err(|error| {
+        error!("failed to schedule user deletion in database. {}", error);
+        internal_server_error()
+    })?;
+    Ok(())
+}
+
+#[tracing::instrument(skip(db_client))]
+pub async fn cancel_user_deletion(
+    db_client: impl PgExecutor<'_>,
+    user_id: &Uuid,
+) -> Result<bool, (StatusCode, Json<ErrorResponse>)> {
+    sqlx::query!(
+        r#"UPDATE "user" SET deletion_scheduled_at = NULL, updated_at = now()
+           WHERE id = $1 AND deletion_scheduled_at IS NOT NULL AND deleted_at IS NULL"#,
+        user_id
+    )
+    .execute(db_client)
+    .await
+    .map(|result| result.rows_affected() > 0)
+    .map_err(|error| {
+        error!("failed to cancel user deletion in database. {}", error);
+        internal_server_error()
+    })
+}
+
+#[tracing::instrument(skip(db_client))]
+pub async fn list_users_due_for_deletion(
+    db_client: impl PgExecutor<'_>,
+) -> Result<Vec<Uuid>, (StatusCode, Json<ErrorResponse>)> {
+    sqlx::query_scalar!(
+        r#"SELECT id FROM "user" WHERE deletion_scheduled_at <= now() AND deleted_at IS NULL
+           ORDER BY deletion_scheduled_at"#
+    )
+    .fetch_all(db_client)
+    .await
+    .map_err(|error| {
+        error!(
+            "failed to list users due for deletion from database. {}",
+            error
+        );
+        internal_server_error()
+    })
+}
+
+// The row stays as a 'Deleted user' placeholder so that reports, warnings and audit logs keep their references
+// Returns false if the deletion was cancelled or the user is already anonymised
+#[tracing::instrument(skip(db_client))]
+pub async fn anonymize_user(
+    db_client: impl PgExecutor<'_>,
+    user_id: &Uuid,
+) -> Result<bool, (StatusCode, Json<ErrorResponse>)> {
+    sqlx::query!(
+        r#"UPDATE "user" SET email = 'deleted-' || id || '@deleted.invalid', password = NULL, verified = FALSE,
+           name = 'Deleted user', avatar = NULL, tokens_revoked_at = now(), deletion_scheduled_at = NULL,
+           deleted_at = now(), updated_at = now()
+           WHERE id = $1 AND deletion_scheduled_at <= now() AND deleted_at IS NULL"#,
+        user_id
+    )
+    .execute(db_client)
+    .await
+    .map(|result| result.rows_affected() > 0)
+    .map_err(|error| {
+        error!("failed to anonymize user in database. {}", error);
+        internal_server_error()
+    })
+}
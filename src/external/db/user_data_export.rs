@@ -0,0 +1,177 @@
+use super::internal_server_error;
+use super::models::{UserDataExport, UserDataExportStatus};
+use crate::server::handlers::ErrorResponse;
+use axum::{http::StatusCode, Json};
+use sqlx::PgExecutor;
+use time::OffsetDateTime;
+use tracing::error;
+use uuid::Uuid;
+
+#[tracing::instrument(skip(db_client))]
+pub async fn insert_user_data_export(
+    db_client: impl PgExecutor<'_>,
+    user_id: &Uuid,
+) -> Result<UserDataExport, (StatusCode, Json<ErrorResponse>)> {
+    sqlx::query_as!(
+        UserDataExport,
+        r#"INSERT INTO user_data_export (user_id) VALUES ($1)
+           RETURNING id, user_id, status AS "status: UserDataExportStatus", created_at, completed_at, expires_at"#,
+        user_id
+    )
+    .fetch_one(db_client)
+    .await
+    .map_err(|error| {
+        error!(
+            "failed to insert new user data export record into database. {}",
+            error
+        );
+        internal_server_error()
+    })
+}
+
+#[tracing::instrument(skip(db_client, archive))]
+pub async fn complete_user_data_export(
+    db_client: impl PgExecutor<'_>,
+    export_id: &Uuid,
+    archive: serde_json::Value,
+    expires_at: OffsetDateTime,
+) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+    sqlx::query!(
+        r#"UPDATE user_data_export SET status = 'completed', archive = $2, completed_at = now(), expires_at = $3
+           WHERE id = $1"#,
+        export_id,
+        archive,
+        expires_at
+    )
+    .execute(db_client)
+    .await
+    .map_err(|error| {
+        error!("failed to complete user data export in database. {}", error);
+        internal_server_error()
+    })?;
+    Ok(())
+}
+
+// A failed export has nothing to keep, so it expires right away
+#[tracing::instrument(skip(db_client))]
+pub async fn fail_user_data_export(
+    db_client: impl PgExecutor<'_>,
+    export_id: &Uuid,
+) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+    sqlx::query!(
+        "UPDATE user_data_export SET status = 'failed', completed_at = now(), expires_at = now() WHERE id = $1",
+        export_id
+    )
+    .execute(db_client)
+    .await
+    .map_err(|error| {
+        error!("failed to mark user data export as failed in database. {}", error);
+        internal_server_error()
+    })?;
+    Ok(())
+}
+
+#[tracing::instrument(skip(db_client))]
+pub async fn get_user_data_export(
+    db_client: impl PgExecutor<'_>,
+    user_id: &Uuid,
+    export_id: &Uuid,
+) -> Result<Option<UserDataExport>, (StatusCode, Json<ErrorResponse>)> {
+    sqlx::query_as!(
+        UserDataExport,
+        r#"SELECT id, user_id, status AS "status: UserDataExportStatus", created_at, completed_at, expires_at
+           FROM user_data_export WHERE id = $1 AND user_id = $2"#,
+        export_id,
+        user_id
+    )
+    .fetch_optional(db_client)
+    .await
+    .map_err(|error| {
+        error!("failed to get user data export from database. {}", error);
+        internal_server_error()
+    })
+}
+
+// Latest export of the user that is still being built and was started after the given time
+#[tracing::instrument(skip(db_client))]
+pub async fn get_pending_user_data_export(
+    db_client: impl PgExecutor<'_>,
+    user_id: &Uuid,
+    created_after: OffsetDateTime,
+) -> Result<Option<UserDataExport>, (StatusCode, Json<ErrorResponse>)> {
+    sqlx::query_as!(
+        UserDataExport,
+        r#"SELECT id, user_id, status AS "status: UserDataExportStatus", created_at, completed_at, expires_at
+           FROM user_data_export WHERE user_id = $1 AND status = 'pending' AND created_at > $2
+           ORDER BY created_at DESC LIMIT 1"#,
+        user_id,
+        created_after
+    )
+    .fetch_optional(db_client)
+    .await
+    .map_err(|error| {
+        error!(
+            "failed to get pending user data export from database. {}",
+            error
+        );
+        internal_server_error()
+    })
+}
+
+#[tracing::instrument(skip(db_client))]
+pub async fn get_user_data_export_archive(
+    db_client: impl PgExecutor<'_>,
+    user_id: &Uuid,
+    export_id: &Uuid,
+) -> Result<Option<serde_json::Value>, (StatusCode, Json<ErrorResponse>)> {
+    sqlx::query_scalar!(
+        r#"SELECT archive AS "archive!" FROM user_data_export
+           WHERE id = $1 AND user_id = $2 AND status = 'completed' AND expires_at > now()"#,
+        export_id,
+        user_id
+    )
+    .fetch_optional(db_client)
+    .await
+    .map_err(|error| {
+        error!(
+            "failed to get user data export archive from database. {}",
+            error
+        );
+        internal_server_error()
+    })
+}
+
+#[tracing::instrument(skip(db_client))]
+pub async fn delete_user_data_exports(
+    db_client: impl PgExecutor<'_>,
+    user_id: &Uuid,
+) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+    sqlx::query!("DELETE FROM user_data_export WHERE user_id = $1", user_id)
+        .execute(db_client)
+        .await
+        .map_err(|error| {
+            error!(
+                "failed to delete user data exports from database. {}",
+                error
+            );
+            internal_server_error()
+        })?;
+    Ok(())
+}
+
+#[tracing::instrument(skip(db_client))]
+pub async fn delete_expired_user_data_exports(
+    db_client: impl PgExecutor<'_>,
+) -> Result<u64, (StatusCode, Json<ErrorResponse>)> {
+    sqlx::query!("DELETE FROM user_data_export WHERE expires_at <= now()")
+        .execute(db_client)
+        .await
+        .map(|result| result.rows_affected())
+        .map_err(|error| {
+            error!(
+                "failed to delete expired user data exports from database. {}",
+                error
+            );
+            internal_server_error()
+        })
+}
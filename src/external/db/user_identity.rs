@@ -0,0 +1,113 @@
+use super::internal_server_error;
+use super::models::UserIdentity;
+use crate::server::handlers::ErrorResponse;
+use axum::{http::StatusCode, Json};
+use sqlx::PgExecutor;
+use tracing::error;
+use uuid::Uuid;
+
+#[derive(Clone, Debug)]
+pub struct NewUserIdentity {
+    pub user_id: Uuid,
+    pub provider: String,
+    pub subject: String,
+    pub email: Option<String>,
+}
+
+#[tracing::instrument(skip(db_client))]
+pub async fn get_user_identity(
+    db_client: impl PgExecutor<'_>,
+    provider: &str,
+    subject: &str,
+) -> Result<Option<UserIdentity>, (StatusCode, Json<ErrorResponse>)> {
+    sqlx::query_as!(
+        UserIdentity,
+        "SELECT id, user_id, provider, subject, email, created_at
+         FROM user_identity WHERE provider = $1 AND subject = $2",
+        provider,
+        subject
+    )
+    .fetch_optional(db_client)
+    .await
+    .map_err(|error| {
+        error!("failed to get user identity from database. {}", error);
+        internal_server_error()
+    })
+}
+
+#[tracing::instrument(skip(db_client))]
+pub async fn list_user_identities(
+    db_client: impl PgExecutor<'_>,
+    user_id: &Uuid,
+) -> Result<Vec<UserIdentity>, (StatusCode, Json<ErrorResponse>)> {
+    sqlx::query_as!(
+        UserIdentity,
+        "SELECT id, user_id, provider, subject, email, created_at
+         FROM user_identity WHERE user_id = $1 ORDER BY created_at",
+        user_id
+    )
+    .fetch_all(db_client)
+    .await
+    .map_err(|error| {
+        error!("failed to list user identities from database. {}", error);
+        internal_server_error()
+    })
+}
+
+// Returns false if the account is already linked or the user already linked another account of the provider
+#[tracing::instrument(skip(db_client))]
+pub async fn insert_user_identity(
+    db_client: impl PgExecutor<'_>,
+    identity: &NewUserIdentity,
+) -> Result<bool, (StatusCode, Json<ErrorResponse>)> {
+    sqlx::query!(
+        "INSERT INTO user_identity (user_id, provider, subject, email) VALUES ($1, $2, $3, $4)
+         ON CONFLICT DO NOTHING",
+        identity.user_id,
+        identity.provider,
+        identity.subject,
+        identity.email
+    )
+    .execute(db_client)
+    .await
+    .map(|result| result.rows_affected() > 0)
+    .map_err(|error| {
+        error!("failed to insert user identity into database. {}", error);
+        internal_server_error()
+    })
+}
+
+#[tracing::instrument(skip(db_client))]
+pub async fn delete_user_identity(
+    db_client: impl PgExecutor<'_>,
+    user_id: &Uuid,
+    provider: &str,
+) -> Result<bool, (StatusCode, Json<ErrorResponse>)> {
+    sqlx::query!(
+        "DELETE FROM user_identity WHERE user_id = $1 AND provider = $2",
+        user_id,
+        provider
+    )
+    .execute(db_client)
+    .await
+    .map(|result| result.rows_affected() > 0)
+    .map_err(|error| {
+        error!("failed to delete user identity from database. {}", error);
+        internal_server_error()
+    })
+}
+
+#[tracing::instrument(skip(db_client))]
+pub async fn delete_user_identities(
+    db_client: impl PgExecutor<'_>,
+    user_id: &Uuid,
+) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+    sqlx::query!("DELETE FROM user_identity WHERE user_id = $1", user_id)
+        .execute(db_client)
+        .await
+        .map_err(|error| {
+            error!("failed to delete user identities from database. {}", error);
+            internal_server_error()
+        })?;
+    Ok(())
+}
@@ -0,0 +1,200 @@
+use super::internal_server_error;
+use super::models::{LoginFailureReason, UserLoginHistory};
+use crate::server::handlers::ErrorResponse;
+use axum::{http::StatusCode, Json};
+use sqlx::PgExecutor;
+use time::OffsetDateTime;
+use tracing::error;
+use uuid::Uuid;
+
+#[derive(Debug)]
+pub struct NewUserLoginHistory {
+    pub user_id: Uuid,
+    pub success: bool,
+    pub failure_reason: Option<LoginFailureReason>,
+    pub ip: String,
+    pub user_agent: Option<String>,
+    pub is_new_device: bool,
+}
+
+#[tracing::instrument(skip(db_client))]
+pub async fn get_login_locked_until(
+    db_client: impl PgExecutor<'_>,
+    user_id: &Uuid,
+) -> Result<Option<OffsetDateTime>, (StatusCode, Json<ErrorResponse>)> {
+    sqlx::query_scalar!(
+        "SELECT locked_until FROM user_login_lockout WHERE user_id = $1",
+        user_id
+    )
+    .fetch_optional(db_client)
+    .await
+    .map(Option::flatten)
+    .map_err(|error| {
+        error!("failed to get user login lockout from database. {}", error);
+        internal_server_error()
+    })
+}
+
+// Count starts over when the last failure happened before 'reset_before'
+#[tracing::instrument(skip(db_client))]
+pub async fn increment_failed_logins(
+    db_client: impl PgExecutor<'_>,
+    user_id: &Uuid,
+    reset_before: OffsetDateTime,
+) -> Result<i32, (StatusCode, Json<ErrorResponse>)> {
+    sqlx::query_scalar!(
+        r#"INSERT INTO user_login_lockout (user_id, failed_count) VALUES ($1, 1)
+           ON CONFLICT (user_id) DO UPDATE SET
+             failed_count = CASE WHEN user_login_lockout.last_failed_at < $2 THEN 1
+                                 ELSE user_login_lockout.failed_count + 1 END,
+             last_failed_at = now()
+           RETURNING failed_count"#,
+        user_id,
+        reset_before
+    )
+    .fetch_one(db_client)
+    .await
+    .map_err(|error| {
+        error!(
+            "failed to increment failed logins of user in database. {}",
+            error
+        );
+        internal_server_error()
+    })
+}
+
+// A concurrent failure may have locked the account for longer already, the later expiry wins
+#[tracing::instrument(skip(db_client))]
+pub async fn lock_login(
+    db_client: impl PgExecutor<'_>,
+    user_id: &Uuid,
+    locked_until: OffsetDateTime,
+) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+    sqlx::query!(
+        "UPDATE user_login_lockout SET locked_until = GREATEST(locked_until, $2) WHERE user_id = $1",
+        user_id,
+        locked_until
+    )
+    .execute(db_client)
+    .await
+    .map_err(|error| {
+        error!("failed to lock user login in database. {}", error);
+        internal_server_error()
+    })?;
+    Ok(())
+}
+
+#[tracing::instrument(skip(db_client))]
+pub async fn delete_login_lockout(
+    db_client: impl PgExecutor<'_>,
+    user_id: &Uuid,
+) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+    sqlx::query!("DELETE FROM user_login_lockout WHERE user_id = $1", user_id)
+        .execute(db_client)
+        .await
+        .map_err(|error| {
+            error!(
+                "failed to delete user login lockout from database. {}",
+                error
+            );
+            internal_server_error()
+        })?;
+    Ok(())
+}
+
+// The very first login has nothing to compare with and is never treated as a new device
+#[tracing::instrument(skip(db_client))]
+pub async fn is_new_device(
+    db_client: impl PgExecutor<'_>,
+    user_id: &Uuid,
+    ip: &str,
+    user_agent: Option<&str>,
+) -> Result<bool, (StatusCode, Json<ErrorResponse>)> {
+    sqlx::query_scalar!(
+        r#"SELECT EXISTS (SELECT 1 FROM user_login_history WHERE user_id = $1 AND success)
+             AND (NOT EXISTS (SELECT 1 FROM user_login_history WHERE user_id = $1 AND success AND ip = $2)
+                  OR NOT EXISTS (SELECT 1 FROM user_login_history
+                                 WHERE user_id = $1 AND success AND user_agent IS NOT DISTINCT FROM $3))
+           AS "is_new_device!""#,
+        user_id,
+        ip,
+        user_agent
+    )
+    .fetch_one(db_client)
+    .await
+    .map_err(|error| {
+        error!("failed to check login device of user in database. {}", error);
+        internal_server_error()
+    })
+}
+
+#[tracing::instrument(skip(db_client))]
+pub async fn insert_login_history(
+    db_client: impl PgExecutor<'_>,
+    login: NewUserLoginHistory,
+) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+    sqlx::query!(
+        r#"INSERT INTO user_login_history (user_id, success, failure_reason, ip, user_agent, is_new_device)
+           VALUES ($1, $2, $3, $4, $5, $6)"#,
+        login.user_id,
+        login.success,
+        login.failure_reason as Option<LoginFailureReason>,
+        login.ip,
+        login.user_agent,
+        login.is_new_device
+    )
+    .execute(db_client)
+    .await
+    .map_err(|error| {
+        error!(
+            "failed to insert new user login history record into database. {}",
+            error
+        );
+        internal_server_error()
+    })?;
+    Ok(())
+}
+
+#[tracing::instrument(skip(db_client))]
+pub async fn list_login_history(
+    db_client: impl PgExecutor<'_>,
+    user_id: &Uuid,
+    limit: i64,
+    offset: i64,
+) -> Result<Vec<UserLoginHistory>, (StatusCode, Json<ErrorResponse>)> {
+    sqlx::query_as!(
+        UserLoginHistory,
+        r#"SELECT id, user_id, success, failure_reason AS "failure_reason: LoginFailureReason",
+           ip, user_agent, is_new_device, created_at
+           FROM user_login_history WHERE user_id = $1
+           ORDER BY created_at DESC
+           LIMIT $2 OFFSET $3"#,
+        user_id,
+        limit,
+        offset
+    )
+    .fetch_all(db_client)
+    .await
+    .map_err(|error| {
+        error!("failed to list user login history from database. {}", error);
+        internal_server_error()
+    })
+}
+
+#[tracing::instrument(skip(db_client))]
+pub async fn delete_login_history(
+    db_client: impl PgExecutor<'_>,
+    user_id: &Uuid,
+) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+    sqlx::query!("DELETE FROM user_login_history WHERE user_id = $1", user_id)
+        .execute(db_client)
+        .await
+        .map_err(|error| {
+            error!(
+                "failed to delete user login history from database. {}",
+                error
+            );
+            internal_server_error()
+        })?;
+    Ok(())
+}
@@ -0,0 +1,189 @@
+use super::internal_server_error;
+use super::models::UserTwoFactor;
+use crate::logger::Secret;
+use crate::server::handlers::ErrorResponse;
+use axum::{http::StatusCode, Json};
+use sqlx::PgExecutor;
+use tracing::error;
+use uuid::Uuid;
+
+#[tracing::instrument(skip(db_client))]
+pub async fn get_user_two_factor(
+    db_client: impl PgExecutor<'_>,
+    user_id: &Uuid,
+) -> Result<Option<UserTwoFactor>, (StatusCode, Json<ErrorResponse>)> {
+    sqlx::query_as!(
+        UserTwoFactor,
+        r#"SELECT user_id, secret AS "secret: Secret<String>", enabled, last_used_step
+           FROM user_two_factor WHERE user_id = $1"#,
+        user_id
+    )
+    .fetch_optional(db_client)
+    .await
+    .map_err(|error| {
+        error!("failed to get user two factor from database. {}", error);
+        internal_server_error()
+    })
+}
+
+// Starting the enrollment again replaces a pending secret but never an enabled one
+#[tracing::instrument(skip(db_client))]
+pub async fn upsert_pending_user_two_factor(
+    db_client: impl PgExecutor<'_>,
+    user_id: &Uuid,
+    secret: &Secret<String>,
+) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+    sqlx::query!(
+        r#"INSERT INTO user_two_factor (user_id, secret) VALUES ($1, $2)
+           ON CONFLICT (user_id) DO UPDATE SET secret = EXCLUDED.secret, created_at = now()
+           WHERE user_two_factor.enabled = FALSE"#,
+        user_id,
+        secret.expose()
+    )
+    .execute(db_client)
+    .await
+    .map_err(|error| {
+        error!(
+            "failed to upsert pending user two factor into database. {}",
+            error
+        );
+        internal_server_error()
+    })?;
+    Ok(())
+}
+
+// Returns false if two-factor authentication was already enabled by a concurrent request
+#[tracing::instrument(skip(db_client))]
+pub async fn enable_user_two_factor(
+    db_client: impl PgExecutor<'_>,
+    user_id: &Uuid,
+    step: i64,
+) -> Result<bool, (StatusCode, Json<ErrorResponse>)> {
+    sqlx::query!(
+        r#"UPDATE user_two_factor SET enabled = TRUE, enabled_at = now(), last_used_step = $2
+           WHERE user_id = $1 AND enabled = FALSE"#,
+        user_id,
+        step
+    )
+    .execute(db_client)
+    .await
+    .map(|result| result.rows_affected() == 1)
+    .map_err(|error| {
+        error!("failed to enable user two factor in database. {}", error);
+        internal_server_error()
+    })
+}
+
+// Returns false if a code of the same or a later time step was already accepted, i.e. the code is replayed
+#[tracing::instrument(skip(db_client))]
+pub async fn update_last_used_step(
+    db_client: impl PgExecutor<'_>,
+    user_id: &Uuid,
+    step: i64,
+) -> Result<bool, (StatusCode, Json<ErrorResponse>)> {
+    sqlx::query!(
+        r#"UPDATE user_two_factor SET last_used_step = $2
+           WHERE user_id = $1 AND (last_used_step IS NULL OR last_used_step < $2)"#,
+        user_id,
+        step
+    )
+    .execute(db_client)
+    .await
+    .map(|result| result.rows_affected() == 1)
+    .map_err(|error| {
+        error!(
+            "failed to update user two factor last used step in database. {}",
+            error
+        );
+        internal_server_error()
+    })
+}
+
+#[tracing::instrument(skip(db_client))]
+pub async fn delete_user_two_factor(
+    db_client: impl PgExecutor<'_>,
+    user_id: &Uuid,
+) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+    sqlx::query!("DELETE FROM user_two_factor WHERE user_id = $1", user_id)
+        .execute(db_client)
+        .await
+        .map_err(|error| {
+            error!("failed to delete user two factor from database. {}", error);
+            internal_server_error()
+        })?;
+    Ok(())
+}
+
+#[tracing::instrument(skip(db_client, code_hashes))]
+pub async fn insert_recovery_codes(
+    db_client: impl PgExecutor<'_>,
+    user_id: &Uuid,
+    code_hashes: &[String],
+) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+    sqlx::query!(
+        "INSERT INTO user_recovery_code (user_id, code_hash) SELECT $1, unnest($2::TEXT[])",
+        user_id,
+        code_hashes
+    )
+    .execute(db_client)
+    .await
+    .map_err(|error| {
+        error!("failed to insert recovery codes into database. {}", error);
+        internal_server_error()
+    })?;
+    Ok(())
+}
+
+#[tracing::instrument(skip(db_client))]
+pub async fn delete_recovery_codes(
+    db_client: impl PgExecutor<'_>,
+    user_id: &Uuid,
+) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+    sqlx::query!("DELETE FROM user_recovery_code WHERE user_id = $1", user_id)
+        .execute(db_client)
+        .await
+        .map_err(|error| {
+            error!("failed to delete recovery codes from database. {}", error);
+            internal_server_error()
+        })?;
+    Ok(())
+}
+
+// Each recovery code can only be used once, returns false if there is no unused code with the hash
+#[tracing::instrument(skip(db_client, code_hash))]
+pub async fn use_recovery_code(
+    db_client: impl PgExecutor<'_>,
+    user_id: &Uuid,
+    code_hash: &str,
+) -> Result<bool, (StatusCode, Json<ErrorResponse>)> {
+    sqlx::query!(
+        r#"UPDATE user_recovery_code SET used_at = now()
+           WHERE user_id = $1 AND code_hash = $2 AND used_at IS NULL"#,
+        user_id,
+        code_hash
+    )
+    .execute(db_client)
+    .await
+    .map(|result| result.rows_affected() > 0)
+    .map_err(|error| {
+        error!("failed to use recovery code in database. {}", error);
+        internal_server_error()
+    })
+}
+
+#[tracing::instrument(skip(db_client))]
+pub async fn count_unused_recovery_codes(
+    db_client: impl PgExecutor<'_>,
+    user_id: &Uuid,
+) -> Result<i64, (StatusCode, Json<ErrorResponse>)> {
+    sqlx::query_scalar!(
+        r#"SELECT COUNT(*) AS "count!" FROM user_recovery_code WHERE user_id = $1 AND used_at IS NULL"#,
+        user_id
+    )
+    .fetch_one(db_client)
+    .await
+    .map_err(|error| {
+        error!("failed to count recovery codes in database. {}", error);
+        internal_server_error()
+    })
+}
@@ -1,51 +1,57 @@
+use super::internal_server_error;
 use crate::server::handlers::ErrorResponse;
 use axum::{http::StatusCode, Json};
-use sqlx::{Pool, Postgres};
+use sqlx::PgExecutor;
+use time::OffsetDateTime;
 use tracing::error;
 use uuid::Uuid;
 
 #[derive(Debug)]
 pub struct NewUserVerification {
     pub user_id: Uuid,
-    pub secret: String,
+    pub token_hash: String,
+    pub expires_at: OffsetDateTime,
 }
 
-#[tracing::instrument]
-pub async fn get_user_verification_secret(
-    db_client: &Pool<Postgres>,
-    user_id: &Uuid,
-) -> Result<String, (StatusCode, Json<ErrorResponse>)> {
-    let user_verification_secret = sqlx::query_scalar!(
-        "SELECT secret FROM user_verification WHERE user_id = $1",
-        user_id
+#[derive(Debug)]
+pub struct UserVerification {
+    pub user_id: Uuid,
+    pub expires_at: OffsetDateTime,
+}
+
+// A token is removed as soon as it is presented, so it can be used once even if it turns out to be expired
+#[tracing::instrument(skip(db_client))]
+pub async fn delete_user_verification(
+    db_client: impl PgExecutor<'_>,
+    token_hash: &str,
+) -> Result<Option<UserVerification>, (StatusCode, Json<ErrorResponse>)> {
+    sqlx::query_as!(
+        UserVerification,
+        r#"DELETE FROM user_verification WHERE token_hash = $1
+           RETURNING user_id AS "user_id!", expires_at"#,
+        token_hash
     )
-    .fetch_one(db_client)
+    .fetch_optional(db_client)
     .await
     .map_err(|error| {
         error!(
-            "failed to get user verification secret from database. {}",
+            "failed to delete user verification from database. {}",
             error
         );
-        return (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(ErrorResponse {
-                success: false,
-                error: format!("Internal server error."),
-            }),
-        );
-    })?;
-    Ok(user_verification_secret)
+        internal_server_error()
+    })
 }
 
-#[tracing::instrument]
+#[tracing::instrument(skip(db_client))]
 pub async fn insert_new_user_verification(
-    db_client: &Pool<Postgres>,
+    db_client: impl PgExecutor<'_>,
     user_verification: NewUserVerification,
 ) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
     sqlx::query!(
-        "INSERT INTO user_verification (user_id, secret) VALUES ($1, $2)",
+        "INSERT INTO user_verification (user_id, token_hash, expires_at) VALUES ($1, $2, $3)",
         user_verification.user_id,
-        user_verification.secret
+        user_verification.token_hash,
+        user_verification.expires_at
     )
     .execute(db_client)
     .await
@@ -54,13 +60,25 @@ pub async fn insert_new_user_verification(
             "failed to insert new user verification record into database. {}",
             error
         );
-        return (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(ErrorResponse {
-                success: false,
-                error: format!("Internal server error."),
-            }),
-        );
+        internal_server_error()
     })?;
     Ok(())
 }
+
+#[tracing::instrument(skip(db_client))]
+pub async fn delete_user_verifications(
+    db_client: impl PgExecutor<'_>,
+    user_id: &Uuid,
+) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+    sqlx::query!("DELETE FROM user_verification WHERE user_id = $1", user_id)
+        .execute(db_client)
+        .await
+        .map_err(|error| {
+            error!(
+                "failed to delete user verifications from database. {}",
+                error
+            );
+            internal_server_error()
+        })?;
+    Ok(())
+}
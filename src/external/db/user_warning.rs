@@ -0,0 +1,57 @@
+use super::internal_server_error;
+use super::models::ExportedWarning;
+use crate::server::handlers::ErrorResponse;
+use axum::{http::StatusCode, Json};
+use sqlx::PgExecutor;
+use tracing::error;
+use uuid::Uuid;
+
+#[derive(Debug)]
+pub struct NewUserWarning {
+    pub user_id: Uuid,
+    pub moderator_id: Uuid,
+    pub report_id: Option<Uuid>,
+    pub reason: String,
+}
+
+#[tracing::instrument(skip(db_client))]
+pub async fn insert_new_user_warning(
+    db_client: impl PgExecutor<'_>,
+    user_warning: NewUserWarning,
+) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+    sqlx::query!(
+        "INSERT INTO user_warning (user_id, moderator_id, report_id, reason) VALUES ($1, $2, $3, $4)",
+        user_warning.user_id,
+        user_warning.moderator_id,
+        user_warning.report_id,
+        user_warning.reason
+    )
+    .execute(db_client)
+    .await
+    .map_err(|error| {
+        error!(
+            "failed to insert new user warning record into database. {}",
+            error
+        );
+        internal_server_error()
+    })?;
+    Ok(())
+}
+
+#[tracing::instrument(skip(db_client))]
+pub async fn list_user_warnings(
+    db_client: impl PgExecutor<'_>,
+    user_id: &Uuid,
+) -> Result<Vec<ExportedWarning>, (StatusCode, Json<ErrorResponse>)> {
+    sqlx::query_as!(
+        ExportedWarning,
+        "SELECT id, reason, created_at FROM user_warning WHERE user_id = $1 ORDER BY created_at DESC",
+        user_id
+    )
+    .fetch_all(db_client)
+    .await
+    .map_err(|error| {
+        error!("failed to list user warnings from database. {}", error);
+        internal_server_error()
+    })
+}
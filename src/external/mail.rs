@@ -0,0 +1,134 @@
+use dotenvy::var;
+use lettre::message::Mailbox;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex};
+use tracing::{error, info};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Mail {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+// Sending never blocks the request, delivery failures are only logged
+pub trait Mailer: Debug + Send + Sync {
+    fn send(&self, mail: Mail);
+}
+
+// Writes mails to the log instead of delivering them, for development without a mail server
+#[derive(Debug, Default)]
+pub struct LogMailer;
+
+impl Mailer for LogMailer {
+    fn send(&self, mail: Mail) {
+        info!(
+            "mail '{}' to {} is not delivered without smtp transport. {}",
+            mail.subject, mail.to, mail.body
+        );
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SmtpMailer {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+}
+
+impl SmtpMailer {
+    // The url looks like 'smtps://<user>:<password>@<host>:465', see lettre's 'from_url' for all options
+    pub fn new(url: &str, from: Mailbox) -> Result<Self, anyhow::Error> {
+        let transport = AsyncSmtpTransport::<Tokio1Executor>::from_url(url)?.build();
+        Ok(Self { transport, from })
+    }
+}
+
+impl Mailer for SmtpMailer {
+    fn send(&self, mail: Mail) {
+        let message = mail
+            .to
+            .parse::<Mailbox>()
+            .map_err(anyhow::Error::from)
+            .and_then(|to| {
+                Message::builder()
+                    .from(self.from.clone())
+                    .to(to)
+                    .subject(&mail.subject)
+                    .body(mail.body)
+                    .map_err(anyhow::Error::from)
+            });
+        let message = match message {
+            Ok(message) => message,
+            Err(error) => {
+                error!("failed to build mail '{}'. {}", mail.subject, error);
+                return;
+            }
+        };
+
+        let transport = self.transport.clone();
+        tokio::spawn(async move {
+            if let Err(error) = transport.send(message).await {
+                error!("failed to deliver mail '{}'. {}", mail.subject, error);
+            }
+        });
+    }
+}
+
+// Keeps every mail in process memory, handlers are tested against it without a mail server
+#[derive(Debug, Default)]
+pub struct MemoryMailer {
+    sent: Mutex<Vec<Mail>>,
+}
+
+impl MemoryMailer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn sent(&self) -> Vec<Mail> {
+        self.sent
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clone()
+    }
+}
+
+impl Mailer for MemoryMailer {
+    fn send(&self, mail: Mail) {
+        self.sent
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .push(mail);
+    }
+}
+
+// Build the mailer from 'MAIL_TRANSPORT' (log or smtp) and related environment variables
+pub fn from_env() -> Result<Arc<dyn Mailer>, anyhow::Error> {
+    match var("MAIL_TRANSPORT").as_deref() {
+        Ok("smtp") => {
+            let url = var("SMTP_URL").map_err(|e| {
+                anyhow::anyhow!("Missing config for environment variable SMTP_URL. {}", e)
+            })?;
+            let from = var("MAIL_FROM")
+                .ok()
+                .and_then(|from| from.parse::<Mailbox>().ok())
+                .ok_or_else(|| {
+                    anyhow::anyhow!("Invalid config for environment variable MAIL_FROM. Expected a mail address like 'chat-rs <no-reply@example.com>'.")
+                })?;
+            let mailer = SmtpMailer::new(&url, from).map_err(|e| {
+                anyhow::anyhow!("Invalid config for environment variable SMTP_URL. {}.", e)
+            })?;
+            info!("mailer is using smtp transport");
+            Ok(Arc::new(mailer))
+        }
+        Ok("log") | Err(_) => {
+            info!("mailer is using log transport");
+            Ok(Arc::new(LogMailer))
+        }
+        Ok(other) => Err(anyhow::anyhow!(
+            "Invalid config for environment variable MAIL_TRANSPORT. Unknown transport {}.",
+            other
+        )),
+    }
+}
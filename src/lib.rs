@@ -0,0 +1,6 @@
+pub mod config;
+pub mod external;
+pub mod logger;
+pub mod server;
+
+use external::db;
@@ -0,0 +1,556 @@
+mod redaction;
+mod rolling;
+
+use crate::config::{invalid_config, parse_env};
+use crate::server::metrics::DbQueryMetricsLayer;
+use dotenvy::var;
+use opentelemetry::trace::TraceError;
+use opentelemetry::KeyValue;
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::Tracer;
+use opentelemetry_sdk::Resource;
+pub use redaction::{Redaction, Secret, REDACTED};
+pub use rolling::SizeRollingWriter;
+use std::collections::{BTreeMap, HashMap};
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+use tracing::field::Field;
+use tracing::{span, Event, Metadata, Subscriber};
+use tracing_appender::non_blocking::{NonBlockingBuilder, WorkerGuard};
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::field::Visit;
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::prelude::__tracing_subscriber_SubscriberExt;
+use tracing_subscriber::registry::{LookupSpan, Scope};
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{EnvFilter, Layer};
+
+// axum logs rejections from built-in extractors are at TRACE level
+const DEFAULT_LOG_FILTER: &str = "chat_rs=debug,axum::rejection=trace,tower_http=debug";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LogFormat {
+    // One JSON object per line, for log shippers
+    Json,
+    // Indented multi-line JSON
+    Pretty,
+    // Human-readable single line for local development
+    Compact,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum FileRotation {
+    Daily,
+    Hourly,
+    Size(u64),
+    Never,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum LogOutput {
+    Stdout,
+    File {
+        directory: PathBuf,
+        file_name: String,
+        rotation: FileRotation,
+        // Number of rotated files to keep, older ones are removed
+        max_files: usize,
+    },
+}
+
+#[derive(Clone, Debug)]
+pub struct LogConfig {
+    pub format: LogFormat,
+    pub output: LogOutput,
+    // Directives like 'tower_http=info,sqlx=warn' applied on top of 'RUST_LOG'
+    pub target_levels: Vec<String>,
+    pub redaction: Redaction,
+}
+
+impl LogConfig {
+    pub fn from_env() -> Result<Self, anyhow::Error> {
+        let format = match var("LOG_FORMAT").as_deref() {
+            Ok("json") | Err(_) => LogFormat::Json,
+            Ok("pretty") => LogFormat::Pretty,
+            Ok("compact") => LogFormat::Compact,
+            Ok(_) => {
+                return Err(invalid_config(
+                    "LOG_FORMAT",
+                    "'json', 'pretty' or 'compact'",
+                ))
+            }
+        };
+
+        let output = match var("LOG_OUTPUT").as_deref() {
+            Ok("stdout") | Err(_) => LogOutput::Stdout,
+            Ok("file") => {
+                let rotation = match var("LOG_FILE_ROTATION").as_deref() {
+                    Ok("daily") | Err(_) => FileRotation::Daily,
+                    Ok("hourly") => FileRotation::Hourly,
+                    Ok("never") => FileRotation::Never,
+                    Ok("size") => FileRotation::Size(
+                        parse_env::<u64>("LOG_FILE_MAX_SIZE_MB", 100, "a number of megabytes")?
+                            * 1024
+                            * 1024,
+                    ),
+                    Ok(_) => {
+                        return Err(invalid_config(
+                            "LOG_FILE_ROTATION",
+                            "'daily', 'hourly', 'size' or 'never'",
+                        ))
+                    }
+                };
+                LogOutput::File {
+                    directory: var("LOG_FILE_DIR")
+                        .unwrap_or_else(|_| "logs".to_string())
+                        .into(),
+                    file_name: var("LOG_FILE_NAME").unwrap_or_else(|_| "chat-rs.log".to_string()),
+                    rotation,
+                    max_files: parse_env("LOG_FILE_MAX_FILES", 7, "a number of files")?,
+                }
+            }
+            Ok(_) => return Err(invalid_config("LOG_OUTPUT", "'stdout' or 'file'")),
+        };
+
+        let target_levels = var("LOG_LEVELS")
+            .map(|value| {
+                value
+                    .split(',')
+                    .map(|directive| directive.trim().to_string())
+                    .filter(|directive| !directive.is_empty())
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        // Field names redacted in addition to the built-in ones, e.g. 'api_key,phone'
+        let redacted_fields: Vec<String> = var("LOG_REDACT_FIELDS")
+            .map(|value| {
+                value
+                    .split(',')
+                    .map(|field| field.trim().to_string())
+                    .filter(|field| !field.is_empty())
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Ok(Self {
+            format,
+            output,
+            target_levels,
+            redaction: Redaction::new(redacted_fields),
+        })
+    }
+
+    fn env_filter(&self) -> Result<EnvFilter, anyhow::Error> {
+        let filter =
+            EnvFilter::try_from_default_env().unwrap_or_else(|_| DEFAULT_LOG_FILTER.into());
+        self.target_levels
+            .iter()
+            .try_fold(filter, |filter, directive| match directive.parse() {
+                Ok(directive) => Ok(filter.add_directive(directive)),
+                Err(_) => Err(invalid_config(
+                    "LOG_LEVELS",
+                    "'<target>=<level>' separated by commas",
+                )),
+            })
+    }
+
+    // Lines are written by a background thread so logging never waits on stdout or the disk
+    fn writer(
+        &self,
+    ) -> Result<(tracing_appender::non_blocking::NonBlocking, WorkerGuard), anyhow::Error> {
+        // Block rather than drop lines in the rare case the buffer is full
+        let builder = NonBlockingBuilder::default().lossy(false);
+        match &self.output {
+            LogOutput::Stdout => Ok(builder.finish(std::io::stdout())),
+            LogOutput::File {
+                directory,
+                file_name,
+                rotation: FileRotation::Size(max_size),
+                max_files,
+            } => SizeRollingWriter::new(directory, file_name, *max_size, *max_files)
+                .map(|writer| builder.finish(writer))
+                .map_err(|e| anyhow::anyhow!("Cannot open log file. {}", e)),
+            LogOutput::File {
+                directory,
+                file_name,
+                rotation,
+                max_files,
+            } => {
+                let rotation = match rotation {
+                    FileRotation::Hourly => Rotation::HOURLY,
+                    FileRotation::Never => Rotation::NEVER,
+                    _ => Rotation::DAILY,
+                };
+                // Old files are pruned on startup, which fails if the directory doesn't exist yet
+                std::fs::create_dir_all(directory)
+                    .map_err(|e| anyhow::anyhow!("Cannot create log directory. {}", e))?;
+                // The active file is kept in addition to the rotated ones
+                RollingFileAppender::builder()
+                    .rotation(rotation)
+                    .filename_prefix(file_name)
+                    .max_log_files(max_files + 1)
+                    .build(directory)
+                    .map(|writer| builder.finish(writer))
+                    .map_err(|e| anyhow::anyhow!("Cannot open log file. {}", e))
+            }
+        }
+    }
+}
+
+pub struct CustomLayer<W> {
+    format: LogFormat,
+    make_writer: W,
+    redaction: Redaction,
+}
+
+impl<W> CustomLayer<W>
+where
+    W: for<'writer> MakeWriter<'writer> + 'static,
+{
+    pub fn new(format: LogFormat, make_writer: W, redaction: Redaction) -> Self {
+        Self {
+            format,
+            make_writer,
+            redaction,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+struct JsonStorage<'a> {
+    storage: HashMap<&'a str, serde_json::Value>,
+}
+
+impl<'a> JsonStorage<'a> {
+    pub fn get_storage(&self) -> &HashMap<&'a str, serde_json::Value> {
+        &self.storage
+    }
+}
+
+// Logs are flushed when the returned guard is dropped, so it must be kept until the process exits
+pub fn init() -> Result<WorkerGuard, anyhow::Error> {
+    let config = LogConfig::from_env()?;
+    let (writer, guard) = config.writer()?;
+
+    // Spans are only exported when an OpenTelemetry collector endpoint is configured
+    let otlp_layer = match var("OTEL_EXPORTER_OTLP_ENDPOINT") {
+        Ok(endpoint) if !endpoint.is_empty() => {
+            let service_name = var("OTEL_SERVICE_NAME").unwrap_or_else(|_| "chat-rs".to_string());
+            let tracer = otlp_tracer(&endpoint, &service_name)
+                .map_err(|e| anyhow::anyhow!("Cannot initiate OpenTelemetry exporter. {}", e))?;
+            Some(
+                tracing_opentelemetry::layer()
+                    .with_tracer(tracer)
+                    .with_filter(config.env_filter()?),
+            )
+        }
+        _ => None,
+    };
+
+    // Filters are set per layer, the log level must not decide which queries are timed
+    tracing_subscriber::registry()
+        .with(
+            CustomLayer::new(config.format, writer, config.redaction.clone())
+                .with_filter(config.env_filter()?),
+        )
+        .with(DbQueryMetricsLayer::filtered())
+        .with(otlp_layer)
+        .init();
+    Ok(guard)
+}
+
+// Export spans in batches to the collector over OTLP/HTTP, endpoint is the collector base url
+pub fn otlp_tracer(endpoint: &str, service_name: &str) -> Result<Tracer, TraceError> {
+    // Incoming 'traceparent' headers are picked up through the global propagator
+    opentelemetry::global::set_text_map_propagator(TraceContextPropagator::new());
+    opentelemetry_otlp::new_pipeline()
+        .tracing()
+        .with_exporter(
+            opentelemetry_otlp::new_exporter()
+                .http()
+                .with_endpoint(format!("{}/v1/traces", endpoint.trim_end_matches('/'))),
+        )
+        .with_trace_config(
+            opentelemetry_sdk::trace::config().with_resource(Resource::new(vec![KeyValue::new(
+                "service.name",
+                service_name.to_string(),
+            )])),
+        )
+        .install_batch(opentelemetry_sdk::runtime::Tokio)
+}
+
+// Flush spans that are still buffered, this blocks until the exporter is done
+pub fn shutdown() {
+    opentelemetry::global::shutdown_tracer_provider();
+}
+
+// Time between creating and closing a span, and the part of it spent inside the span
+// For async functions the difference is the time spent waiting on other tasks or I/O
+struct SpanTiming {
+    created_at: Instant,
+    entered_at: Option<Instant>,
+    busy: Duration,
+}
+
+fn milliseconds(duration: Duration) -> serde_json::Value {
+    serde_json::json!((duration.as_secs_f64() * 1_000_000.0).round() / 1_000.0)
+}
+
+impl<W> CustomLayer<W>
+where
+    W: for<'writer> MakeWriter<'writer> + 'static,
+{
+    fn new_output(metadata: &Metadata<'_>) -> HashMap<&'static str, serde_json::Value> {
+        let mut output: HashMap<&str, serde_json::Value> = HashMap::new();
+        // Log the event time
+        output.insert(
+            "timestamp",
+            serde_json::Value::String(OffsetDateTime::now_utc().format(&Rfc3339).unwrap()),
+        );
+        // Log the log message level
+        output.insert(
+            "level",
+            serde_json::Value::String(metadata.level().to_string().to_lowercase()),
+        );
+        // Log the location / module where the span / event happens
+        output.insert(
+            "target",
+            serde_json::Value::String(metadata.target().to_string()),
+        );
+        output
+    }
+
+    // Log the innermost function name, the chain of spans from the root and the fields recorded on them
+    fn insert_span_fields<'a, S>(
+        output: &mut HashMap<&'a str, serde_json::Value>,
+        scope: Option<Scope<'_, S>>,
+    ) where
+        S: for<'lookup> LookupSpan<'lookup>,
+    {
+        let Some(scope) = scope else {
+            return;
+        };
+        let spans: Vec<_> = scope.from_root().collect();
+        let Some(span) = spans.last() else {
+            return;
+        };
+        output.insert(
+            "function",
+            serde_json::Value::String(span.name().to_string()),
+        );
+        output.insert(
+            "spans",
+            serde_json::json!(spans.iter().map(|span| span.name()).collect::<Vec<_>>()),
+        );
+
+        // Log the function parameters if there are some
+        let mut parameters: HashMap<&str, serde_json::Value> = HashMap::new();
+        let extensions = span.extensions();
+        // Get the data from extensions that store in on_new_span and on_record steps
+        if let Some(visitor) = extensions.get::<JsonStorage>() {
+            for (key, value) in visitor.get_storage() {
+                match key.to_owned() {
+                    "method" | "request_id" | "trace_id" | "status" | "error" => {
+                        output.insert(key, value.clone())
+                    }
+                    "uri" => output.insert("path", value.clone()),
+                    "version" => None,
+                    _ => parameters.insert(key, value.clone()),
+                };
+            }
+        }
+        if !parameters.is_empty() {
+            output.insert("params", serde_json::json!(parameters));
+        }
+    }
+
+    fn write(&self, mut output: HashMap<&str, serde_json::Value>) {
+        // Secrets are removed last so that nothing added above can bypass the redaction
+        output
+            .iter_mut()
+            .for_each(|(key, value)| self.redaction.redact(key, value));
+
+        let mut line = match self.format {
+            LogFormat::Json => serde_json::to_string(&serde_json::json!(output)).unwrap(),
+            LogFormat::Pretty => serde_json::to_string_pretty(&serde_json::json!(output)).unwrap(),
+            LogFormat::Compact => format_compact(output),
+        };
+        line.push('\n');
+        // There is nowhere left to report a failed log write
+        let _ = self.make_writer.make_writer().write_all(line.as_bytes());
+    }
+}
+
+impl<S, W> Layer<S> for CustomLayer<W>
+where
+    S: Subscriber + for<'lookup> LookupSpan<'lookup>,
+    W: for<'writer> MakeWriter<'writer> + 'static,
+{
+    // We want to record all the key-value pairs of function parameters if there are any
+    fn on_new_span(&self, attrs: &span::Attributes<'_>, id: &span::Id, ctx: Context<'_, S>) {
+        let span = ctx.span(id).expect("Span not found.");
+        let mut extensions = span.extensions_mut();
+
+        // Inherit fields from parent span if there is one
+        let mut visitor = if let Some(parent_span) = span.parent() {
+            // Extensions can be used for storing additional data to a span
+            let mut extensions = parent_span.extensions_mut();
+            extensions
+                .get_mut::<JsonStorage>()
+                .map(|value| value.to_owned())
+                .unwrap_or_default()
+        } else {
+            JsonStorage::default()
+        };
+
+        // Record all the fields of current span
+        attrs.values().record(&mut visitor);
+        // Insert the storage into current span extensions
+        extensions.insert(visitor);
+        extensions.insert(SpanTiming {
+            created_at: Instant::now(),
+            entered_at: None,
+            busy: Duration::ZERO,
+        });
+    }
+
+    // Fields declared as empty are recorded after the span is created, e.g. the request id or an error
+    fn on_record(&self, id: &span::Id, values: &span::Record<'_>, ctx: Context<'_, S>) {
+        let span = ctx.span(id).expect("Span not found.");
+        let mut extensions = span.extensions_mut();
+        if let Some(visitor) = extensions.get_mut::<JsonStorage>() {
+            values.record(visitor);
+        }
+    }
+
+    fn on_enter(&self, id: &span::Id, ctx: Context<'_, S>) {
+        let span = ctx.span(id).expect("Span not found.");
+        let mut extensions = span.extensions_mut();
+        if let Some(timing) = extensions.get_mut::<SpanTiming>() {
+            // A span entered again while already entered keeps counting from the outer enter
+            timing.entered_at.get_or_insert_with(Instant::now);
+        }
+    }
+
+    fn on_exit(&self, id: &span::Id, ctx: Context<'_, S>) {
+        let span = ctx.span(id).expect("Span not found.");
+        let mut extensions = span.extensions_mut();
+        if let Some(timing) = extensions.get_mut::<SpanTiming>() {
+            if let Some(entered_at) = timing.entered_at.take() {
+                timing.busy += entered_at.elapsed();
+            }
+        }
+    }
+
+    // Every span logs how long it took once it is closed
+    fn on_close(&self, id: span::Id, ctx: Context<'_, S>) {
+        let span = ctx.span(&id).expect("Span not found.");
+        let mut output = Self::new_output(span.metadata());
+        output.insert(
+            "message",
+            serde_json::Value::String("span closed".to_string()),
+        );
+        if let Some(timing) = span.extensions().get::<SpanTiming>() {
+            output.insert("elapsed_ms", milliseconds(timing.created_at.elapsed()));
+            output.insert("busy_ms", milliseconds(timing.busy));
+        }
+        Self::insert_span_fields(&mut output, Some(span.scope()));
+        self.write(output);
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        // Record all the fields of current event
+        let mut event_visitor = JsonStorage::default();
+        event.record(&mut event_visitor);
+
+        // Initialize the HashMap that will store all the fields that construct the log message
+        let mut output = Self::new_output(event.metadata());
+        Self::insert_span_fields(&mut output, ctx.event_scope(event));
+        // Log the custom message we typed, event fields take precedence over span fields
+        event_visitor.get_storage().iter().for_each(|(key, value)| {
+            output.insert(key, value.clone());
+        });
+
+        self.write(output);
+    }
+}
+
+// e.g. '2023-08-01T10:00:00Z  INFO chat_rs::server::handlers request:login_handler: received request method=POST ...'
+fn format_compact(mut output: HashMap<&str, serde_json::Value>) -> String {
+    let mut take = |key: &str| match output.remove(key) {
+        Some(serde_json::Value::String(value)) => value,
+        Some(value) => value.to_string(),
+        None => String::new(),
+    };
+    let timestamp = take("timestamp");
+    let level = take("level").to_uppercase();
+    let target = take("target");
+    let function = take("function");
+    let message = take("message");
+    // The chain of spans replaces the innermost function name, e.g. 'request:login_handler'
+    let spans = match output.remove("spans") {
+        Some(serde_json::Value::Array(spans)) => spans
+            .iter()
+            .filter_map(|span| span.as_str())
+            .collect::<Vec<_>>()
+            .join(":"),
+        _ => function,
+    };
+
+    let mut line = format!("{} {:>5} {}", timestamp, level, target);
+    if !spans.is_empty() {
+        line.push(' ');
+        line.push_str(&spans);
+    }
+    line.push_str(": ");
+    line.push_str(&message);
+
+    // Sort the remaining fields so that lines are easy to scan
+    let fields: BTreeMap<_, _> = output.into_iter().collect();
+    for (key, value) in fields {
+        let value = match value {
+            serde_json::Value::String(value) => value,
+            value => value.to_string(),
+        };
+        line.push_str(&format!(" {}={}", key, value));
+    }
+    line
+}
+
+impl<'a> Visit for JsonStorage<'a> {
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.storage.insert(field.name(), serde_json::json!(value));
+    }
+
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.storage.insert(field.name(), serde_json::json!(value));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.storage.insert(field.name(), serde_json::json!(value));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.storage.insert(field.name(), serde_json::json!(value));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.storage.insert(field.name(), serde_json::json!(value));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.storage
+            .insert(field.name(), serde_json::json!(format!("{:?}", value)));
+    }
+
+    fn record_error(&mut self, field: &Field, value: &(dyn std::error::Error + 'static)) {
+        self.storage
+            .insert(field.name(), serde_json::json!(value.to_string()));
+    }
+}
@@ -0,0 +1,170 @@
+use serde::{Deserialize, Serialize, Serializer};
+use std::fmt;
+
+pub const REDACTED: &str = "***";
+
+// Fields named like these, or ending with '_<name>' such as 'verification_secret', are never logged
+const DEFAULT_REDACTED_FIELDS: &[&str] =
+    &["password", "secret", "token", "authorization", "cookie"];
+
+// Only matched by the whole name, 'status_code' or 'error_code' are safe to log
+// 'code' covers authorization codes in the OpenID Connect callback uri and two-factor codes
+const EXACT_REDACTED_FIELDS: &[&str] = &["code"];
+
+// Wraps a value that must never be logged, Debug and Display print '***' instead of the value
+#[derive(Clone, Default, PartialEq, Eq, Deserialize, sqlx::Type)]
+#[serde(transparent)]
+#[sqlx(transparent)]
+pub struct Secret<T>(T);
+
+impl<T> Secret<T> {
+    pub fn new(value: T) -> Self {
+        Self(value)
+    }
+
+    // Access to the value is explicit so that it is easy to audit where secrets are used
+    pub fn expose(&self) -> &T {
+        &self.0
+    }
+
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> From<T> for Secret<T> {
+    fn from(value: T) -> Self {
+        Self(value)
+    }
+}
+
+impl<T> fmt::Debug for Secret<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(REDACTED)
+    }
+}
+
+impl<T> fmt::Display for Secret<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(REDACTED)
+    }
+}
+
+// Validation errors carry the rejected value, a secret is serialized redacted as well
+// This is one way, deserializing reads the real value, so a serialized secret can never be read back
+// Never serialize a secret that has to be stored or sent somewhere
+impl<T> Serialize for Secret<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(REDACTED)
+    }
+}
+
+// Deny-list of field names whose values are replaced before a log line is written
+// Catches secrets that are not wrapped in Secret, e.g. 'password: "..."' inside a Debug printed struct
+// or 'token=...' inside a request uri
+#[derive(Clone, Debug)]
+pub struct Redaction {
+    fields: Vec<String>,
+}
+
+impl Default for Redaction {
+    fn default() -> Self {
+        Self::new(Vec::<String>::new())
+    }
+}
+
+impl Redaction {
+    // Extra field names are added to the built-in ones
+    pub fn new(fields: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        let fields = DEFAULT_REDACTED_FIELDS
+            .iter()
+            .map(|field| field.to_string())
+            .chain(fields.into_iter().map(|field| field.into().to_lowercase()))
+            .collect();
+        Self { fields }
+    }
+
+    pub fn is_redacted(&self, name: &str) -> bool {
+        let name = name.to_lowercase();
+        if EXACT_REDACTED_FIELDS.contains(&name.as_str()) {
+            return true;
+        }
+        self.fields.iter().any(|field| {
+            name == *field
+                || name
+                    .strip_suffix(field.as_str())
+                    .is_some_and(|prefix| prefix.ends_with('_'))
+        })
+    }
+
+    // Redact the value of a logged field, nested objects like the function parameters included
+    pub fn redact(&self, name: &str, value: &mut serde_json::Value) {
+        if self.is_redacted(name) {
+            *value = serde_json::Value::String(REDACTED.to_string());
+            return;
+        }
+        match value {
+            serde_json::Value::String(text) => *text = self.scrub(text),
+            serde_json::Value::Object(object) => object
+                .iter_mut()
+                .for_each(|(name, value)| self.redact(name, value)),
+            serde_json::Value::Array(values) => {
+                values.iter_mut().for_each(|value| self.redact("", value))
+            }
+            _ => {}
+        }
+    }
+
+    // Replace the values following redacted names in free text
+    fn scrub(&self, text: &str) -> String {
+        let is_identifier = |char: char| char.is_ascii_alphanumeric() || char == '_';
+        let mut output = String::with_capacity(text.len());
+        let mut rest = text;
+        while let Some(start) = rest.find(is_identifier) {
+            output.push_str(&rest[..start]);
+            let remaining = &rest[start..];
+            let end = remaining
+                .find(|char| !is_identifier(char))
+                .unwrap_or(remaining.len());
+            let (identifier, tail) = remaining.split_at(end);
+            output.push_str(identifier);
+            rest = tail;
+
+            if self.is_redacted(identifier) {
+                if let Some((prefix, length)) = value_after_name(tail) {
+                    output.push_str(prefix);
+                    output.push_str(REDACTED);
+                    rest = &tail[prefix.len() + length..];
+                }
+            }
+        }
+        output.push_str(rest);
+        output
+    }
+}
+
+// Find the value following a name in Debug ('name: "value"'), JSON ('"name":"value"')
+// or query string ('name=value') notation, returns the separator and the length of the value
+fn value_after_name(tail: &str) -> Option<(&str, usize)> {
+    const QUOTED: &[&str] = &[": Some(\"", ": \"", "\":\"", "\": \""];
+    if let Some(prefix) = QUOTED.iter().find(|prefix| tail.starts_with(**prefix)) {
+        let value = &tail[prefix.len()..];
+        // The value ends at the first quote that is not escaped
+        let mut is_escaped = false;
+        let length = value
+            .char_indices()
+            .find(|(_, char)| {
+                let is_end = *char == '"' && !is_escaped;
+                is_escaped = *char == '\\' && !is_escaped;
+                is_end
+            })
+            .map(|(index, _)| index)
+            .unwrap_or(value.len());
+        return Some((prefix, length));
+    }
+    let value = tail.strip_prefix('=')?;
+    let length = value
+        .find(|char: char| matches!(char, '&' | '"' | ',' | ')') || char.is_whitespace())
+        .unwrap_or(value.len());
+    Some(("=", length))
+}
@@ -1,16 +1,20 @@
-mod config;
-mod external;
-mod logger;
-mod server;
-
-use config::load_env_vars;
-use external::db;
+use chat_rs::config::load_env_vars;
+use chat_rs::external::db;
+use chat_rs::{logger, server};
+use tracing::error;
 
 #[tokio::main]
 async fn main() {
     // Load environment variables and initialize logger
     load_env_vars();
-    logger::init();
+    // Nothing can be logged yet, so a broken log config is reported on stderr
+    let log_guard = match logger::init() {
+        Ok(log_guard) => log_guard,
+        Err(error) => {
+            eprintln!("logger failed. {}", error);
+            std::process::exit(1);
+        }
+    };
     // Create a new database client
     let db_client = db::init().await;
     // Automatically run migrations upon each service start
@@ -18,6 +22,16 @@ async fn main() {
     //       we should use a centralized repository solely for dealing with database migrations
     //       but as an experimental project in early stage we will stick with this approach first
     db::migrate(&db_client).await;
-    // Initialize web server
-    server::init(db_client.clone()).await;
+    // Initialize web server, it only returns once shut down
+    let result = server::init(db_client.clone()).await;
+    if let Err(error) = &result {
+        error!("web server failed. {}", error);
+    }
+    // Flushing exported spans blocks, so it must not run on the async runtime
+    let _ = tokio::task::spawn_blocking(logger::shutdown).await;
+    // Exiting skips destructors, buffered log lines are written out when the guard is dropped
+    drop(log_guard);
+    if result.is_err() {
+        std::process::exit(1);
+    }
 }
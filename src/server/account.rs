@@ -0,0 +1,131 @@
+use super::ServerState;
+use crate::external::db::models::User;
+use crate::external::db::repository::AccountRepository;
+use crate::external::mail::Mail;
+use dotenvy::var;
+use std::sync::Arc;
+use time::format_description::well_known::Rfc3339;
+use time::{Duration, OffsetDateTime};
+use tokio::sync::Notify;
+use tokio::task::JoinHandle;
+use tracing::{error, info};
+use uuid::Uuid;
+
+const DEFAULT_DELETION_GRACE_PERIOD_DAYS: i64 = 30;
+// Archives hold personal data, so they are only kept for a week
+pub const EXPORT_LIFETIME: Duration = Duration::days(7);
+// Exports are built in memory of the server, one still pending after this long was lost with a restart
+pub const EXPORT_TIMEOUT: Duration = Duration::minutes(15);
+const PURGE_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60 * 60);
+
+// Time between requesting the deletion and purging the account, the user can still cancel meanwhile
+pub fn deletion_grace_period_from_env() -> Result<Duration, anyhow::Error> {
+    match var("ACCOUNT_DELETION_GRACE_PERIOD_DAYS") {
+        Ok(value) => value
+            .trim()
+            .parse::<i64>()
+            .ok()
+            .filter(|days| *days >= 0)
+            .map(Duration::days)
+            .ok_or_else(|| {
+                anyhow::anyhow!("Invalid config for environment variable ACCOUNT_DELETION_GRACE_PERIOD_DAYS. Expected a number of days.")
+            }),
+        Err(_) => Ok(Duration::days(DEFAULT_DELETION_GRACE_PERIOD_DAYS)),
+    }
+}
+
+fn format_time(time: OffsetDateTime) -> String {
+    time.format(&Rfc3339).unwrap_or_else(|_| time.to_string())
+}
+
+pub fn deletion_scheduled_mail(email: &str, scheduled_at: OffsetDateTime) -> Mail {
+    Mail {
+        to: email.to_string(),
+        subject: "Your account will be deleted".to_string(),
+        body: format!(
+            "Your account and all data stored about you will be deleted at {}.\n\n\
+             You have been logged out everywhere. \
+             To keep your account, log in again and cancel the deletion before then.",
+            format_time(scheduled_at),
+        ),
+    }
+}
+
+pub fn export_ready_mail(email: &str, expires_at: OffsetDateTime) -> Mail {
+    Mail {
+        to: email.to_string(),
+        subject: "Your data export is ready".to_string(),
+        body: format!(
+            "The export of your data is ready for download until {}.",
+            format_time(expires_at),
+        ),
+    }
+}
+
+// Build the archive off the request, the user polls the export until it is completed
+pub fn spawn_export(state: Arc<ServerState>, user: User, export_id: Uuid) {
+    tokio::spawn(async move {
+        let archive = match state.accounts.export_user_data(&user.id).await {
+            Ok(Some(archive)) => serde_json::to_value(archive).map_err(|error| {
+                error!("failed to serialize user data archive. {}", error);
+            }),
+            Ok(None) => {
+                error!("user of data export {} no longer exists", export_id);
+                Err(())
+            }
+            Err(_) => Err(()),
+        };
+        let result = match archive {
+            Ok(archive) => {
+                let expires_at = OffsetDateTime::now_utc() + EXPORT_LIFETIME;
+                let result = state
+                    .accounts
+                    .complete_data_export(&export_id, archive, expires_at)
+                    .await;
+                if result.is_ok() {
+                    info!("data export {} completed", export_id);
+                    state
+                        .mailer
+                        .send(export_ready_mail(&user.email, expires_at));
+                }
+                result
+            }
+            Err(()) => state.accounts.fail_data_export(&export_id).await,
+        };
+        if result.is_err() {
+            error!("failed to store the result of data export {}", export_id);
+        }
+    });
+}
+
+// Purge accounts whose grace period is over and remove expired exports
+pub async fn purge(accounts: &dyn AccountRepository) {
+    match accounts.purge_deleted_users().await {
+        Ok(user_ids) if !user_ids.is_empty() => info!("purged {} deleted users", user_ids.len()),
+        Ok(_) => {}
+        Err(_) => error!("failed to purge deleted users"),
+    }
+    match accounts.delete_expired_data_exports().await {
+        Ok(count) if count > 0 => info!("deleted {} expired data exports", count),
+        Ok(_) => {}
+        Err(_) => error!("failed to delete expired data exports"),
+    }
+}
+
+// Runs until the server shuts down, purging is idempotent so several instances may run it
+// A purge that already started is finished, the returned handle resolves once the task stopped
+pub fn spawn_purge_task(
+    accounts: Arc<dyn AccountRepository>,
+    shutdown_started: Arc<Notify>,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(PURGE_INTERVAL);
+        loop {
+            tokio::select! {
+                _ = interval.tick() => purge(accounts.as_ref()).await,
+                _ = shutdown_started.notified() => break,
+            }
+        }
+        info!("purge task stopped");
+    })
+}
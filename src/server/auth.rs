@@ -0,0 +1,255 @@
+use super::handlers::{error_response, ErrorResponse};
+use super::signing_keys::signing_keys;
+use super::ServerState;
+use crate::external::db::models::{User, UserRestriction, UserRole};
+use crate::logger::Secret;
+use axum::async_trait;
+use axum::extract::FromRequestParts;
+use axum::http::request::Parts;
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::Json;
+use axum_extra::extract::cookie::{Cookie, CookieJar, SameSite};
+use dotenvy::var;
+use jsonwebtoken::{decode, decode_header, encode, Header, Validation};
+use serde::{Deserialize, Serialize};
+use std::ops::Add;
+use std::sync::{Arc, OnceLock};
+use time::{Duration, OffsetDateTime};
+use tracing::{debug, error};
+use uuid::Uuid;
+
+// Name of the cookie carrying the JWT access token
+pub const ACCESS_TOKEN_COOKIE: &str = "token";
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Claims {
+    pub sub: Uuid,
+    pub iss: String,
+    pub iat: u64,
+    pub exp: u64,
+}
+
+impl Claims {
+    pub fn new(sub: Uuid, iss: String, lifetime: Duration) -> Self {
+        let now = OffsetDateTime::now_utc();
+        Self {
+            sub,
+            iss,
+            iat: now.unix_timestamp().unsigned_abs(),
+            exp: now.add(lifetime).unix_timestamp().unsigned_abs(),
+        }
+    }
+}
+
+// Authenticated caller resolved from the access token
+#[derive(Debug)]
+pub struct AuthUser(pub User);
+
+// Authenticated caller holding at least the moderator role
+#[derive(Debug)]
+pub struct ModeratorUser(pub User);
+
+// Authenticated caller holding the admin role
+#[derive(Debug)]
+pub struct AdminUser(pub User);
+
+// Issuer of every token this server signs and the secret of the ones signed with HMAC
+// Access tokens are signed with the signing keys instead
+#[derive(Clone, Debug)]
+pub struct TokenConfig {
+    pub issuer: String,
+    pub secret: Secret<String>,
+    // Issuer shown in authenticator apps for two-factor authentication
+    pub totp_issuer: String,
+}
+
+fn missing_config(name: &str) -> anyhow::Error {
+    anyhow::anyhow!("Missing config for environment variable {}.", name)
+}
+
+impl TokenConfig {
+    pub fn from_env() -> Result<Self, anyhow::Error> {
+        Ok(Self {
+            issuer: var("TOKEN_ISS").map_err(|_| missing_config("TOKEN_ISS"))?,
+            secret: Secret::new(
+                var("ACCESS_TOKEN_SECRET").map_err(|_| missing_config("ACCESS_TOKEN_SECRET"))?,
+            ),
+            totp_issuer: var("TOTP_ISSUER").unwrap_or_else(|_| "chat-rs".to_string()),
+        })
+    }
+}
+
+static TOKEN_CONFIG: OnceLock<TokenConfig> = OnceLock::new();
+
+// Installed once on start like the signing keys
+// Returns false when a config was already installed, that one stays in use
+pub fn init(config: TokenConfig) -> bool {
+    TOKEN_CONFIG.set(config).is_ok()
+}
+
+pub fn token_config() -> &'static TokenConfig {
+    TOKEN_CONFIG
+        .get()
+        .expect("token config should be installed before serving requests")
+}
+
+// Construct a JWT access token for the user signed with the current signing key
+// The key id is put into the header so that verifiers can pick the matching public key
+pub fn encode_access_token(user_id: Uuid) -> Result<String, (StatusCode, Json<ErrorResponse>)> {
+    let token_iss = token_config().issuer.clone();
+    let signing_key = signing_keys().signing_key();
+    let mut header = Header::new(signing_key.algorithm);
+    header.kid = Some(signing_key.id.clone());
+
+    encode(
+        &header,
+        &Claims::new(user_id, token_iss, Duration::minutes(5)),
+        &signing_key.encoding_key,
+    )
+    .map_err(|error| {
+        error!("jwt access token construction error. {}", error);
+        error_response(StatusCode::INTERNAL_SERVER_ERROR, "Internal server error.")
+    })
+}
+
+// Construct cookie for the JWT access token
+pub fn access_token_cookie(access_token: String) -> Cookie<'static> {
+    Cookie::build(ACCESS_TOKEN_COOKIE, access_token)
+        .path("/")
+        .secure(false) // Forbid cookie from transmitting over simple HTTP
+        .http_only(true) // Blocks access of related cookie from client side
+        .same_site(SameSite::Lax) // SameSite 'none' has to be used together with secure - true
+        .max_age(Duration::minutes(5)) // The duration better to align with expiry time of access token
+        .finish()
+}
+
+// Reject users that are currently suspended or banned
+pub fn ensure_not_restricted(user: &User) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+    match user.active_restriction() {
+        Some(UserRestriction::Suspended) => {
+            Err(error_response(StatusCode::FORBIDDEN, "User is suspended."))
+        }
+        Some(UserRestriction::Banned) => {
+            Err(error_response(StatusCode::FORBIDDEN, "User is banned."))
+        }
+        None => Ok(()),
+    }
+}
+
+// Access token can come from either 'Authorization: Bearer <token>' header or the token cookie
+pub fn extract_access_token(headers: &HeaderMap) -> Option<String> {
+    let bearer_token = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(|token| token.trim().to_string());
+
+    bearer_token.or_else(|| {
+        CookieJar::from_headers(headers)
+            .get(ACCESS_TOKEN_COOKIE)
+            .map(|cookie| cookie.value().to_string())
+    })
+}
+
+// Verify the signature, issuer and expiry of a JWT access token and return its claims
+// Only the algorithm of the key named by the header is accepted, so a token cannot pick a weaker one
+pub fn decode_access_token(
+    access_token: &str,
+) -> Result<Claims, (StatusCode, Json<ErrorResponse>)> {
+    let invalid_access_token = |error: jsonwebtoken::errors::Error| {
+        debug!("invalid jwt access token. {}", error);
+        error_response(StatusCode::UNAUTHORIZED, "Invalid access token.")
+    };
+    let token_iss = token_config().issuer.clone();
+    let signing_key = decode_header(access_token)
+        .map_err(invalid_access_token)?
+        .kid
+        .and_then(|kid| signing_keys().get(&kid))
+        .ok_or_else(|| {
+            debug!("invalid jwt access token. unknown key id");
+            error_response(StatusCode::UNAUTHORIZED, "Invalid access token.")
+        })?;
+    let mut validation = Validation::new(signing_key.algorithm);
+    validation.set_issuer(&[token_iss]);
+
+    decode::<Claims>(access_token, &signing_key.decoding_key, &validation)
+        .map(|token_data| token_data.claims)
+        .map_err(invalid_access_token)
+}
+
+#[async_trait]
+impl FromRequestParts<Arc<ServerState>> for AuthUser {
+    type Rejection = (StatusCode, Json<ErrorResponse>);
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &Arc<ServerState>,
+    ) -> Result<Self, Self::Rejection> {
+        let access_token = extract_access_token(&parts.headers)
+            .ok_or_else(|| error_response(StatusCode::UNAUTHORIZED, "Missing access token."))?;
+        let claims = decode_access_token(&access_token)?;
+
+        let user = state
+            .users
+            .get_user_by_id(&claims.sub)
+            .await?
+            .ok_or_else(|| error_response(StatusCode::UNAUTHORIZED, "Invalid access token."))?;
+
+        // Tokens issued before a force logout are no longer accepted
+        if let Some(tokens_revoked_at) = user.tokens_revoked_at {
+            if claims.iat as i64 <= tokens_revoked_at.unix_timestamp() {
+                debug!("jwt access token has been revoked");
+                return Err(error_response(
+                    StatusCode::UNAUTHORIZED,
+                    "Invalid access token.",
+                ));
+            }
+        }
+        ensure_not_restricted(&user)?;
+
+        Ok(AuthUser(user))
+    }
+}
+
+async fn require_role(
+    parts: &mut Parts,
+    state: &Arc<ServerState>,
+    role: UserRole,
+) -> Result<User, (StatusCode, Json<ErrorResponse>)> {
+    let AuthUser(user) = AuthUser::from_request_parts(parts, state).await?;
+    if user.role < role {
+        return Err(error_response(
+            StatusCode::FORBIDDEN,
+            "Insufficient permission.",
+        ));
+    }
+    Ok(user)
+}
+
+#[async_trait]
+impl FromRequestParts<Arc<ServerState>> for ModeratorUser {
+    type Rejection = (StatusCode, Json<ErrorResponse>);
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &Arc<ServerState>,
+    ) -> Result<Self, Self::Rejection> {
+        require_role(parts, state, UserRole::Moderator)
+            .await
+            .map(ModeratorUser)
+    }
+}
+
+#[async_trait]
+impl FromRequestParts<Arc<ServerState>> for AdminUser {
+    type Rejection = (StatusCode, Json<ErrorResponse>);
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &Arc<ServerState>,
+    ) -> Result<Self, Self::Rejection> {
+        require_role(parts, state, UserRole::Admin)
+            .await
+            .map(AdminUser)
+    }
+}
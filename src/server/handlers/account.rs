@@ -0,0 +1,216 @@
+use super::{CustomJson, CustomPath};
+use crate::external::db::models::{UserDataExport, UserDataExportStatus};
+use crate::logger::Secret;
+use crate::server::account;
+use crate::server::auth::AuthUser;
+use crate::server::handlers::{error_response, ErrorResponse, SuccessResponse};
+use crate::server::ServerState;
+use axum::extract::State;
+use axum::http::{header, StatusCode};
+use axum::response::IntoResponse;
+use axum::Json;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use time::OffsetDateTime;
+use tracing::{debug, error, info};
+use uuid::Uuid;
+use validator::Validate;
+
+// Users with a password have to confirm the deletion with it, a stolen session alone is not enough
+#[derive(Clone, Debug, Deserialize, Validate)]
+pub struct DeleteAccountSchema {
+    #[validate(length(
+        min = 1,
+        max = 72,
+        message = "Password must be between 1 and 72 characters long."
+    ))]
+    password: Option<Secret<String>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DeleteAccountResponse {
+    message: String,
+    #[serde(with = "time::serde::rfc3339")]
+    deletion_scheduled_at: OffsetDateTime,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RestoreAccountResponse {
+    message: String,
+}
+
+// Handler function for path '/api/v1/user/me'
+// Request body is skipped so that the plaintext password never reaches the logs
+#[tracing::instrument(skip(state, user, body))]
+pub async fn delete_account_handler(
+    State(state): State<Arc<ServerState>>,
+    AuthUser(user): AuthUser,
+    CustomJson(body): CustomJson<DeleteAccountSchema>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ErrorResponse>)> {
+    info!("received request");
+    if let Some(password_hash) = &user.password {
+        let password = body.password.as_ref().ok_or_else(|| {
+            error_response(
+                StatusCode::BAD_REQUEST,
+                "Password is required to delete the account.",
+            )
+        })?;
+        debug!("going to verify user password");
+        let is_password_valid = state
+            .password_hasher
+            .verify(password, password_hash)
+            .await
+            .map_err(|error| {
+                error!("password verification error. {}", error);
+                error_response(StatusCode::INTERNAL_SERVER_ERROR, "Internal server error.")
+            })?;
+        if !is_password_valid {
+            return Err(error_response(
+                StatusCode::UNAUTHORIZED,
+                "Invalid password.",
+            ));
+        }
+    }
+
+    debug!("going to schedule account deletion");
+    let deletion_scheduled_at = OffsetDateTime::now_utc() + state.deletion_grace_period;
+    state
+        .accounts
+        .schedule_deletion(&user.id, deletion_scheduled_at)
+        .await?;
+    state.mailer.send(account::deletion_scheduled_mail(
+        &user.email,
+        deletion_scheduled_at,
+    ));
+
+    Ok((
+        StatusCode::ACCEPTED,
+        Json(SuccessResponse::<DeleteAccountResponse> {
+            success: true,
+            result: DeleteAccountResponse {
+                message: "Account deletion scheduled.".to_string(),
+                deletion_scheduled_at,
+            },
+        }),
+    ))
+}
+
+// Handler function for path '/api/v1/user/me/restore'
+#[tracing::instrument(skip(state, user))]
+pub async fn restore_account_handler(
+    State(state): State<Arc<ServerState>>,
+    AuthUser(user): AuthUser,
+) -> Result<impl IntoResponse, (StatusCode, Json<ErrorResponse>)> {
+    info!("received request");
+    if !state.accounts.cancel_deletion(&user.id).await? {
+        return Err(error_response(
+            StatusCode::BAD_REQUEST,
+            "Account is not scheduled for deletion.",
+        ));
+    }
+
+    Ok((
+        StatusCode::OK,
+        Json(SuccessResponse::<RestoreAccountResponse> {
+            success: true,
+            result: RestoreAccountResponse {
+                message: "Account deletion cancelled.".to_string(),
+            },
+        }),
+    ))
+}
+
+// Handler function for path '/api/v1/user/me/export'
+#[tracing::instrument(skip(state, user))]
+pub async fn create_export_handler(
+    State(state): State<Arc<ServerState>>,
+    AuthUser(user): AuthUser,
+) -> Result<impl IntoResponse, (StatusCode, Json<ErrorResponse>)> {
+    info!("received request");
+    // Requesting an export again while one is being built returns that one instead of starting another
+    let created_after = OffsetDateTime::now_utc() - account::EXPORT_TIMEOUT;
+    let export = match state
+        .accounts
+        .get_pending_data_export(&user.id, created_after)
+        .await?
+    {
+        Some(export) => export,
+        None => {
+            let export = state.accounts.create_data_export(&user.id).await?;
+            debug!("going to build data export in background");
+            account::spawn_export(state.clone(), user, export.id);
+            export
+        }
+    };
+
+    Ok((
+        StatusCode::ACCEPTED,
+        Json(SuccessResponse::<UserDataExport> {
+            success: true,
+            result: export,
+        }),
+    ))
+}
+
+// Handler function for path '/api/v1/user/me/export/:export_id'
+#[tracing::instrument(skip(state, user))]
+pub async fn get_export_handler(
+    State(state): State<Arc<ServerState>>,
+    AuthUser(user): AuthUser,
+    CustomPath(export_id): CustomPath<Uuid>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ErrorResponse>)> {
+    info!("received request");
+    let export = state
+        .accounts
+        .get_data_export(&user.id, &export_id)
+        .await?
+        .ok_or_else(|| error_response(StatusCode::NOT_FOUND, "Data export not found."))?;
+
+    Ok((
+        StatusCode::OK,
+        Json(SuccessResponse::<UserDataExport> {
+            success: true,
+            result: export,
+        }),
+    ))
+}
+
+// Handler function for path '/api/v1/user/me/export/:export_id/download'
+#[tracing::instrument(skip(state, user))]
+pub async fn download_export_handler(
+    State(state): State<Arc<ServerState>>,
+    AuthUser(user): AuthUser,
+    CustomPath(export_id): CustomPath<Uuid>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ErrorResponse>)> {
+    info!("received request");
+    if let Some(archive) = state
+        .accounts
+        .get_data_export_archive(&user.id, &export_id)
+        .await?
+    {
+        return Ok((
+            StatusCode::OK,
+            [(
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"chat-rs-export-{}.json\"", export_id),
+            )],
+            Json(archive),
+        ));
+    }
+
+    // Tell apart why there is nothing to download
+    let export = state
+        .accounts
+        .get_data_export(&user.id, &export_id)
+        .await?
+        .ok_or_else(|| error_response(StatusCode::NOT_FOUND, "Data export not found."))?;
+    Err(match export.status {
+        UserDataExportStatus::Pending => {
+            error_response(StatusCode::CONFLICT, "Data export is not ready yet.")
+        }
+        UserDataExportStatus::Completed | UserDataExportStatus::Failed => error_response(
+            StatusCode::GONE,
+            "Data export has expired or failed. Please request a new one.",
+        ),
+    })
+}
@@ -0,0 +1,331 @@
+use super::{find_user, page, CustomJson, CustomPath, CustomQuery};
+use crate::external::db::admin_audit_log::NewAdminAuditLog;
+use crate::external::db::models::{AdminAuditLog, User, UserRestriction, UserRole};
+use crate::external::db::user::{UserFilter, UserRestrictionUpdate};
+use crate::server::auth::AdminUser;
+use crate::server::handlers::{ErrorResponse, SuccessResponse};
+use crate::server::validation::validate_not_blank;
+use crate::server::ServerState;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use axum::Json;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use time::OffsetDateTime;
+use tracing::{debug, info};
+use uuid::Uuid;
+use validator::Validate;
+
+#[derive(Clone, Debug, Deserialize, Validate)]
+pub struct ListUsersSchema {
+    #[validate(length(max = 100, message = "Email filter must not exceed 100 characters."))]
+    email: Option<String>,
+    role: Option<UserRole>,
+    verified: Option<bool>,
+    #[validate(range(min = 1, max = 200, message = "Limit must be between 1 and 200."))]
+    limit: Option<i64>,
+    #[validate(range(min = 0, message = "Offset must not be negative."))]
+    offset: Option<i64>,
+}
+
+#[derive(Clone, Debug, Deserialize, Validate)]
+pub struct ListAuditLogsSchema {
+    user_id: Option<Uuid>,
+    #[validate(range(min = 1, max = 200, message = "Limit must be between 1 and 200."))]
+    limit: Option<i64>,
+    #[validate(range(min = 0, message = "Offset must not be negative."))]
+    offset: Option<i64>,
+}
+
+#[derive(Clone, Debug, Deserialize, Validate)]
+pub struct RestrictUserSchema {
+    #[validate(
+        custom = "validate_not_blank",
+        length(max = 500, message = "Reason must not exceed 500 characters.")
+    )]
+    reason: String,
+    // Restriction stays in place until lifted manually when expiry is not provided
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    expires_at: Option<OffsetDateTime>,
+}
+
+#[derive(Clone, Debug, Deserialize, Validate)]
+pub struct UpdateRoleSchema {
+    role: UserRole,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AdminActionResponse {
+    message: String,
+}
+
+// Recorded by the repository on the same transaction as the action itself so that no action goes unrecorded
+pub(super) fn admin_action(
+    actor: &User,
+    action: &str,
+    target_user_id: Uuid,
+    details: serde_json::Value,
+) -> NewAdminAuditLog {
+    NewAdminAuditLog {
+        actor_id: actor.id,
+        action: action.to_string(),
+        target_user_id: Some(target_user_id),
+        details,
+    }
+}
+
+fn admin_action_response(message: &str) -> impl IntoResponse {
+    (
+        StatusCode::OK,
+        Json(SuccessResponse::<AdminActionResponse> {
+            success: true,
+            result: AdminActionResponse {
+                message: message.to_string(),
+            },
+        }),
+    )
+}
+
+// Handler function for path '/api/v1/admin/users'
+#[tracing::instrument(skip(state, _admin))]
+pub async fn list_users_handler(
+    State(state): State<Arc<ServerState>>,
+    AdminUser(_admin): AdminUser,
+    CustomQuery(params): CustomQuery<ListUsersSchema>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ErrorResponse>)> {
+    info!("received request");
+    let (limit, offset) = page(params.limit, params.offset);
+    let users = state
+        .admin
+        .list_users(UserFilter {
+            email: params.email,
+            role: params.role,
+            verified: params.verified,
+            limit,
+            offset,
+        })
+        .await?;
+
+    Ok((
+        StatusCode::OK,
+        Json(SuccessResponse::<Vec<User>> {
+            success: true,
+            result: users,
+        }),
+    ))
+}
+
+// Handler function for path '/api/v1/admin/users/:user_id'
+#[tracing::instrument(skip(state, _admin))]
+pub async fn get_user_handler(
+    State(state): State<Arc<ServerState>>,
+    AdminUser(_admin): AdminUser,
+    CustomPath(user_id): CustomPath<Uuid>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ErrorResponse>)> {
+    info!("received request");
+    let user = find_user(&state, &user_id).await?;
+
+    Ok((
+        StatusCode::OK,
+        Json(SuccessResponse::<User> {
+            success: true,
+            result: user,
+        }),
+    ))
+}
+
+// Handler function for path '/api/v1/admin/users/:user_id/verify'
+#[tracing::instrument(skip(state, admin))]
+pub async fn verify_user_handler(
+    State(state): State<Arc<ServerState>>,
+    AdminUser(admin): AdminUser,
+    CustomPath(user_id): CustomPath<Uuid>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ErrorResponse>)> {
+    info!("received request");
+    let user = find_user(&state, &user_id).await?;
+
+    debug!("going to force update user verification status");
+    state
+        .admin
+        .verify_user(
+            &user.id,
+            admin_action(
+                &admin,
+                "verify_user",
+                user.id,
+                serde_json::json!({ "previously_verified": user.verified }),
+            ),
+        )
+        .await?;
+
+    Ok(admin_action_response("User verified."))
+}
+
+async fn restrict_user(
+    state: &ServerState,
+    admin: &User,
+    user_id: Uuid,
+    restriction: UserRestriction,
+    body: RestrictUserSchema,
+) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+    let user = find_user(state, &user_id).await?;
+    if user.id == admin.id {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(ErrorResponse {
+                success: false,
+                error: "Admin cannot restrict themselves.".to_string(),
+            }),
+        ));
+    }
+
+    let action = match restriction {
+        UserRestriction::Suspended => "suspend_user",
+        UserRestriction::Banned => "ban_user",
+    };
+    let audit_log = admin_action(
+        admin,
+        action,
+        user.id,
+        serde_json::json!({
+            "reason": body.reason,
+            "expires_at": body.expires_at.map(|expires_at| expires_at.unix_timestamp()),
+        }),
+    );
+    // Restricted users lose their existing sessions immediately
+    debug!("going to update user restriction");
+    state
+        .admin
+        .restrict_user(
+            &user.id,
+            UserRestrictionUpdate {
+                restriction,
+                reason: body.reason,
+                expires_at: body.expires_at,
+            },
+            audit_log,
+        )
+        .await
+}
+
+// Handler function for path '/api/v1/admin/users/:user_id/suspend'
+#[tracing::instrument(skip(state, admin))]
+pub async fn suspend_user_handler(
+    State(state): State<Arc<ServerState>>,
+    AdminUser(admin): AdminUser,
+    CustomPath(user_id): CustomPath<Uuid>,
+    CustomJson(body): CustomJson<RestrictUserSchema>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ErrorResponse>)> {
+    info!("received request");
+    restrict_user(&state, &admin, user_id, UserRestriction::Suspended, body).await?;
+
+    Ok(admin_action_response("User suspended."))
+}
+
+// Handler function for path '/api/v1/admin/users/:user_id/ban'
+#[tracing::instrument(skip(state, admin))]
+pub async fn ban_user_handler(
+    State(state): State<Arc<ServerState>>,
+    AdminUser(admin): AdminUser,
+    CustomPath(user_id): CustomPath<Uuid>,
+    CustomJson(body): CustomJson<RestrictUserSchema>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ErrorResponse>)> {
+    info!("received request");
+    restrict_user(&state, &admin, user_id, UserRestriction::Banned, body).await?;
+
+    Ok(admin_action_response("User banned."))
+}
+
+// Handler function for path '/api/v1/admin/users/:user_id/logout'
+#[tracing::instrument(skip(state, admin))]
+pub async fn logout_user_handler(
+    State(state): State<Arc<ServerState>>,
+    AdminUser(admin): AdminUser,
+    CustomPath(user_id): CustomPath<Uuid>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ErrorResponse>)> {
+    info!("received request");
+    let user = find_user(&state, &user_id).await?;
+
+    debug!("going to revoke all issued access tokens of user");
+    state
+        .admin
+        .revoke_tokens(
+            &user.id,
+            admin_action(&admin, "force_logout", user.id, serde_json::json!({})),
+        )
+        .await?;
+
+    Ok(admin_action_response("User logged out."))
+}
+
+// Handler function for path '/api/v1/admin/users/:user_id/role'
+#[tracing::instrument(skip(state, admin))]
+pub async fn update_role_handler(
+    State(state): State<Arc<ServerState>>,
+    AdminUser(admin): AdminUser,
+    CustomPath(user_id): CustomPath<Uuid>,
+    CustomJson(body): CustomJson<UpdateRoleSchema>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ErrorResponse>)> {
+    info!("received request");
+    let user = find_user(&state, &user_id).await?;
+    if user.id == admin.id {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(ErrorResponse {
+                success: false,
+                error: "Admin cannot change their own role.".to_string(),
+            }),
+        ));
+    }
+
+    debug!("going to update user role");
+    let is_updated = state
+        .admin
+        .update_role(
+            &user.id,
+            body.role,
+            admin_action(
+                &admin,
+                "update_role",
+                user.id,
+                serde_json::json!({ "previous_role": user.role, "role": body.role }),
+            ),
+        )
+        .await?;
+    // Another admin demoted every other admin in the meantime
+    if !is_updated {
+        return Err((
+            StatusCode::CONFLICT,
+            Json(ErrorResponse {
+                success: false,
+                error: "The last admin cannot be demoted.".to_string(),
+            }),
+        ));
+    }
+
+    Ok(admin_action_response("User role updated."))
+}
+
+// Handler function for path '/api/v1/admin/audit-logs'
+#[tracing::instrument(skip(state, _admin))]
+pub async fn list_audit_logs_handler(
+    State(state): State<Arc<ServerState>>,
+    AdminUser(_admin): AdminUser,
+    CustomQuery(params): CustomQuery<ListAuditLogsSchema>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ErrorResponse>)> {
+    info!("received request");
+    let (limit, offset) = page(params.limit, params.offset);
+    let audit_logs = state
+        .admin
+        .list_audit_logs(params.user_id, limit, offset)
+        .await?;
+
+    Ok((
+        StatusCode::OK,
+        Json(SuccessResponse::<Vec<AdminAuditLog>> {
+            success: true,
+            result: audit_logs,
+        }),
+    ))
+}
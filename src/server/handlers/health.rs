@@ -0,0 +1,126 @@
+use super::SuccessResponse;
+use crate::db;
+use crate::server::ServerState;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use axum::Json;
+use serde::Serialize;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+// A dependency that does not answer within this time is reported as down
+const DEPENDENCY_CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Serialize)]
+pub struct Liveness {
+    status: &'static str,
+    version: &'static str,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DependencyStatus {
+    name: &'static str,
+    status: &'static str,
+    latency_ms: u128,
+    // Only a short reason is exposed, the details are logged
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<&'static str>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Readiness {
+    status: &'static str,
+    version: &'static str,
+    dependencies: Vec<DependencyStatus>,
+}
+
+async fn check_dependency<F>(name: &'static str, check: F) -> DependencyStatus
+where
+    F: Future<Output = Result<(), anyhow::Error>>,
+{
+    let started_at = Instant::now();
+    let result = tokio::time::timeout(DEPENDENCY_CHECK_TIMEOUT, check).await;
+    let latency_ms = started_at.elapsed().as_millis();
+
+    let error = match result {
+        Ok(Ok(_)) => None,
+        Ok(Err(error)) => {
+            warn!("dependency {} is not ready. {}", name, error);
+            Some("unavailable")
+        }
+        Err(_) => {
+            warn!(
+                "dependency {} is not ready. timed out after {}ms",
+                name,
+                DEPENDENCY_CHECK_TIMEOUT.as_millis()
+            );
+            Some("timeout")
+        }
+    };
+    DependencyStatus {
+        name,
+        status: if error.is_none() { "up" } else { "down" },
+        latency_ms,
+        error,
+    }
+}
+
+// Handler function for path '/healthz'
+// The process is alive as long as it can answer, dependencies are left to the readiness probe
+#[tracing::instrument]
+pub async fn liveness_handler() -> impl IntoResponse {
+    info!("received request");
+    (
+        StatusCode::OK,
+        Json(SuccessResponse::<Liveness> {
+            success: true,
+            result: Liveness {
+                status: "ok",
+                version: VERSION,
+            },
+        }),
+    )
+}
+
+// Handler function for path '/readyz'
+#[tracing::instrument(skip(state))]
+pub async fn readiness_handler(State(state): State<Arc<ServerState>>) -> impl IntoResponse {
+    info!("received request");
+    let database = check_dependency("database", async {
+        db::ping(&state.db).await.map_err(anyhow::Error::from)
+    });
+    // Redis is only checked when the rate limiter is configured to use it
+    let rate_limit_store = async {
+        match state.rate_limiter.service() {
+            Some(name) => Some(check_dependency(name, state.rate_limiter.ping()).await),
+            None => None,
+        }
+    };
+    let (database, rate_limit_store) = tokio::join!(database, rate_limit_store);
+
+    let dependencies: Vec<DependencyStatus> =
+        std::iter::once(database).chain(rate_limit_store).collect();
+    let is_ready = dependencies
+        .iter()
+        .all(|dependency| dependency.status == "up");
+
+    (
+        if is_ready {
+            StatusCode::OK
+        } else {
+            StatusCode::SERVICE_UNAVAILABLE
+        },
+        Json(SuccessResponse::<Readiness> {
+            success: is_ready,
+            result: Readiness {
+                status: if is_ready { "ready" } else { "degraded" },
+                version: VERSION,
+                dependencies,
+            },
+        }),
+    )
+}
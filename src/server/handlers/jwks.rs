@@ -0,0 +1,16 @@
+use crate::server::signing_keys::signing_keys;
+use axum::http::header;
+use axum::response::IntoResponse;
+use axum::Json;
+use tracing::info;
+
+// Handler function for path '/.well-known/jwks.json'
+// Verifiers may cache the keys for a while, so a new key has to be published before it signs
+#[tracing::instrument]
+pub async fn jwks_handler() -> impl IntoResponse {
+    info!("received request");
+    (
+        [(header::CACHE_CONTROL, "public, max-age=300")],
+        Json(signing_keys().jwk_set()),
+    )
+}
@@ -0,0 +1,44 @@
+use super::{page, CustomQuery};
+use crate::external::db::models::UserLoginHistory;
+use crate::server::auth::AuthUser;
+use crate::server::handlers::{ErrorResponse, SuccessResponse};
+use crate::server::ServerState;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use axum::Json;
+use serde::Deserialize;
+use std::sync::Arc;
+use tracing::info;
+use validator::Validate;
+
+#[derive(Clone, Debug, Deserialize, Validate)]
+pub struct ListLoginHistorySchema {
+    #[validate(range(min = 1, max = 200, message = "Limit must be between 1 and 200."))]
+    limit: Option<i64>,
+    #[validate(range(min = 0, message = "Offset must not be negative."))]
+    offset: Option<i64>,
+}
+
+// Handler function for path '/api/v1/user/login-history'
+#[tracing::instrument(skip(state, user))]
+pub async fn list_login_history_handler(
+    State(state): State<Arc<ServerState>>,
+    AuthUser(user): AuthUser,
+    CustomQuery(params): CustomQuery<ListLoginHistorySchema>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ErrorResponse>)> {
+    info!("received request");
+    let (limit, offset) = page(params.limit, params.offset);
+    let login_history = state
+        .logins
+        .list_login_history(&user.id, limit, offset)
+        .await?;
+
+    Ok((
+        StatusCode::OK,
+        Json(SuccessResponse::<Vec<UserLoginHistory>> {
+            success: true,
+            result: login_history,
+        }),
+    ))
+}
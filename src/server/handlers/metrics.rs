@@ -0,0 +1,30 @@
+use super::ErrorResponse;
+use crate::server::metrics::metrics;
+use crate::server::ServerState;
+use axum::extract::State;
+use axum::http::{header, StatusCode};
+use axum::response::IntoResponse;
+use axum::Json;
+use std::sync::Arc;
+use tracing::{error, info};
+
+// Handler function for path '/metrics'
+#[tracing::instrument(skip(state))]
+pub async fn metrics_handler(
+    State(state): State<Arc<ServerState>>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ErrorResponse>)> {
+    info!("received request");
+    metrics().observe_db_pool(&state.db);
+    let body = metrics().render().map_err(|error| {
+        error!("failed to render metrics. {}", error);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse {
+                success: false,
+                error: "Internal server error.".to_string(),
+            }),
+        )
+    })?;
+
+    Ok(([(header::CONTENT_TYPE, prometheus::TEXT_FORMAT)], body))
+}
@@ -1,102 +1,241 @@
+pub mod account;
+pub mod admin;
+pub mod health;
+pub mod jwks;
+pub mod login_history;
+pub mod metrics;
+pub mod moderation;
+pub mod oidc;
+mod rejection;
+pub mod report;
+pub mod two_factor;
 pub mod user;
 
-use axum::extract::rejection::{JsonRejection, QueryRejection};
-use axum::extract::Query;
-use axum::http::StatusCode;
+use super::request_id::RequestContext;
+use super::ServerState;
+use crate::external::db::models::User;
+use axum::async_trait;
+use axum::body::{Bytes, HttpBody};
+use axum::extract::{FromRequest, FromRequestParts, Path};
+use axum::http::request::Parts;
+use axum::http::{header, HeaderMap, Request, StatusCode};
 use axum::response::{IntoResponse, Response};
-use axum::Json;
-use axum_macros::{FromRequest, FromRequestParts};
-use serde::Serialize;
+use axum::{BoxError, Json};
+use axum_macros::FromRequestParts;
+use serde::de::DeserializeOwned;
+use serde::ser::SerializeStruct;
+use serde::{Serialize, Serializer};
 use tracing::{error, info};
+use uuid::Uuid;
+use validator::Validate;
 
-#[derive(FromRequest)]
-#[from_request(via(Json), rejection(CustomError))]
+const DEFAULT_PAGE_SIZE: i64 = 50;
+const MAX_PAGE_SIZE: i64 = 200;
+
+// JSON request body which is validated against the schema before reaching the handler
 pub struct CustomJson<T>(T);
 
-#[derive(FromRequestParts)]
-#[from_request(via(Query), rejection(CustomError))]
+// Query string which is validated against the schema before reaching the handler
 pub struct CustomQuery<T>(T);
 
+#[derive(FromRequestParts)]
+#[from_request(via(Path), rejection(CustomError))]
+pub struct CustomPath<T>(T);
+
 pub struct CustomError {
     status: StatusCode,
+    // Original rejection reason which is only written to the logs
     message: String,
+    // Client facing error message
+    error: String,
+    fields: Vec<FieldError>,
 }
 
 #[derive(Debug, Serialize)]
 pub struct HealthCheck;
 
-#[derive(Debug, Serialize)]
+#[derive(Debug)]
 pub struct ErrorResponse {
     pub success: bool,
     pub error: String,
 }
 
+pub(crate) fn error_response(
+    status: StatusCode,
+    message: &str,
+) -> (StatusCode, Json<ErrorResponse>) {
+    (
+        status,
+        Json(ErrorResponse {
+            success: false,
+            error: message.to_string(),
+        }),
+    )
+}
+
+pub(crate) fn internal_server_error() -> (StatusCode, Json<ErrorResponse>) {
+    error_response(StatusCode::INTERNAL_SERVER_ERROR, "Internal server error.")
+}
+
+#[derive(Debug, Serialize)]
+pub struct FieldError {
+    pub field: String,
+    pub code: String,
+    pub message: String,
+}
+
+// Error response listing every field that failed validation
+#[derive(Debug)]
+pub struct ValidationErrorResponse {
+    pub success: bool,
+    pub error: String,
+    pub fields: Vec<FieldError>,
+}
+
+// Error responses carry the id of the request being handled so that they can be matched with the logs
+fn serialize_request_id<S: SerializeStruct>(state: &mut S) -> Result<(), S::Error> {
+    match RequestContext::current() {
+        Some(context) => state.serialize_field("request_id", &context.request_id),
+        None => state.skip_field("request_id"),
+    }
+}
+
+impl Serialize for ErrorResponse {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("ErrorResponse", 3)?;
+        state.serialize_field("success", &self.success)?;
+        state.serialize_field("error", &self.error)?;
+        serialize_request_id(&mut state)?;
+        state.end()
+    }
+}
+
+impl Serialize for ValidationErrorResponse {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("ValidationErrorResponse", 4)?;
+        state.serialize_field("success", &self.success)?;
+        state.serialize_field("error", &self.error)?;
+        state.serialize_field("fields", &self.fields)?;
+        serialize_request_id(&mut state)?;
+        state.end()
+    }
+}
+
 #[derive(Debug, Serialize)]
 pub struct SuccessResponse<T> {
     success: bool,
     result: T,
 }
 
-impl From<JsonRejection> for CustomError {
-    fn from(rejection: JsonRejection) -> Self {
-        Self {
-            status: rejection.status(),
-            message: rejection.body_text(),
+#[async_trait]
+impl<T, S, B> FromRequest<S, B> for CustomJson<T>
+where
+    T: DeserializeOwned + Validate,
+    S: Send + Sync,
+    B: HttpBody + Send + 'static,
+    B::Data: Send,
+    B::Error: Into<BoxError>,
+{
+    type Rejection = CustomError;
+
+    async fn from_request(request: Request<B>, state: &S) -> Result<Self, Self::Rejection> {
+        if !has_json_content_type(request.headers()) {
+            return Err(CustomError::missing_json_content_type());
         }
+        let bytes = Bytes::from_request(request, state).await?;
+        // Deserialize through serde_path_to_error so that errors can tell which field failed
+        let deserializer = &mut serde_json::Deserializer::from_slice(&bytes);
+        let value: T = serde_path_to_error::deserialize(deserializer).map_err(CustomError::from)?;
+        value.validate()?;
+        Ok(CustomJson(value))
     }
 }
 
-impl From<QueryRejection> for CustomError {
-    fn from(rejection: QueryRejection) -> Self {
-        Self {
-            status: rejection.status(),
-            message: rejection.body_text(),
-        }
+// Same check as axum's Json extractor, accepts 'application/json' and 'application/*+json'
+fn has_json_content_type(headers: &HeaderMap) -> bool {
+    let Some(content_type) = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+    else {
+        return false;
+    };
+    let essence = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+    essence
+        .strip_prefix("application/")
+        .is_some_and(|subtype| subtype == "json" || subtype.ends_with("+json"))
+}
+
+#[async_trait]
+impl<T, S> FromRequestParts<S> for CustomQuery<T>
+where
+    T: DeserializeOwned + Validate,
+    S: Send + Sync,
+{
+    type Rejection = CustomError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        // Deserialize through serde_path_to_error so that errors can tell which field failed
+        let query = parts.uri.query().unwrap_or_default();
+        let deserializer =
+            serde_urlencoded::Deserializer::new(form_urlencoded::parse(query.as_bytes()));
+        let value: T = serde_path_to_error::deserialize(deserializer).map_err(CustomError::from)?;
+        value.validate()?;
+        Ok(CustomQuery(value))
     }
 }
 
 impl IntoResponse for CustomError {
     fn into_response(self) -> Response {
         error!("{}", self.message);
-        // format!("Missing required fields in request body.")
-        let error_message = match self.status {
-            StatusCode::BAD_REQUEST => {
-                if self.message.contains("missing field") {
-                    let message_shards: Vec<&str> = self.message.split('`').collect();
-                    let field_name = message_shards[1].trim();
-                    format!("Missing required field {} in query.", field_name)
-                } else {
-                    format!("Invalid request.")
-                }
-            }
-            StatusCode::UNSUPPORTED_MEDIA_TYPE => format!("Expected non-empty request body."),
-            StatusCode::UNPROCESSABLE_ENTITY => {
-                if self.message.contains("invalid type") {
-                    let message_shards: Vec<&str> = self.message.split(':').collect();
-                    let field_name = message_shards[1].trim();
-                    let field_type_shards: Vec<&str> =
-                        message_shards[3].trim().split(' ').collect();
-                    let field_type = field_type_shards[0].trim();
-                    format!("Invalid data type {} for field {}.", field_type, field_name)
-                } else if self.message.contains("missing field") {
-                    let message_shards: Vec<&str> = self.message.split('`').collect();
-                    let field_name = message_shards[1].trim();
-                    format!("Missing required field {} in request body.", field_name)
-                } else {
-                    format!("Unable to process request body.")
-                }
-            }
-            _ => self.message,
-        };
+        if self.fields.is_empty() {
+            (
+                self.status,
+                Json(ErrorResponse {
+                    success: false,
+                    error: self.error,
+                }),
+            )
+                .into_response()
+        } else {
+            (
+                self.status,
+                Json(ValidationErrorResponse {
+                    success: false,
+                    error: self.error,
+                    fields: self.fields,
+                }),
+            )
+                .into_response()
+        }
+    }
+}
+
+// Resolve pagination query parameters into bounded limit and offset
+fn page(limit: Option<i64>, offset: Option<i64>) -> (i64, i64) {
+    (
+        limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE),
+        offset.unwrap_or(0).max(0),
+    )
+}
+
+async fn find_user(
+    state: &ServerState,
+    user_id: &Uuid,
+) -> Result<User, (StatusCode, Json<ErrorResponse>)> {
+    state.users.get_user_by_id(user_id).await?.ok_or_else(|| {
         (
-            self.status,
+            StatusCode::NOT_FOUND,
             Json(ErrorResponse {
                 success: false,
-                error: error_message,
+                error: "User not found.".to_string(),
             }),
         )
-            .into_response()
-    }
+    })
 }
 
 // Handler function for path '/'
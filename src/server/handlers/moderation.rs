@@ -0,0 +1,187 @@
+use super::admin::admin_action;
+use super::{find_user, page, CustomJson, CustomPath, CustomQuery};
+use crate::external::db::models::{Report, ReportAction, ReportStatus, UserRestriction};
+use crate::external::db::report::{ReportFollowUp, ReportResolution};
+use crate::external::db::user::UserRestrictionUpdate;
+use crate::external::db::user_warning::NewUserWarning;
+use crate::server::auth::ModeratorUser;
+use crate::server::handlers::{ErrorResponse, SuccessResponse};
+use crate::server::ServerState;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use axum::Json;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use time::OffsetDateTime;
+use tracing::{debug, info};
+use uuid::Uuid;
+use validator::Validate;
+
+#[derive(Clone, Debug, Deserialize, Validate)]
+pub struct ListReportsSchema {
+    status: Option<ReportStatus>,
+    #[validate(range(min = 1, max = 200, message = "Limit must be between 1 and 200."))]
+    limit: Option<i64>,
+    #[validate(range(min = 0, message = "Offset must not be negative."))]
+    offset: Option<i64>,
+}
+
+#[derive(Clone, Debug, Deserialize, Validate)]
+pub struct ResolveReportSchema {
+    action: ReportAction,
+    #[validate(length(max = 1000, message = "Note must not exceed 1000 characters."))]
+    note: Option<String>,
+    // Only used by the suspend action, suspension never lifts by itself when not provided
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    suspension_expires_at: Option<OffsetDateTime>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ResolveReportResponse {
+    message: String,
+}
+
+// Handler function for path '/api/v1/moderation/reports'
+#[tracing::instrument(skip(state, _moderator))]
+pub async fn list_reports_handler(
+    State(state): State<Arc<ServerState>>,
+    ModeratorUser(_moderator): ModeratorUser,
+    CustomQuery(params): CustomQuery<ListReportsSchema>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ErrorResponse>)> {
+    info!("received request");
+    let (limit, offset) = page(params.limit, params.offset);
+    // The queue shows pending reports unless asked otherwise
+    let status = params.status.unwrap_or(ReportStatus::Pending);
+    let reports = state.reports.list_reports(status, limit, offset).await?;
+
+    Ok((
+        StatusCode::OK,
+        Json(SuccessResponse::<Vec<Report>> {
+            success: true,
+            result: reports,
+        }),
+    ))
+}
+
+// Handler function for path '/api/v1/moderation/reports/:report_id/resolve'
+#[tracing::instrument(skip(state, moderator))]
+pub async fn resolve_report_handler(
+    State(state): State<Arc<ServerState>>,
+    ModeratorUser(moderator): ModeratorUser,
+    CustomPath(report_id): CustomPath<Uuid>,
+    CustomJson(body): CustomJson<ResolveReportSchema>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ErrorResponse>)> {
+    info!("received request");
+    let already_resolved = || {
+        (
+            StatusCode::CONFLICT,
+            Json(ErrorResponse {
+                success: false,
+                error: "Report has already been resolved.".to_string(),
+            }),
+        )
+    };
+
+    let report = state.reports.get_report(&report_id).await?.ok_or_else(|| {
+        (
+            StatusCode::NOT_FOUND,
+            Json(ErrorResponse {
+                success: false,
+                error: "Report not found.".to_string(),
+            }),
+        )
+    })?;
+    if report.status != ReportStatus::Pending {
+        return Err(already_resolved());
+    }
+
+    let target_user = find_user(&state, &report.target_user_id).await?;
+    // Moderators can only take action against users with a lower role than their own
+    if body.action != ReportAction::Dismiss && target_user.role >= moderator.role {
+        return Err((
+            StatusCode::FORBIDDEN,
+            Json(ErrorResponse {
+                success: false,
+                error: "Insufficient permission.".to_string(),
+            }),
+        ));
+    }
+
+    let reason = body
+        .note
+        .clone()
+        .unwrap_or_else(|| format!("Reported for {:?}.", report.category));
+    let (status, follow_up, message) = match body.action {
+        ReportAction::Dismiss => (
+            ReportStatus::Dismissed,
+            ReportFollowUp::None,
+            "Report dismissed.",
+        ),
+        ReportAction::Warn => (
+            ReportStatus::Actioned,
+            ReportFollowUp::Warn(NewUserWarning {
+                user_id: target_user.id,
+                moderator_id: moderator.id,
+                report_id: Some(report.id),
+                reason,
+            }),
+            "User warned.",
+        ),
+        // Suspended users lose their existing sessions immediately
+        ReportAction::Suspend => (
+            ReportStatus::Actioned,
+            ReportFollowUp::Suspend {
+                user_id: target_user.id,
+                audit_log: admin_action(
+                    &moderator,
+                    "suspend_user",
+                    target_user.id,
+                    serde_json::json!({
+                        "reason": reason,
+                        "expires_at": body
+                            .suspension_expires_at
+                            .map(|expires_at| expires_at.unix_timestamp()),
+                        "report_id": report.id,
+                    }),
+                ),
+                update: UserRestrictionUpdate {
+                    restriction: UserRestriction::Suspended,
+                    reason,
+                    expires_at: body.suspension_expires_at,
+                },
+            },
+            "User suspended.",
+        ),
+    };
+
+    // Report resolution and its follow-up action either both happen or neither does
+    debug!("going to mark report as resolved");
+    let is_resolved = state
+        .reports
+        .resolve_report(
+            &report.id,
+            ReportResolution {
+                status,
+                action: body.action,
+                moderator_id: moderator.id,
+                moderator_note: body.note,
+            },
+            follow_up,
+        )
+        .await?;
+    // Another moderator resolved the same report in the meantime
+    if !is_resolved {
+        return Err(already_resolved());
+    }
+
+    Ok((
+        StatusCode::OK,
+        Json(SuccessResponse::<ResolveReportResponse> {
+            success: true,
+            result: ResolveReportResponse {
+                message: message.to_string(),
+            },
+        }),
+    ))
+}
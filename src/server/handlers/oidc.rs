@@ -0,0 +1,377 @@
+use super::user::complete_login;
+use super::{CustomPath, CustomQuery};
+use crate::external::db::models::User;
+use crate::external::db::user_identity::NewUserIdentity;
+use crate::logger::Secret;
+use crate::server::auth::{self, AuthUser};
+use crate::server::handlers::{error_response, ErrorResponse, SuccessResponse};
+use crate::server::login_security::ClientInfo;
+use crate::server::oidc::{self, IdTokenClaims, LoginState, ProviderConfig};
+use crate::server::validation::normalize_email;
+use crate::server::ServerState;
+use axum::extract::State;
+use axum::http::{header, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use axum_extra::extract::CookieJar;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use time::OffsetDateTime;
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+use validator::Validate;
+
+// Column sizes of the user table
+const EMAIL_MAX_LENGTH: usize = 100;
+const NAME_MAX_LENGTH: usize = 50;
+
+// Query string the provider redirects back with, either a code or an error
+#[derive(Clone, Debug, Deserialize, Validate)]
+pub struct CallbackSchema {
+    code: Option<Secret<String>>,
+    state: Option<String>,
+    error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct IdentityResponse {
+    provider: String,
+    email: Option<String>,
+    #[serde(with = "time::serde::rfc3339")]
+    created_at: OffsetDateTime,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LinkResponse {
+    message: String,
+}
+
+fn invalid_state() -> (StatusCode, Json<ErrorResponse>) {
+    error_response(
+        StatusCode::UNAUTHORIZED,
+        "Invalid or expired sign-in state.",
+    )
+}
+
+// Redirect the browser to the provider, the state cookie is checked again on the callback
+async fn redirect_to_provider(
+    state: &ServerState,
+    provider: &str,
+    link_user_id: Option<Uuid>,
+) -> Result<Response, (StatusCode, Json<ErrorResponse>)> {
+    let config = state.oidc.provider_config(provider)?;
+    let login_state = LoginState::new(&config.name, link_user_id);
+    let authorization_url = state.oidc.authorization_url(config, &login_state).await?;
+    let cookie = oidc::state_cookie(&login_state)?;
+
+    Ok((
+        StatusCode::FOUND,
+        [
+            (header::LOCATION, authorization_url),
+            (header::SET_COOKIE, cookie.to_string()),
+        ],
+    )
+        .into_response())
+}
+
+// Only an email the provider verified may be used to find or create a user
+fn verified_email(claims: &IdTokenClaims) -> Result<String, (StatusCode, Json<ErrorResponse>)> {
+    let email = claims
+        .email
+        .as_deref()
+        .map(normalize_email)
+        .filter(|email| !email.is_empty())
+        .ok_or_else(|| {
+            error_response(
+                StatusCode::FORBIDDEN,
+                "Identity provider did not share an email address.",
+            )
+        })?;
+    if !claims.email_verified {
+        return Err(error_response(
+            StatusCode::FORBIDDEN,
+            "Email address is not verified by the identity provider.",
+        ));
+    }
+    if email.len() > EMAIL_MAX_LENGTH {
+        return Err(error_response(
+            StatusCode::FORBIDDEN,
+            "Email address must not exceed 100 characters.",
+        ));
+    }
+    Ok(email)
+}
+
+// Resolve the user of the provider account
+// Unknown accounts are linked to the user with the same verified email, or a new verified user is created
+async fn find_or_create_user(
+    state: &ServerState,
+    config: &ProviderConfig,
+    claims: IdTokenClaims,
+) -> Result<User, (StatusCode, Json<ErrorResponse>)> {
+    let user_id = match state
+        .identities
+        .get_identity(&config.name, &claims.sub)
+        .await?
+    {
+        Some(identity) => identity.user_id,
+        None => {
+            let email = verified_email(&claims)?;
+            match state.users.get_user_by_email(&email).await? {
+                Some(user) => {
+                    debug!("going to link identity to existing user");
+                    let identity = NewUserIdentity {
+                        user_id: user.id,
+                        provider: config.name.clone(),
+                        subject: claims.sub,
+                        email: Some(email),
+                    };
+                    // Someone else may have registered the email without being able to activate it
+                    if !state
+                        .identities
+                        .link_identity(identity, !user.verified)
+                        .await?
+                    {
+                        return Err(error_response(
+                            StatusCode::CONFLICT,
+                            "User already linked another account of the identity provider.",
+                        ));
+                    }
+                    user.id
+                }
+                None => {
+                    debug!("going to register user from identity provider");
+                    let name = claims
+                        .name
+                        .map(|name| {
+                            name.trim()
+                                .chars()
+                                .take(NAME_MAX_LENGTH)
+                                .collect::<String>()
+                        })
+                        .filter(|name| !name.is_empty());
+                    state
+                        .identities
+                        .register_identity_user(email, name, config.name.clone(), claims.sub)
+                        .await?
+                }
+            }
+        }
+    };
+
+    state
+        .users
+        .get_user_by_id(&user_id)
+        .await?
+        .ok_or_else(invalid_state)
+}
+
+// Sign in as the user of the provider account, two-factor authentication still applies
+async fn sign_in(
+    state: &ServerState,
+    config: &ProviderConfig,
+    claims: IdTokenClaims,
+    client: &ClientInfo,
+) -> Result<Response, (StatusCode, Json<ErrorResponse>)> {
+    let user = find_or_create_user(state, config, claims).await?;
+    if !user.verified {
+        return Err(error_response(
+            StatusCode::FORBIDDEN,
+            "User is not activated.",
+        ));
+    }
+    auth::ensure_not_restricted(&user)?;
+
+    complete_login(state, &user, client).await
+}
+
+// Link the provider account to the user who started the flow
+async fn link(
+    state: &ServerState,
+    config: &ProviderConfig,
+    user_id: Uuid,
+    claims: IdTokenClaims,
+) -> Result<Response, (StatusCode, Json<ErrorResponse>)> {
+    let user = state
+        .users
+        .get_user_by_id(&user_id)
+        .await?
+        .ok_or_else(invalid_state)?;
+    auth::ensure_not_restricted(&user)?;
+
+    if let Some(identity) = state
+        .identities
+        .get_identity(&config.name, &claims.sub)
+        .await?
+    {
+        if identity.user_id != user.id {
+            warn!("identity is already linked to another user");
+            return Err(error_response(
+                StatusCode::CONFLICT,
+                "Account is already linked to another user.",
+            ));
+        }
+    }
+    let identity = NewUserIdentity {
+        user_id: user.id,
+        provider: config.name.clone(),
+        subject: claims.sub,
+        email: claims.email.as_deref().map(normalize_email),
+    };
+    if !state.identities.link_identity(identity, false).await? {
+        return Err(error_response(
+            StatusCode::CONFLICT,
+            "Identity provider is already linked.",
+        ));
+    }
+
+    Ok((
+        StatusCode::OK,
+        Json(SuccessResponse::<LinkResponse> {
+            success: true,
+            result: LinkResponse {
+                message: "Identity provider linked.".to_string(),
+            },
+        }),
+    )
+        .into_response())
+}
+
+// Handler function for path '/api/v1/user/oidc/:provider/authorize'
+#[tracing::instrument(skip(state))]
+pub async fn authorize_handler(
+    State(state): State<Arc<ServerState>>,
+    CustomPath(provider): CustomPath<String>,
+) -> Result<Response, (StatusCode, Json<ErrorResponse>)> {
+    info!("received request");
+    redirect_to_provider(&state, &provider, None).await
+}
+
+// Handler function for path '/api/v1/user/oidc/:provider/link'
+#[tracing::instrument(skip(state, user))]
+pub async fn link_handler(
+    State(state): State<Arc<ServerState>>,
+    AuthUser(user): AuthUser,
+    CustomPath(provider): CustomPath<String>,
+) -> Result<Response, (StatusCode, Json<ErrorResponse>)> {
+    info!("received request");
+    redirect_to_provider(&state, &provider, Some(user.id)).await
+}
+
+// Handler function for path '/api/v1/user/oidc/:provider/callback'
+#[tracing::instrument(skip(state, client, cookies, params))]
+pub async fn callback_handler(
+    State(state): State<Arc<ServerState>>,
+    CustomPath(provider): CustomPath<String>,
+    client: ClientInfo,
+    cookies: CookieJar,
+    CustomQuery(params): CustomQuery<CallbackSchema>,
+) -> Result<Response, (StatusCode, Json<ErrorResponse>)> {
+    info!("received request");
+    let config = state.oidc.provider_config(&provider)?;
+    let login_state = cookies
+        .get(oidc::STATE_COOKIE)
+        .ok_or_else(invalid_state)
+        .and_then(|cookie| oidc::decode_state_cookie(cookie.value()))?;
+    // The state proves that the callback belongs to the sign-in started by this browser
+    if login_state.provider != config.name || params.state.as_ref() != Some(&login_state.state) {
+        warn!("oidc state does not match");
+        return Err(invalid_state());
+    }
+    if let Some(error) = params.error {
+        debug!("identity provider returned error {}", error);
+        return Err(error_response(
+            StatusCode::UNAUTHORIZED,
+            "Sign-in was cancelled or rejected by the identity provider.",
+        ));
+    }
+    let code = params
+        .code
+        .ok_or_else(|| error_response(StatusCode::BAD_REQUEST, "Missing authorization code."))?;
+
+    let claims = state
+        .oidc
+        .authenticate(config, code.expose(), &login_state)
+        .await?;
+    let mut response = match login_state.link_user_id {
+        Some(user_id) => link(&state, config, user_id, claims).await?,
+        None => sign_in(&state, config, claims, &client).await?,
+    };
+
+    response.headers_mut().append(
+        header::SET_COOKIE,
+        oidc::removal_state_cookie().to_string().parse().unwrap(),
+    );
+    Ok(response)
+}
+
+// Handler function for path '/api/v1/user/oidc'
+#[tracing::instrument(skip(state, user))]
+pub async fn list_identities_handler(
+    State(state): State<Arc<ServerState>>,
+    AuthUser(user): AuthUser,
+) -> Result<impl IntoResponse, (StatusCode, Json<ErrorResponse>)> {
+    info!("received request");
+    let identities = state
+        .identities
+        .list_identities(&user.id)
+        .await?
+        .into_iter()
+        .map(|identity| IdentityResponse {
+            provider: identity.provider,
+            email: identity.email,
+            created_at: identity.created_at,
+        })
+        .collect();
+
+    Ok((
+        StatusCode::OK,
+        Json(SuccessResponse::<Vec<IdentityResponse>> {
+            success: true,
+            result: identities,
+        }),
+    ))
+}
+
+// Handler function for path '/api/v1/user/oidc/:provider'
+#[tracing::instrument(skip(state, user))]
+pub async fn unlink_handler(
+    State(state): State<Arc<ServerState>>,
+    AuthUser(user): AuthUser,
+    CustomPath(provider): CustomPath<String>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ErrorResponse>)> {
+    info!("received request");
+    let identities = state.identities.list_identities(&user.id).await?;
+    if !identities
+        .iter()
+        .any(|identity| identity.provider == provider)
+    {
+        return Err(error_response(
+            StatusCode::NOT_FOUND,
+            "Identity provider is not linked.",
+        ));
+    }
+    // A user without password would have no way left to sign in
+    if user.password.is_none() && identities.len() == 1 {
+        return Err(error_response(
+            StatusCode::CONFLICT,
+            "The only sign-in method of the user cannot be unlinked.",
+        ));
+    }
+
+    debug!("going to unlink identity provider");
+    state
+        .identities
+        .unlink_identity(&user.id, &provider)
+        .await?;
+
+    Ok((
+        StatusCode::OK,
+        Json(SuccessResponse::<LinkResponse> {
+            success: true,
+            result: LinkResponse {
+                message: "Identity provider unlinked.".to_string(),
+            },
+        }),
+    ))
+}
@@ -0,0 +1,284 @@
+use super::{CustomError, FieldError};
+use axum::extract::path::ErrorKind;
+use axum::extract::rejection::{BytesRejection, FailedToBufferBody, PathRejection};
+use axum::http::StatusCode;
+use serde_json::error::Category;
+use validator::ValidationErrors;
+
+type JsonError = serde_path_to_error::Error<serde_json::Error>;
+type QueryError = serde_path_to_error::Error<serde_urlencoded::de::Error>;
+
+// Map serde's standard error messages to a stable code for clients
+fn error_code(reason: &str) -> &'static str {
+    if reason.starts_with("missing field") {
+        "missing_field"
+    } else if reason.starts_with("invalid type") {
+        "invalid_type"
+    } else if reason.starts_with("invalid value") {
+        "invalid_value"
+    } else if reason.starts_with("invalid length") {
+        "invalid_length"
+    } else if reason.starts_with("unknown variant") {
+        "unknown_variant"
+    } else if reason.starts_with("unknown field") {
+        "unknown_field"
+    } else {
+        "invalid"
+    }
+}
+
+// Extract the field name out of "missing field `name`"
+fn missing_field_name(reason: &str) -> Option<&str> {
+    reason
+        .strip_prefix("missing field `")
+        .and_then(|rest| rest.split('`').next())
+        .filter(|name| !name.is_empty())
+}
+
+// serde_json appends the position to its message, which is reported separately
+fn strip_position(error: &serde_json::Error) -> String {
+    let message = error.to_string();
+    let position = format!(" at line {} column {}", error.line(), error.column());
+    message
+        .strip_suffix(&position)
+        .map(|reason| reason.to_string())
+        .unwrap_or(message)
+}
+
+fn join_field(parent: &str, name: &str) -> String {
+    if parent.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}.{}", parent, name)
+    }
+}
+
+fn field_error(field: String, reason: &str, location: &str) -> (String, FieldError) {
+    let code = error_code(reason);
+    let message = match code {
+        "missing_field" => format!("Missing required field {} in {}.", field, location),
+        _ => format!(
+            "Invalid value for field {} in {}: {}.",
+            field, location, reason
+        ),
+    };
+    (
+        message.clone(),
+        FieldError {
+            field,
+            code: code.to_string(),
+            message,
+        },
+    )
+}
+
+fn json_data_error(error: &JsonError) -> CustomError {
+    let reason = strip_position(error.inner());
+    // serde_path_to_error reports the root object as "."
+    let path = match error.path().to_string() {
+        path if path == "." => String::new(),
+        path => path,
+    };
+    let field = match missing_field_name(&reason) {
+        Some(name) => join_field(&path, name),
+        None if path.is_empty() => "body".to_string(),
+        None => path,
+    };
+    let (message, field_error) = field_error(field, &reason, "request body");
+
+    CustomError {
+        status: StatusCode::UNPROCESSABLE_ENTITY,
+        message: error.to_string(),
+        error: message,
+        fields: vec![field_error],
+    }
+}
+
+fn json_syntax_error(error: &JsonError) -> CustomError {
+    let inner = error.inner();
+    CustomError {
+        status: StatusCode::BAD_REQUEST,
+        message: error.to_string(),
+        error: format!(
+            "Malformed JSON in request body at line {} column {}: {}.",
+            inner.line(),
+            inner.column(),
+            strip_position(inner)
+        ),
+        fields: Vec::new(),
+    }
+}
+
+fn bytes_rejection(rejection: BytesRejection) -> CustomError {
+    let message = rejection.body_text();
+    match rejection {
+        BytesRejection::FailedToBufferBody(FailedToBufferBody::LengthLimitError(_)) => {
+            CustomError::new(
+                StatusCode::PAYLOAD_TOO_LARGE,
+                message,
+                "Request body is too large.",
+            )
+        }
+        _ => CustomError::new(
+            StatusCode::BAD_REQUEST,
+            message,
+            "Unable to read request body.",
+        ),
+    }
+}
+
+impl CustomError {
+    fn new(status: StatusCode, message: String, error: &str) -> Self {
+        Self {
+            status,
+            message,
+            error: error.to_string(),
+            fields: Vec::new(),
+        }
+    }
+
+    pub(super) fn missing_json_content_type() -> Self {
+        CustomError::new(
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            "Expected request with `Content-Type: application/json`".to_string(),
+            "Expected request with Content-Type: application/json.",
+        )
+    }
+}
+
+impl From<JsonError> for CustomError {
+    fn from(error: JsonError) -> Self {
+        match error.inner().classify() {
+            // Valid JSON which doesn't match the schema
+            Category::Data => json_data_error(&error),
+            // Invalid JSON or a body which ends too early
+            Category::Syntax | Category::Eof | Category::Io => json_syntax_error(&error),
+        }
+    }
+}
+
+impl From<BytesRejection> for CustomError {
+    fn from(rejection: BytesRejection) -> Self {
+        bytes_rejection(rejection)
+    }
+}
+
+impl From<QueryError> for CustomError {
+    fn from(error: QueryError) -> Self {
+        let reason = error.inner().to_string();
+        let path = match error.path().to_string() {
+            path if path == "." => String::new(),
+            path => path,
+        };
+        let field = match missing_field_name(&reason) {
+            Some(name) => join_field(&path, name),
+            None if path.is_empty() => "query".to_string(),
+            None => path,
+        };
+        let (message, field_error) = field_error(field, &reason, "query");
+
+        CustomError {
+            status: StatusCode::BAD_REQUEST,
+            message: error.to_string(),
+            error: message,
+            fields: vec![field_error],
+        }
+    }
+}
+
+impl From<PathRejection> for CustomError {
+    fn from(rejection: PathRejection) -> Self {
+        let message = rejection.body_text();
+        match rejection {
+            PathRejection::FailedToDeserializePathParams(error) => match error.into_kind() {
+                ErrorKind::ParseErrorAtKey {
+                    key,
+                    value,
+                    expected_type,
+                } => {
+                    let error = format!(
+                        "Invalid value {} for path parameter {}, expected {}.",
+                        value, key, expected_type
+                    );
+                    CustomError {
+                        status: StatusCode::BAD_REQUEST,
+                        message,
+                        error: error.clone(),
+                        fields: vec![FieldError {
+                            field: key,
+                            code: "invalid_type".to_string(),
+                            message: error,
+                        }],
+                    }
+                }
+                ErrorKind::ParseErrorAtIndex {
+                    value,
+                    expected_type,
+                    ..
+                }
+                | ErrorKind::ParseError {
+                    value,
+                    expected_type,
+                } => CustomError::new(
+                    StatusCode::BAD_REQUEST,
+                    message,
+                    &format!(
+                        "Invalid value {} in path, expected {}.",
+                        value, expected_type
+                    ),
+                ),
+                ErrorKind::InvalidUtf8InPathParam { key } => CustomError::new(
+                    StatusCode::BAD_REQUEST,
+                    message,
+                    &format!("Path parameter {} is not valid UTF-8.", key),
+                ),
+                // Custom deserialize errors such as a malformed uuid end up here
+                ErrorKind::Message(reason) => CustomError::new(
+                    StatusCode::BAD_REQUEST,
+                    message,
+                    &format!("Invalid path parameter: {}.", reason),
+                ),
+                // Remaining kinds are caused by a mismatch between route and handler, not by the client
+                _ => CustomError::new(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    message,
+                    "Internal server error.",
+                ),
+            },
+            _ => CustomError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                message,
+                "Internal server error.",
+            ),
+        }
+    }
+}
+
+impl From<ValidationErrors> for CustomError {
+    fn from(errors: ValidationErrors) -> Self {
+        let mut fields: Vec<FieldError> = errors
+            .field_errors()
+            .into_iter()
+            .flat_map(|(field, field_errors)| {
+                field_errors.iter().map(move |field_error| FieldError {
+                    field: field.to_string(),
+                    code: field_error.code.to_string(),
+                    message: field_error
+                        .message
+                        .as_ref()
+                        .map(|message| message.to_string())
+                        .unwrap_or_else(|| format!("Invalid value for field {}.", field)),
+                })
+            })
+            .collect();
+        // HashMap iteration order is random, keep the response stable for clients
+        fields.sort_by(|a, b| a.field.cmp(&b.field));
+
+        Self {
+            status: StatusCode::UNPROCESSABLE_ENTITY,
+            message: errors.to_string(),
+            error: "Request validation failed.".to_string(),
+            fields,
+        }
+    }
+}
@@ -0,0 +1,71 @@
+use super::{find_user, CustomJson};
+use crate::external::db::models::ReportCategory;
+use crate::external::db::report::NewReport;
+use crate::server::auth::AuthUser;
+use crate::server::handlers::{ErrorResponse, SuccessResponse};
+use crate::server::ServerState;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use axum::Json;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tracing::{debug, info};
+use uuid::Uuid;
+use validator::Validate;
+
+#[derive(Clone, Debug, Deserialize, Validate)]
+pub struct CreateReportSchema {
+    target_user_id: Uuid,
+    category: ReportCategory,
+    #[validate(length(max = 1000, message = "Description must not exceed 1000 characters."))]
+    description: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CreateReportResponse {
+    message: String,
+    report_id: Uuid,
+}
+
+// Handler function for path '/api/v1/reports'
+#[tracing::instrument(skip(state, reporter))]
+pub async fn create_report_handler(
+    State(state): State<Arc<ServerState>>,
+    AuthUser(reporter): AuthUser,
+    CustomJson(body): CustomJson<CreateReportSchema>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ErrorResponse>)> {
+    info!("received request");
+    if body.target_user_id == reporter.id {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(ErrorResponse {
+                success: false,
+                error: "User cannot report themselves.".to_string(),
+            }),
+        ));
+    }
+    let target_user = find_user(&state, &body.target_user_id).await?;
+
+    debug!("going to insert new report record into database");
+    let report_id = state
+        .reports
+        .insert_report(NewReport {
+            reporter_id: reporter.id,
+            target_user_id: target_user.id,
+            category: body.category,
+            description: body.description,
+        })
+        .await?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(SuccessResponse::<CreateReportResponse> {
+            success: true,
+            result: CreateReportResponse {
+                message: "Report submitted.".to_string(),
+                report_id,
+            },
+        }),
+    ))
+}
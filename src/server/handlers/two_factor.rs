@@ -0,0 +1,292 @@
+use super::{CustomJson, CustomQuery};
+use crate::external::db::models::UserTwoFactor;
+use crate::logger::Secret;
+use crate::server::auth::AuthUser;
+use crate::server::handlers::{error_response, ErrorResponse, SuccessResponse};
+use crate::server::two_factor;
+use crate::server::ServerState;
+use axum::extract::State;
+use axum::http::{header, StatusCode};
+use axum::response::IntoResponse;
+use axum::Json;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tracing::{debug, info};
+use uuid::Uuid;
+use validator::Validate;
+
+#[derive(Clone, Debug, Deserialize, Validate)]
+pub struct TwoFactorCodeSchema {
+    // Either a 6 digit TOTP code or a recovery code
+    #[validate(length(
+        min = 6,
+        max = 32,
+        message = "Code must be between 6 and 32 characters long."
+    ))]
+    code: Secret<String>,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum QrCodeFormat {
+    #[default]
+    Png,
+    Svg,
+}
+
+#[derive(Clone, Debug, Deserialize, Validate)]
+pub struct QrCodeSchema {
+    #[serde(default)]
+    format: QrCodeFormat,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TwoFactorStatusResponse {
+    enabled: bool,
+    recovery_codes_remaining: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct EnrollResponse {
+    message: String,
+    // For authenticator apps that cannot scan the QR code
+    secret: String,
+    otpauth_uri: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ConfirmResponse {
+    message: String,
+    // Shown only once, the user has to store them somewhere safe
+    recovery_codes: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DisableResponse {
+    message: String,
+}
+
+fn invalid_code() -> (StatusCode, Json<ErrorResponse>) {
+    error_response(StatusCode::UNAUTHORIZED, "Invalid two-factor code.")
+}
+
+// Check a TOTP code or an unused recovery code, either of them can only be used once
+pub async fn verify_second_factor(
+    state: &ServerState,
+    user_id: &Uuid,
+    two_factor: &UserTwoFactor,
+    code: &str,
+) -> Result<bool, (StatusCode, Json<ErrorResponse>)> {
+    let code = code.trim();
+    if two_factor::is_totp_code(code) {
+        match two_factor::verify_code(&two_factor.secret, code, two_factor.last_used_step)? {
+            Some(step) => state.two_factor.use_code_step(user_id, step).await,
+            None => Ok(false),
+        }
+    } else {
+        debug!("going to check recovery code");
+        state
+            .two_factor
+            .use_recovery_code(user_id, &two_factor::hash_recovery_code(code))
+            .await
+    }
+}
+
+async fn pending_enrollment(
+    state: &ServerState,
+    user_id: &Uuid,
+) -> Result<UserTwoFactor, (StatusCode, Json<ErrorResponse>)> {
+    match state.two_factor.get_two_factor(user_id).await? {
+        Some(two_factor) if !two_factor.enabled => Ok(two_factor),
+        Some(_) => Err(error_response(
+            StatusCode::CONFLICT,
+            "Two-factor authentication is already enabled.",
+        )),
+        None => Err(error_response(
+            StatusCode::NOT_FOUND,
+            "Two-factor enrollment has not been started.",
+        )),
+    }
+}
+
+// Handler function for path '/api/v1/user/2fa'
+#[tracing::instrument(skip(state, user))]
+pub async fn status_handler(
+    State(state): State<Arc<ServerState>>,
+    AuthUser(user): AuthUser,
+) -> Result<impl IntoResponse, (StatusCode, Json<ErrorResponse>)> {
+    info!("received request");
+    let enabled = state
+        .two_factor
+        .get_two_factor(&user.id)
+        .await?
+        .is_some_and(|two_factor| two_factor.enabled);
+    let recovery_codes_remaining = state
+        .two_factor
+        .count_unused_recovery_codes(&user.id)
+        .await?;
+
+    Ok((
+        StatusCode::OK,
+        Json(SuccessResponse::<TwoFactorStatusResponse> {
+            success: true,
+            result: TwoFactorStatusResponse {
+                enabled,
+                recovery_codes_remaining,
+            },
+        }),
+    ))
+}
+
+// Handler function for path '/api/v1/user/2fa/enroll'
+// Starting again replaces the pending secret, e.g. when the QR code was never scanned
+#[tracing::instrument(skip(state, user))]
+pub async fn enroll_handler(
+    State(state): State<Arc<ServerState>>,
+    AuthUser(user): AuthUser,
+) -> Result<impl IntoResponse, (StatusCode, Json<ErrorResponse>)> {
+    info!("received request");
+    let is_enabled = state
+        .two_factor
+        .get_two_factor(&user.id)
+        .await?
+        .is_some_and(|two_factor| two_factor.enabled);
+    if is_enabled {
+        return Err(error_response(
+            StatusCode::CONFLICT,
+            "Two-factor authentication is already enabled.",
+        ));
+    }
+
+    debug!("going to store pending two-factor secret");
+    let secret = two_factor::generate_secret();
+    let otpauth_uri = two_factor::otpauth_uri(&secret, &user.email)?;
+    state
+        .two_factor
+        .start_enrollment(&user.id, secret.clone())
+        .await?;
+
+    Ok((
+        StatusCode::OK,
+        Json(SuccessResponse::<EnrollResponse> {
+            success: true,
+            result: EnrollResponse {
+                message: "Scan the QR code and confirm with a code from the authenticator app."
+                    .to_string(),
+                secret: secret.into_inner(),
+                otpauth_uri,
+            },
+        }),
+    ))
+}
+
+// Handler function for path '/api/v1/user/2fa/enroll/qr'
+#[tracing::instrument(skip(state, user))]
+pub async fn qr_code_handler(
+    State(state): State<Arc<ServerState>>,
+    AuthUser(user): AuthUser,
+    CustomQuery(params): CustomQuery<QrCodeSchema>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ErrorResponse>)> {
+    info!("received request");
+    let two_factor = pending_enrollment(&state, &user.id).await?;
+    let otpauth_uri = two_factor::otpauth_uri(&two_factor.secret, &user.email)?;
+
+    debug!("going to render qr code");
+    let (content_type, body) = match params.format {
+        QrCodeFormat::Png => ("image/png", two_factor::qr_code_png(&otpauth_uri)?),
+        QrCodeFormat::Svg => (
+            "image/svg+xml",
+            two_factor::qr_code_svg(&otpauth_uri)?.into_bytes(),
+        ),
+    };
+    // The QR code contains the secret and must not be kept by any cache
+    Ok((
+        [
+            (header::CONTENT_TYPE, content_type),
+            (header::CACHE_CONTROL, "no-store"),
+        ],
+        body,
+    ))
+}
+
+// Handler function for path '/api/v1/user/2fa/confirm'
+#[tracing::instrument(skip(state, user, body))]
+pub async fn confirm_handler(
+    State(state): State<Arc<ServerState>>,
+    AuthUser(user): AuthUser,
+    CustomJson(body): CustomJson<TwoFactorCodeSchema>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ErrorResponse>)> {
+    info!("received request");
+    let two_factor = pending_enrollment(&state, &user.id).await?;
+    // Recovery codes don't exist yet, only a TOTP code proves that the authenticator app is set up
+    let code = body.code.expose().trim();
+    if !two_factor::is_totp_code(code) {
+        return Err(invalid_code());
+    }
+    let step = two_factor::verify_code(&two_factor.secret, code, two_factor.last_used_step)?
+        .ok_or_else(invalid_code)?;
+
+    debug!("going to enable two-factor authentication");
+    let recovery_codes = two_factor::generate_recovery_codes();
+    let recovery_code_hashes = recovery_codes
+        .iter()
+        .map(|code| two_factor::hash_recovery_code(code))
+        .collect();
+    let is_enabled = state
+        .two_factor
+        .enable_two_factor(&user.id, step, recovery_code_hashes)
+        .await?;
+    if !is_enabled {
+        return Err(error_response(
+            StatusCode::CONFLICT,
+            "Two-factor authentication is already enabled.",
+        ));
+    }
+
+    Ok((
+        StatusCode::OK,
+        Json(SuccessResponse::<ConfirmResponse> {
+            success: true,
+            result: ConfirmResponse {
+                message: "Two-factor authentication enabled.".to_string(),
+                recovery_codes,
+            },
+        }),
+    ))
+}
+
+// Handler function for path '/api/v1/user/2fa/disable'
+// A valid code is required so that a stolen session alone cannot turn two-factor authentication off
+#[tracing::instrument(skip(state, user, body))]
+pub async fn disable_handler(
+    State(state): State<Arc<ServerState>>,
+    AuthUser(user): AuthUser,
+    CustomJson(body): CustomJson<TwoFactorCodeSchema>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ErrorResponse>)> {
+    info!("received request");
+    let two_factor = match state.two_factor.get_two_factor(&user.id).await? {
+        Some(two_factor) if two_factor.enabled => two_factor,
+        _ => {
+            return Err(error_response(
+                StatusCode::BAD_REQUEST,
+                "Two-factor authentication is not enabled.",
+            ))
+        }
+    };
+    if !verify_second_factor(&state, &user.id, &two_factor, body.code.expose()).await? {
+        return Err(invalid_code());
+    }
+
+    debug!("going to disable two-factor authentication");
+    state.two_factor.disable_two_factor(&user.id).await?;
+
+    Ok((
+        StatusCode::OK,
+        Json(SuccessResponse::<DisableResponse> {
+            success: true,
+            result: DisableResponse {
+                message: "Two-factor authentication disabled.".to_string(),
+            },
+        }),
+    ))
+}
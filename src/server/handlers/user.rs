@@ -1,41 +1,72 @@
 use super::{CustomJson, CustomQuery};
-use crate::db;
+use crate::external::db::models::{LoginFailureReason, User};
 use crate::external::db::user::NewUser;
-use crate::external::db::user_verification::NewUserVerification;
+use crate::external::db::user_login::NewUserLoginHistory;
+use crate::logger::Secret;
+use crate::server::auth;
 use crate::server::handlers::{ErrorResponse, SuccessResponse};
+use crate::server::login_security::{self, ClientInfo};
+use crate::server::rate_limit::{self, RateLimitDecision};
+use crate::server::two_factor;
+use crate::server::validation::{deserialize_email, validate_password_strength};
+use crate::server::verification;
 use crate::server::ServerState;
 use axum::extract::State;
 use axum::http::{header, StatusCode};
-use axum::response::IntoResponse;
+use axum::response::{IntoResponse, Response};
 use axum::Json;
-use axum_extra::extract::cookie::{Cookie, SameSite};
-use bcrypt::{hash, DEFAULT_COST};
-use dotenvy::var;
-use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
-use rand::RngCore;
 use serde::{Deserialize, Serialize};
-use std::ops::Add;
 use std::sync::Arc;
-use time::{Duration, OffsetDateTime};
-use tracing::{debug, error, info};
+use time::OffsetDateTime;
+use tracing::{debug, error, info, warn};
 use uuid::Uuid;
+use validator::Validate;
 
-#[derive(Clone, Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize, Validate)]
 pub struct RegisterSchema {
+    #[serde(deserialize_with = "deserialize_email")]
+    #[validate(
+        email(message = "Invalid email address."),
+        length(max = 100, message = "Email must not exceed 100 characters.")
+    )]
     email: String,
-    password: String,
+    #[validate(
+        length(
+            min = 8,
+            max = 72,
+            message = "Password must be between 8 and 72 characters long."
+        ),
+        custom = "validate_password_strength"
+    )]
+    password: Secret<String>,
 }
 
-#[derive(Clone, Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize, Validate)]
 pub struct ActivateSchema {
-    token: String,
+    #[validate(length(
+        min = 1,
+        max = 1024,
+        message = "Token must be between 1 and 1024 characters long."
+    ))]
+    token: Secret<String>,
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
-pub struct Claims {
-    sub: Uuid,
-    iss: String,
-    exp: u64,
+// Password policy is not enforced on login so that users registered under an older policy can still login
+#[derive(Clone, Debug, Deserialize, Validate)]
+pub struct LoginSchema {
+    #[serde(deserialize_with = "deserialize_email")]
+    #[validate(length(
+        min = 1,
+        max = 100,
+        message = "Email must be between 1 and 100 characters long."
+    ))]
+    email: String,
+    #[validate(length(
+        min = 1,
+        max = 72,
+        message = "Password must be between 1 and 72 characters long."
+    ))]
+    password: Secret<String>,
 }
 
 #[derive(Debug, Serialize)]
@@ -49,6 +80,31 @@ pub struct ActivateResponse {
     message: String,
 }
 
+// Exchanges the challenge token from the password step for an access token
+#[derive(Clone, Debug, Deserialize, Validate)]
+pub struct LoginTwoFactorSchema {
+    #[validate(length(
+        min = 1,
+        max = 1024,
+        message = "Challenge token must be between 1 and 1024 characters long."
+    ))]
+    challenge_token: Secret<String>,
+    #[validate(length(
+        min = 6,
+        max = 32,
+        message = "Code must be between 6 and 32 characters long."
+    ))]
+    code: Secret<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LoginResponse {
+    message: String,
+    two_factor_required: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    challenge_token: Option<String>,
+}
+
 // Handler function for path '/api/v1/user/register'
 #[tracing::instrument]
 pub async fn register_handler(
@@ -56,7 +112,7 @@ pub async fn register_handler(
     CustomJson(body): CustomJson<RegisterSchema>,
 ) -> Result<impl IntoResponse, (StatusCode, Json<ErrorResponse>)> {
     info!("received request");
-    let is_user_exists = db::user::is_user_exists(&state.db, body.email.as_str()).await?;
+    let is_user_exists = state.users.is_user_exists(body.email.as_str()).await?;
 
     // Will not continue the registration if email already exists in database
     if is_user_exists {
@@ -64,139 +120,53 @@ pub async fn register_handler(
             StatusCode::CONFLICT,
             Json(ErrorResponse {
                 success: false,
-                error: format!("User already exists."),
+                error: "User already exists.".to_string(),
             }),
         ));
     }
 
     debug!("going to generate hashed password");
     // Generate hashed password for user
-    let hashed_password = hash(body.password, DEFAULT_COST).map_err(|error| {
-        error!("password hashing error. {}", error);
-        // Will not continue if there is error during password hashing process
-        return (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(ErrorResponse {
-                success: false,
-                error: format!("Internal server error. Please try to register again."),
-            }),
-        );
-    })?;
+    let hashed_password = state
+        .password_hasher
+        .hash(&body.password)
+        .await
+        .map_err(|error| {
+            error!("password hashing error. {}", error);
+            // Will not continue if there is error during password hashing process
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ErrorResponse {
+                    success: false,
+                    error: "Internal server error. Please try to register again.".to_string(),
+                }),
+            )
+        })?;
 
-    debug!("going to insert new user record into database");
-    // Insert a new user record into database
-    let user_id = db::user::insert_new_user(
-        &state.db,
-        NewUser {
-            email: body.email.clone(),
-            // We can safely unwrap this as we will already end the process in section above if we encounter hashing error
-            password: hashed_password,
-        },
-    )
-    .await?;
-
-    // Gather JWT access token related environment variable values
-    let token_iss = var("TOKEN_ISS").map_err(|_| {
-        error!("missing environment variable TOKEN_ISS");
-        return (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(ErrorResponse {
-                success: false,
-                error: format!("Internal server error. Please try to register again."),
-            }),
-        );
-    })?;
+    // Only the hash of the verification token is stored, the token itself is handed out once
+    let verification_token = verification::generate_token();
+    let verification_expires_at = OffsetDateTime::now_utc() + verification::TOKEN_LIFETIME;
 
-    let access_token_secret = var("ACCESS_TOKEN_SECRET").map_err(|_| {
-        error!("missing environment variable ACCESS_TOKEN_SECRET");
-        return (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(ErrorResponse {
-                success: false,
-                error: format!("Internal server error. Please try to register again."),
-            }),
-        );
-    })?;
+    debug!("going to insert new user record into database");
+    // User and verification records are inserted together so a failure never leaves a user who cannot activate
+    let user_id = state
+        .users
+        .register_user(
+            NewUser {
+                email: body.email.clone(),
+                password: hashed_password,
+            },
+            verification::hash_token(verification_token.expose()),
+            verification_expires_at,
+        )
+        .await?;
 
     debug!("constructing jwt access token");
     // Construct JWT access token
-    let access_token = encode(
-        &Header::default(),
-        &Claims {
-            sub: user_id,
-            iss: token_iss.clone(),
-            exp: OffsetDateTime::now_utc()
-                .add(Duration::minutes(5))
-                .unix_timestamp()
-                .unsigned_abs(),
-        },
-        &EncodingKey::from_secret(access_token_secret.as_ref()),
-    )
-    .map_err(|error| {
-        error!("jwt access token construction error. {}", error);
-        return (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(ErrorResponse {
-                success: false,
-                error: format!("Internal server error. Please try to register again."),
-            }),
-        );
-    })?;
-
-    // Generate a random 64 bytes long secret to sign the verification token for the user
-    let mut random_bytes = [0u8; 32];
-    rand::thread_rng().fill_bytes(&mut random_bytes);
-    let verification_secret = random_bytes
-        .iter()
-        .map(|byte| format!("{:x}", byte).to_string())
-        .collect::<Vec<String>>()
-        .join("");
-
-    debug!("inserting new user verification record into database");
-    // Insert a new user verification record
-    db::user_verification::insert_new_user_verification(
-        &state.db,
-        NewUserVerification {
-            user_id: user_id.clone(),
-            secret: verification_secret.clone(),
-        },
-    )
-    .await?;
-
-    debug!("constructing jwt verification token");
-    // Construct the verification token
-    let verification_token = encode(
-        &Header::default(),
-        &Claims {
-            sub: user_id,
-            iss: token_iss,
-            exp: OffsetDateTime::now_utc()
-                .add(Duration::minutes(5))
-                .unix_timestamp()
-                .unsigned_abs(),
-        },
-        &EncodingKey::from_secret(verification_secret.as_ref()),
-    )
-    .map_err(|error| {
-        error!("jwt verification token construction error. {}", error);
-        return (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(ErrorResponse {
-                success: false,
-                error: format!("Internal server error. Please try to register again."),
-            }),
-        );
-    })?;
+    let access_token = auth::encode_access_token(user_id)?;
 
     debug!("constructing cookie for JWT access token");
-    // Construct cookie for the JWT access token
-    let cookie = Cookie::build("token", access_token.to_owned())
-        .path("/")
-        .secure(false) // Forbid cookie from transmitting over simple HTTP
-        .http_only(true) // Blocks access of related cookie from client side
-        .same_site(SameSite::Lax) // SameSite 'none' has to be used together with secure - true
-        .max_age(Duration::minutes(5))
-        .finish(); // The duration better to align with expiry time of access token
+    let cookie = auth::access_token_cookie(access_token);
 
     let mut response = (
         StatusCode::OK,
@@ -206,7 +176,7 @@ pub async fn register_handler(
                 message: "User registration complete.".to_string(),
                 // We should send email with the link of postfix containing the verification token to the user email
                 // We are embedding the verification token here for easier development purpose
-                verification_token,
+                verification_token: verification_token.into_inner(),
             },
         }),
     )
@@ -227,64 +197,392 @@ pub async fn activate_handler(
 ) -> Result<impl IntoResponse, (StatusCode, Json<ErrorResponse>)> {
     info!("received request");
 
-    // Verify user token
-    // As jsonwebtoken library in Rust doesn't directly expose an API to decode without secret
-    // We will get the user_id from claims with reference to the issue below
-    // https://github.com/Keats/jsonwebtoken/issues/277
-    // As we encode the token using default Header, i.e. HS256
-    let mut insecure_validation = Validation::new(jsonwebtoken::Algorithm::HS256);
-    insecure_validation.insecure_disable_signature_validation();
-    let decoded_claims = decode::<Claims>(
-        &params.token,
-        &DecodingKey::from_secret(&[]),
-        &insecure_validation,
-    )
-    .map_err(|error| {
-        error!(
-            "failed to retrieve user_id by decoding jwt token without verification. {}",
-            error
-        );
-        return (
+    // The token is looked up by its hash, nothing inside it is trusted or even decoded
+    debug!("going to activate user with verification token");
+    let is_activated = state
+        .verifications
+        .activate_user(&verification::hash_token(params.token.expose()))
+        .await?;
+    if !is_activated {
+        return Err((
             StatusCode::BAD_REQUEST,
             Json(ErrorResponse {
                 success: false,
-                error: format!("Invalid verification token."),
+                error: "Invalid verification token.".to_string(),
+            }),
+        ));
+    }
+
+    Ok((
+        StatusCode::OK,
+        Json(SuccessResponse::<ActivateResponse> {
+            success: true,
+            result: ActivateResponse {
+                message: "User activated.".to_string(),
+            },
+        }),
+    ))
+}
+
+// Handler function for path '/api/v1/user/login'
+// Request body is skipped so that the plaintext password never reaches the logs
+#[tracing::instrument(skip(state, body))]
+pub async fn login_handler(
+    State(state): State<Arc<ServerState>>,
+    client: ClientInfo,
+    CustomJson(body): CustomJson<LoginSchema>,
+) -> Result<Response, (StatusCode, Json<ErrorResponse>)> {
+    info!("received request");
+    let invalid_credentials = || {
+        (
+            StatusCode::UNAUTHORIZED,
+            Json(ErrorResponse {
+                success: false,
+                error: "Invalid email or password.".to_string(),
+            }),
+        )
+    };
+
+    // Unknown emails, locked accounts and accounts without a password get the same answer after the
+    // same hashing work as a wrong password, so neither the response nor its timing reveals an account
+    let verification_error = |error: anyhow::Error| {
+        error!("password verification error. {}", error);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse {
+                success: false,
+                error: "Internal server error. Please try to login again.".to_string(),
             }),
+        )
+    };
+    let Some(user) = state.users.get_user_by_email(body.email.as_str()).await? else {
+        state
+            .password_hasher
+            .verify_dummy(&body.password)
+            .await
+            .map_err(verification_error)?;
+        return Err(invalid_credentials());
+    };
+
+    // A locked account is rejected even with the right password, so guessing on stops paying off
+    if is_login_locked(&state, &user.id).await? {
+        debug!("user login is locked");
+        record_failed_login(&state, &user.id, &client, LoginFailureReason::Locked).await?;
+        state
+            .password_hasher
+            .verify_dummy(&body.password)
+            .await
+            .map_err(verification_error)?;
+        return Err(invalid_credentials());
+    }
+
+    // Users who only sign in through an identity provider cannot login with a password
+    let Some(password_hash) = user.password.as_ref() else {
+        count_failed_login(
+            &state,
+            &user.id,
+            &client,
+            LoginFailureReason::InvalidPassword,
+        )
+        .await?;
+        state
+            .password_hasher
+            .verify_dummy(&body.password)
+            .await
+            .map_err(verification_error)?;
+        return Err(invalid_credentials());
+    };
+
+    debug!("going to verify user password");
+    let is_password_valid = state
+        .password_hasher
+        .verify(&body.password, password_hash)
+        .await
+        .map_err(verification_error)?;
+    if !is_password_valid {
+        count_failed_login(
+            &state,
+            &user.id,
+            &client,
+            LoginFailureReason::InvalidPassword,
+        )
+        .await?;
+        return Err(invalid_credentials());
+    }
+    if state.password_hasher.needs_rehash(password_hash) {
+        rehash_password(&state, &user.id, &body.password, password_hash).await;
+    }
+
+    // Only activated users that are not suspended or banned can login
+    if !user.verified {
+        return Err((
+            StatusCode::FORBIDDEN,
+            Json(ErrorResponse {
+                success: false,
+                error: "User is not activated.".to_string(),
+            }),
+        ));
+    }
+    auth::ensure_not_restricted(&user)?;
+
+    complete_login(&state, &user, &client).await
+}
+
+async fn record_failed_login(
+    state: &ServerState,
+    user_id: &Uuid,
+    client: &ClientInfo,
+    reason: LoginFailureReason,
+) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+    state
+        .logins
+        .insert_login_history(NewUserLoginHistory {
+            user_id: *user_id,
+            success: false,
+            failure_reason: Some(reason),
+            ip: client.ip.clone(),
+            user_agent: client.user_agent.clone(),
+            is_new_device: false,
+        })
+        .await
+}
+
+async fn is_login_locked(
+    state: &ServerState,
+    user_id: &Uuid,
+) -> Result<bool, (StatusCode, Json<ErrorResponse>)> {
+    Ok(state
+        .logins
+        .get_locked_until(user_id)
+        .await?
+        .is_some_and(|locked_until| locked_until > OffsetDateTime::now_utc()))
+}
+
+// Count a wrong password or two-factor code towards the lockout and lock the account once the policy says so
+async fn count_failed_login(
+    state: &ServerState,
+    user_id: &Uuid,
+    client: &ClientInfo,
+    reason: LoginFailureReason,
+) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+    record_failed_login(state, user_id, client, reason).await?;
+    let policy = &state.lockout_policy;
+    let failed_count = state
+        .logins
+        .record_failed_login(user_id, policy.reset_before())
+        .await?;
+    if let Some(lock_duration) = policy.lock_duration(failed_count) {
+        warn!(
+            "locking user login for {:?} after {} failed attempts",
+            lock_duration, failed_count
         );
-    })?;
+        state
+            .logins
+            .lock_login(user_id, OffsetDateTime::now_utc() + lock_duration)
+            .await?;
+    }
+    Ok(())
+}
 
-    debug!("going to verify user verification token");
-    let secret =
-        db::user_verification::get_user_verification_secret(&state.db, &decoded_claims.claims.sub)
+// Upgrade an outdated hash while the plaintext password is at hand
+// A failure is only logged, the user can still login with the old hash
+async fn rehash_password(
+    state: &ServerState,
+    user_id: &Uuid,
+    password: &Secret<String>,
+    previous_password: &Secret<String>,
+) {
+    debug!("going to rehash user password");
+    let result = match state.password_hasher.hash(password).await {
+        Ok(password_hash) => state
+            .users
+            .update_password_hash(user_id, previous_password, &password_hash)
+            .await
+            .map_err(|_| anyhow::anyhow!("failed to store new hash")),
+        Err(error) => Err(error),
+    };
+    if let Err(error) = result {
+        warn!("password rehash error. {}", error);
+    }
+}
+
+// Finish a login whose first factor has been checked
+// The access token is only issued after the second step when two-factor authentication is enabled
+pub async fn complete_login(
+    state: &ServerState,
+    user: &User,
+    client: &ClientInfo,
+) -> Result<Response, (StatusCode, Json<ErrorResponse>)> {
+    let is_two_factor_enabled = state
+        .two_factor
+        .get_two_factor(&user.id)
+        .await?
+        .is_some_and(|two_factor| two_factor.enabled);
+    if is_two_factor_enabled {
+        debug!("constructing jwt challenge token");
+        let challenge_token = two_factor::encode_challenge_token(user.id)?;
+        return Ok((
+            StatusCode::OK,
+            Json(SuccessResponse::<LoginResponse> {
+                success: true,
+                result: LoginResponse {
+                    message: "Two-factor authentication required.".to_string(),
+                    two_factor_required: true,
+                    challenge_token: Some(challenge_token),
+                },
+            }),
+        )
+            .into_response());
+    }
+
+    login_success(state, user, client).await
+}
+
+// Handler function for path '/api/v1/user/login/2fa'
+// Request body is skipped so that the challenge token and code never reach the logs
+#[tracing::instrument(skip(state, body))]
+pub async fn login_two_factor_handler(
+    State(state): State<Arc<ServerState>>,
+    client: ClientInfo,
+    CustomJson(body): CustomJson<LoginTwoFactorSchema>,
+) -> Result<Response, (StatusCode, Json<ErrorResponse>)> {
+    info!("received request");
+    let invalid_challenge = || {
+        (
+            StatusCode::UNAUTHORIZED,
+            Json(ErrorResponse {
+                success: false,
+                error: "Invalid or expired challenge token.".to_string(),
+            }),
+        )
+    };
+    let claims = two_factor::decode_challenge_token(body.challenge_token.expose())?;
+
+    // Attempts are limited per user as well, so a stolen password cannot be tried from many addresses
+    if let RateLimitDecision::Limited { retry_after } = state
+        .rate_limiter
+        .check_user(&state.two_factor_rate_limits, &claims.sub.to_string())
+        .await
+    {
+        return Ok(rate_limit::too_many_requests(retry_after));
+    }
+
+    let user = state
+        .users
+        .get_user_by_id(&claims.sub)
+        .await?
+        .ok_or_else(invalid_challenge)?;
+    // Challenges issued before a force logout are no longer accepted
+    if let Some(tokens_revoked_at) = user.tokens_revoked_at {
+        if claims.iat as i64 <= tokens_revoked_at.unix_timestamp() {
+            debug!("jwt challenge token has been revoked");
+            return Err(invalid_challenge());
+        }
+    }
+    if !user.verified {
+        return Err((
+            StatusCode::FORBIDDEN,
+            Json(ErrorResponse {
+                success: false,
+                error: "User is not activated.".to_string(),
+            }),
+        ));
+    }
+    auth::ensure_not_restricted(&user)?;
+
+    let two_factor = match state.two_factor.get_two_factor(&user.id).await? {
+        Some(two_factor) if two_factor.enabled => two_factor,
+        _ => return Err(invalid_challenge()),
+    };
+    // Whoever holds the challenge token knows the password already, so the lock can be told apart
+    if let Some(locked_until) = state.logins.get_locked_until(&user.id).await? {
+        let now = OffsetDateTime::now_utc();
+        if locked_until > now {
+            debug!("user login is locked");
+            record_failed_login(&state, &user.id, &client, LoginFailureReason::Locked).await?;
+            return Ok(login_security::account_locked(
+                (locked_until - now).unsigned_abs(),
+            ));
+        }
+    }
+    debug!("going to verify two-factor code");
+    let is_code_valid =
+        super::two_factor::verify_second_factor(&state, &user.id, &two_factor, body.code.expose())
             .await?;
-    // We don't care about the content inside claims as we just want to know if the token are encoded with the same secret
-    decode::<Claims>(
-        &params.token,
-        &DecodingKey::from_secret(secret.as_bytes()),
-        &Validation::new(jsonwebtoken::Algorithm::HS256),
-    )
-    .map_err(|error| {
-        error!("invalid jwt verification token. {}", error);
-        return (
-            StatusCode::BAD_REQUEST,
+    if !is_code_valid {
+        count_failed_login(
+            &state,
+            &user.id,
+            &client,
+            LoginFailureReason::InvalidTwoFactorCode,
+        )
+        .await?;
+        return Err((
+            StatusCode::UNAUTHORIZED,
             Json(ErrorResponse {
                 success: false,
-                error: format!("Invalid verification token."),
+                error: "Invalid two-factor code.".to_string(),
             }),
-        );
-    })?;
+        ));
+    }
 
-    // Update user verification status
-    debug!("going to update user verification status");
-    db::user::update_verified_status(&state.db, &decoded_claims.claims.sub, true).await?;
+    login_success(&state, &user, &client).await
+}
 
-    Ok((
+// Respond with the access token cookie once every login step has passed
+// The login is recorded first and the user is notified when it comes from a new device
+// Failures are only forgotten now, a right password alone does not reset wrong two-factor codes
+async fn login_success(
+    state: &ServerState,
+    user: &User,
+    client: &ClientInfo,
+) -> Result<Response, (StatusCode, Json<ErrorResponse>)> {
+    state.logins.clear_failed_logins(&user.id).await?;
+    let is_new_device = state
+        .logins
+        .is_new_device(&user.id, &client.ip, client.user_agent.as_deref())
+        .await?;
+    state
+        .logins
+        .insert_login_history(NewUserLoginHistory {
+            user_id: user.id,
+            success: true,
+            failure_reason: None,
+            ip: client.ip.clone(),
+            user_agent: client.user_agent.clone(),
+            is_new_device,
+        })
+        .await?;
+    if is_new_device {
+        debug!("going to send new device notification");
+        state.mailer.send(login_security::new_device_mail(
+            &user.email,
+            client,
+            OffsetDateTime::now_utc(),
+        ));
+    }
+
+    debug!("constructing jwt access token");
+    let access_token = auth::encode_access_token(user.id)?;
+    let cookie = auth::access_token_cookie(access_token);
+
+    let mut response = (
         StatusCode::OK,
-        Json(SuccessResponse::<ActivateResponse> {
+        Json(SuccessResponse::<LoginResponse> {
             success: true,
-            result: ActivateResponse {
-                message: "User activated.".to_string(),
+            result: LoginResponse {
+                message: "Login success.".to_string(),
+                two_factor_required: false,
+                challenge_token: None,
             },
         }),
-    ))
+    )
+        .into_response();
+
+    // Embed the cookie in response
+    response
+        .headers_mut()
+        .insert(header::SET_COOKIE, cookie.to_string().parse().unwrap());
+
+    Ok(response)
 }
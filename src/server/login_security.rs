@@ -0,0 +1,159 @@
+use super::handlers::ErrorResponse;
+use super::ServerState;
+use crate::config::{invalid_config, parse_env};
+use crate::external::mail::Mail;
+use axum::async_trait;
+use axum::extract::FromRequestParts;
+use axum::http::request::Parts;
+use axum::http::{header, HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use std::convert::Infallible;
+use std::sync::Arc;
+use std::time::Duration;
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+
+// Same limits as the ip and user_agent columns of the login history
+const IP_MAX_LENGTH: usize = 45;
+const USER_AGENT_MAX_LENGTH: usize = 512;
+
+// Accounts are locked once the threshold of failures in a row is reached
+// Every further failure doubles the lock duration up to the maximum
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LockoutPolicy {
+    pub threshold: i32,
+    pub duration: Duration,
+    pub max_duration: Duration,
+}
+
+impl LockoutPolicy {
+    pub fn from_env() -> Result<Self, anyhow::Error> {
+        let threshold = parse_env(
+            "LOGIN_LOCKOUT_THRESHOLD",
+            5,
+            "a positive number of failures",
+        )?;
+        if threshold < 1 {
+            return Err(invalid_config(
+                "LOGIN_LOCKOUT_THRESHOLD",
+                "a positive number of failures",
+            ));
+        }
+        let seconds = parse_env("LOGIN_LOCKOUT_SECONDS", 60, "a positive number of seconds")?;
+        let max_seconds = parse_env(
+            "LOGIN_LOCKOUT_MAX_SECONDS",
+            3600,
+            "a number of seconds not less than LOGIN_LOCKOUT_SECONDS",
+        )?;
+        if seconds == 0 {
+            return Err(invalid_config(
+                "LOGIN_LOCKOUT_SECONDS",
+                "a positive number of seconds",
+            ));
+        }
+        if max_seconds < seconds {
+            return Err(invalid_config(
+                "LOGIN_LOCKOUT_MAX_SECONDS",
+                "a number of seconds not less than LOGIN_LOCKOUT_SECONDS",
+            ));
+        }
+        Ok(Self {
+            threshold,
+            duration: Duration::from_secs(seconds),
+            max_duration: Duration::from_secs(max_seconds),
+        })
+    }
+
+    // Returns None while the failures are still below the threshold
+    pub fn lock_duration(&self, failed_count: i32) -> Option<Duration> {
+        if failed_count < self.threshold {
+            return None;
+        }
+        let factor = 2u32
+            .checked_pow((failed_count - self.threshold) as u32)
+            .unwrap_or(u32::MAX);
+        Some(
+            self.duration
+                .checked_mul(factor)
+                .map_or(self.max_duration, |duration| {
+                    duration.min(self.max_duration)
+                }),
+        )
+    }
+
+    // Failures are forgotten once none happened for the maximum lock duration
+    pub fn reset_before(&self) -> OffsetDateTime {
+        OffsetDateTime::now_utc() - self.max_duration
+    }
+}
+
+// Where a login attempt comes from, as recorded in the login history
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ClientInfo {
+    pub ip: String,
+    pub user_agent: Option<String>,
+}
+
+#[async_trait]
+impl FromRequestParts<Arc<ServerState>> for ClientInfo {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &Arc<ServerState>,
+    ) -> Result<Self, Self::Rejection> {
+        // The address is resolved the same way as for rate limiting, so proxies are handled alike
+        let ip = state
+            .rate_limiter
+            .client_ip(&parts.headers, &parts.extensions)
+            .chars()
+            .take(IP_MAX_LENGTH)
+            .collect();
+        let user_agent = parts
+            .headers
+            .get(header::USER_AGENT)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.trim().chars().take(USER_AGENT_MAX_LENGTH).collect())
+            .filter(|value: &String| !value.is_empty());
+        Ok(Self { ip, user_agent })
+    }
+}
+
+// Response for a login while the account is locked
+pub fn account_locked(retry_after: Duration) -> Response {
+    // Retry-After only accepts whole seconds
+    let retry_after_seconds = retry_after.as_secs_f64().ceil().max(1.0) as u64;
+    let mut response = (
+        StatusCode::TOO_MANY_REQUESTS,
+        Json(ErrorResponse {
+            success: false,
+            error: "Too many failed login attempts. Please try again later.".to_string(),
+        }),
+    )
+        .into_response();
+    response
+        .headers_mut()
+        .insert(header::RETRY_AFTER, HeaderValue::from(retry_after_seconds));
+    response
+}
+
+// Tell the user about a login from a device that has not been seen before
+pub fn new_device_mail(email: &str, client: &ClientInfo, logged_in_at: OffsetDateTime) -> Mail {
+    let logged_in_at = logged_in_at
+        .format(&Rfc3339)
+        .unwrap_or_else(|_| logged_in_at.to_string());
+    Mail {
+        to: email.to_string(),
+        subject: "New sign-in to your account".to_string(),
+        body: format!(
+            "Your account was signed in from a new device.\n\n\
+             Time: {}\nIP address: {}\nDevice: {}\n\n\
+             If this was you, there is nothing to do. \
+             Otherwise change your password right away.",
+            logged_in_at,
+            client.ip,
+            client.user_agent.as_deref().unwrap_or("unknown"),
+        ),
+    }
+}
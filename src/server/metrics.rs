@@ -0,0 +1,256 @@
+use axum::extract::MatchedPath;
+use axum::http::Request;
+use axum::response::Response;
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounterVec, IntGaugeVec, Opts, Registry, TextEncoder,
+};
+use sqlx::{Pool, Postgres};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::OnceLock;
+use std::task::{Context, Poll};
+use std::time::Instant;
+use tower::{Layer, Service};
+use tracing::{span, Level};
+use tracing_subscriber::filter::{Filtered, Targets};
+use tracing_subscriber::layer::{self, Layer as _};
+use tracing_subscriber::registry::LookupSpan;
+
+// Prefix of the modules holding the database queries, each query function is instrumented with a span
+const DB_QUERY_TARGET: &str = "chat_rs::external::db";
+
+pub struct Metrics {
+    registry: Registry,
+    http_requests_total: IntCounterVec,
+    http_request_duration_seconds: HistogramVec,
+    db_pool_connections: IntGaugeVec,
+    db_query_duration_seconds: HistogramVec,
+    password_hash_duration_seconds: HistogramVec,
+    rate_limit_rejections_total: IntCounterVec,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let registry = Registry::new();
+        // Metric names and labels are fixed, so registration can only fail on a programming error
+        let http_requests_total = IntCounterVec::new(
+            Opts::new("http_requests_total", "Number of HTTP requests handled."),
+            &["method", "route", "status"],
+        )
+        .unwrap();
+        let http_request_duration_seconds = HistogramVec::new(
+            HistogramOpts::new(
+                "http_request_duration_seconds",
+                "Time taken to handle HTTP requests.",
+            ),
+            &["method", "route", "status"],
+        )
+        .unwrap();
+        let db_pool_connections = IntGaugeVec::new(
+            Opts::new(
+                "db_pool_connections",
+                "Number of database pool connections by state.",
+            ),
+            &["state"],
+        )
+        .unwrap();
+        let db_query_duration_seconds = HistogramVec::new(
+            HistogramOpts::new(
+                "db_query_duration_seconds",
+                "Time taken by database query functions.",
+            ),
+            &["query"],
+        )
+        .unwrap();
+        let password_hash_duration_seconds = HistogramVec::new(
+            HistogramOpts::new(
+                "password_hash_duration_seconds",
+                "Time taken to hash or verify passwords.",
+            ),
+            &["operation"],
+        )
+        .unwrap();
+        let rate_limit_rejections_total = IntCounterVec::new(
+            Opts::new(
+                "rate_limit_rejections_total",
+                "Number of requests rejected by the rate limiter.",
+            ),
+            &["group", "scope"],
+        )
+        .unwrap();
+
+        registry
+            .register(Box::new(http_requests_total.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(http_request_duration_seconds.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(db_pool_connections.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(db_query_duration_seconds.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(password_hash_duration_seconds.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(rate_limit_rejections_total.clone()))
+            .unwrap();
+
+        Self {
+            registry,
+            http_requests_total,
+            http_request_duration_seconds,
+            db_pool_connections,
+            db_query_duration_seconds,
+            password_hash_duration_seconds,
+            rate_limit_rejections_total,
+        }
+    }
+
+    // Render every metric in the Prometheus text exposition format
+    pub fn render(&self) -> Result<String, prometheus::Error> {
+        let mut buffer = Vec::new();
+        TextEncoder::new().encode(&self.registry.gather(), &mut buffer)?;
+        String::from_utf8(buffer).map_err(|error| prometheus::Error::Msg(error.to_string()))
+    }
+
+    // Pool usage is sampled when metrics are scraped
+    pub fn observe_db_pool(&self, pool: &Pool<Postgres>) {
+        let size = pool.size() as i64;
+        let idle = pool.num_idle() as i64;
+        self.db_pool_connections
+            .with_label_values(&["idle"])
+            .set(idle);
+        self.db_pool_connections
+            .with_label_values(&["active"])
+            .set(size - idle);
+        self.db_pool_connections
+            .with_label_values(&["max"])
+            .set(pool.options().get_max_connections() as i64);
+    }
+
+    pub fn observe_password_hashing<T>(&self, operation: &str, hashing: impl FnOnce() -> T) -> T {
+        let started_at = Instant::now();
+        let result = hashing();
+        self.password_hash_duration_seconds
+            .with_label_values(&[operation])
+            .observe(started_at.elapsed().as_secs_f64());
+        result
+    }
+
+    pub fn record_rate_limit_rejection(&self, group: &str, scope: &str) {
+        self.rate_limit_rejections_total
+            .with_label_values(&[group, scope])
+            .inc();
+    }
+}
+
+// Metrics are shared by the tracing subscriber and the web server, so they live for the whole process
+pub fn metrics() -> &'static Metrics {
+    static METRICS: OnceLock<Metrics> = OnceLock::new();
+    METRICS.get_or_init(Metrics::new)
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct HttpMetricsLayer;
+
+impl<S> Layer<S> for HttpMetricsLayer {
+    type Service = HttpMetrics<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        HttpMetrics { inner }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct HttpMetrics<S> {
+    inner: S,
+}
+
+impl<S, B> Service<Request<B>> for HttpMetrics<S>
+where
+    S: Service<Request<B>, Response = Response> + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<B>) -> Self::Future {
+        let method = request.method().to_string();
+        // Label by route template rather than the raw path to keep the number of series bounded
+        let route = request
+            .extensions()
+            .get::<MatchedPath>()
+            .map(|path| path.as_str().to_string())
+            .unwrap_or_else(|| "unmatched".to_string());
+        let started_at = Instant::now();
+        let future = self.inner.call(request);
+
+        Box::pin(async move {
+            let response = future.await?;
+            let status = response.status().as_u16().to_string();
+            let labels = [method.as_str(), route.as_str(), status.as_str()];
+            metrics()
+                .http_requests_total
+                .with_label_values(&labels)
+                .inc();
+            metrics()
+                .http_request_duration_seconds
+                .with_label_values(&labels)
+                .observe(started_at.elapsed().as_secs_f64());
+            Ok(response)
+        })
+    }
+}
+
+struct SpanStartedAt(Instant);
+
+// Times the spans of database query functions
+pub struct DbQueryMetricsLayer;
+
+impl DbQueryMetricsLayer {
+    // The layer has its own filter, so queries are timed whatever level the log filter is set to
+    pub fn filtered<S>() -> Filtered<Self, Targets, S>
+    where
+        S: tracing::Subscriber + for<'lookup> LookupSpan<'lookup>,
+    {
+        Self.with_filter(Targets::new().with_target(DB_QUERY_TARGET, Level::TRACE))
+    }
+}
+
+impl<S> layer::Layer<S> for DbQueryMetricsLayer
+where
+    S: tracing::Subscriber + for<'lookup> LookupSpan<'lookup>,
+{
+    fn on_new_span(
+        &self,
+        _attrs: &span::Attributes<'_>,
+        id: &span::Id,
+        ctx: layer::Context<'_, S>,
+    ) {
+        match ctx.span(id) {
+            Some(span) if span.metadata().target().starts_with(DB_QUERY_TARGET) => {
+                span.extensions_mut().insert(SpanStartedAt(Instant::now()));
+            }
+            _ => {}
+        }
+    }
+
+    fn on_close(&self, id: span::Id, ctx: layer::Context<'_, S>) {
+        if let Some(span) = ctx.span(&id) {
+            if let Some(SpanStartedAt(started_at)) = span.extensions().get::<SpanStartedAt>() {
+                metrics()
+                    .db_query_duration_seconds
+                    .with_label_values(&[span.name()])
+                    .observe(started_at.elapsed().as_secs_f64());
+            }
+        }
+    }
+}
@@ -1,62 +1,356 @@
+pub mod account;
+pub mod auth;
 pub mod handlers;
+pub mod login_security;
+pub mod metrics;
+pub mod oidc;
+pub mod password;
+pub mod rate_limit;
+pub mod request_id;
+mod shutdown;
+pub mod signing_keys;
+pub mod two_factor;
+pub mod validation;
+pub mod verification;
 
-use axum::routing::{get, post};
+use crate::external::db::repository::{
+    AccountRepository, AdminRepository, IdentityRepository, LoginSecurityRepository, PgRepository,
+    ReportRepository, TwoFactorRepository, UserRepository, VerificationRepository,
+};
+use crate::external::mail::{self, Mailer};
+use auth::TokenConfig;
+use axum::routing::{delete, get, post};
 use axum::{Router, Server};
 use dotenvy::var;
 use handlers::health_check_handler;
+use login_security::LockoutPolicy;
+use metrics::HttpMetricsLayer;
+use oidc::{OidcClient, ProviderConfig};
+use password::PasswordHasher;
+use rate_limit::{RateLimiter, RateLimits, RouteGroupLimits};
+use request_id::RequestIdLayer;
+use signing_keys::SigningKeys;
 use sqlx::{Pool, Postgres};
-use std::net::SocketAddr;
-use std::sync::Arc;
+use std::future::Future;
+use std::net::{SocketAddr, TcpListener};
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+use tokio::sync::Notify;
+use tokio::time::Instant;
 use tower::ServiceBuilder;
 use tower_http::trace::TraceLayer;
-use tracing::info;
+use tracing::{info, warn};
 
 #[derive(Debug)]
 pub struct ServerState {
     db: Pool<Postgres>,
+    users: Arc<dyn UserRepository>,
+    verifications: Arc<dyn VerificationRepository>,
+    two_factor: Arc<dyn TwoFactorRepository>,
+    identities: Arc<dyn IdentityRepository>,
+    logins: Arc<dyn LoginSecurityRepository>,
+    accounts: Arc<dyn AccountRepository>,
+    admin: Arc<dyn AdminRepository>,
+    reports: Arc<dyn ReportRepository>,
+    mailer: Arc<dyn Mailer>,
+    rate_limiter: RateLimiter,
+    // Also checked by the login handler, which only learns the user from the challenge token
+    two_factor_rate_limits: RouteGroupLimits,
+    password_hasher: PasswordHasher,
+    lockout_policy: LockoutPolicy,
+    deletion_grace_period: time::Duration,
+    oidc: OidcClient,
 }
 
-// Initialize an axum web server instance
-#[tracing::instrument]
-pub async fn init(db_client: Pool<Postgres>) {
-    let server_state = Arc::new(ServerState { db: db_client });
+// Settings parsed from the environment on start, so that invalid values stop the server before it binds
+#[derive(Clone, Debug)]
+pub struct ServerConfig {
+    pub password_hasher: PasswordHasher,
+    pub lockout_policy: LockoutPolicy,
+    pub deletion_grace_period: time::Duration,
+    pub rate_limits: RateLimits,
+    pub oidc_providers: Vec<ProviderConfig>,
+}
+
+impl ServerConfig {
+    pub fn from_env() -> Result<Self, anyhow::Error> {
+        Ok(Self {
+            password_hasher: PasswordHasher::from_env()?,
+            lockout_policy: LockoutPolicy::from_env()?,
+            deletion_grace_period: account::deletion_grace_period_from_env()?,
+            rate_limits: RateLimits::from_env()?,
+            oidc_providers: oidc::providers_from_env()?,
+        })
+    }
+}
+
+impl ServerState {
+    // One backend serves every repository, Postgres in production and memory in tests
+    pub fn new<R>(
+        db_client: Pool<Postgres>,
+        repository: Arc<R>,
+        rate_limiter: RateLimiter,
+        mailer: Arc<dyn Mailer>,
+        config: &ServerConfig,
+    ) -> Self
+    where
+        R: UserRepository
+            + VerificationRepository
+            + TwoFactorRepository
+            + IdentityRepository
+            + LoginSecurityRepository
+            + AccountRepository
+            + AdminRepository
+            + ReportRepository
+            + 'static,
+    {
+        Self {
+            db: db_client,
+            users: repository.clone(),
+            verifications: repository.clone(),
+            two_factor: repository.clone(),
+            identities: repository.clone(),
+            logins: repository.clone(),
+            accounts: repository.clone(),
+            admin: repository.clone(),
+            reports: repository,
+            mailer,
+            rate_limiter,
+            two_factor_rate_limits: config.rate_limits.two_factor.clone(),
+            password_hasher: config.password_hasher.clone(),
+            lockout_policy: config.lockout_policy,
+            deletion_grace_period: config.deletion_grace_period,
+            oidc: OidcClient::new(config.oidc_providers.clone()),
+        }
+    }
+}
+
+// Construct the application router without binding it to a socket
+pub fn build_router(server_state: Arc<ServerState>, rate_limits: &RateLimits) -> Router {
     // https://stackoverflow.com/questions/74302133/how-to-log-and-filter-requests-with-axum-tokio
-    let service = ServiceBuilder::new().layer(TraceLayer::new_for_http());
+    let service = ServiceBuilder::new()
+        .layer(TraceLayer::new_for_http().make_span_with(request_id::make_request_span))
+        .layer(RequestIdLayer)
+        .layer(HttpMetricsLayer);
+    let rate_limiter = &server_state.rate_limiter;
+    let auth_rate_limit = rate_limiter.layer(rate_limits.auth.clone());
+    let api_rate_limit = rate_limiter.layer(rate_limits.api.clone());
+    let two_factor_rate_limit = rate_limiter.layer(rate_limits.two_factor.clone());
     // Define the routes for web server
     let user_routes = Router::new()
         .route("/register", post(handlers::user::register_handler))
-        .route("/activate", get(handlers::user::activate_handler));
-    let api_version_one_routes = Router::new().nest("/user", user_routes);
-    let server = Router::new()
+        .route("/activate", get(handlers::user::activate_handler))
+        .route("/login", post(handlers::user::login_handler))
+        .route(
+            "/login/2fa",
+            post(handlers::user::login_two_factor_handler).layer(two_factor_rate_limit.clone()),
+        )
+        .route(
+            "/oidc/:provider/authorize",
+            get(handlers::oidc::authorize_handler),
+        )
+        .route(
+            "/oidc/:provider/callback",
+            get(handlers::oidc::callback_handler),
+        )
+        .layer(auth_rate_limit);
+    let oidc_routes = Router::new()
+        .route("/", get(handlers::oidc::list_identities_handler))
+        .route("/:provider", delete(handlers::oidc::unlink_handler))
+        .route("/:provider/link", get(handlers::oidc::link_handler));
+    let account_routes = Router::new()
+        .route("/", delete(handlers::account::delete_account_handler))
+        .route("/restore", post(handlers::account::restore_account_handler))
+        .route("/export", post(handlers::account::create_export_handler))
+        .route(
+            "/export/:export_id",
+            get(handlers::account::get_export_handler),
+        )
+        .route(
+            "/export/:export_id/download",
+            get(handlers::account::download_export_handler),
+        );
+    let two_factor_routes = Router::new()
+        .route("/", get(handlers::two_factor::status_handler))
+        .route("/enroll", post(handlers::two_factor::enroll_handler))
+        .route("/enroll/qr", get(handlers::two_factor::qr_code_handler))
+        .route(
+            "/confirm",
+            post(handlers::two_factor::confirm_handler).layer(two_factor_rate_limit.clone()),
+        )
+        .route(
+            "/disable",
+            post(handlers::two_factor::disable_handler).layer(two_factor_rate_limit.clone()),
+        );
+    let admin_routes = Router::new()
+        .route("/users", get(handlers::admin::list_users_handler))
+        .route("/users/:user_id", get(handlers::admin::get_user_handler))
+        .route(
+            "/users/:user_id/verify",
+            post(handlers::admin::verify_user_handler),
+        )
+        .route(
+            "/users/:user_id/suspend",
+            post(handlers::admin::suspend_user_handler),
+        )
+        .route(
+            "/users/:user_id/ban",
+            post(handlers::admin::ban_user_handler),
+        )
+        .route(
+            "/users/:user_id/logout",
+            post(handlers::admin::logout_user_handler),
+        )
+        .route(
+            "/users/:user_id/role",
+            post(handlers::admin::update_role_handler),
+        )
+        .route("/audit-logs", get(handlers::admin::list_audit_logs_handler));
+    let moderation_routes = Router::new()
+        .route("/reports", get(handlers::moderation::list_reports_handler))
+        .route(
+            "/reports/:report_id/resolve",
+            post(handlers::moderation::resolve_report_handler),
+        );
+    let api_version_one_routes = Router::new()
+        .nest("/admin", admin_routes)
+        .nest("/moderation", moderation_routes)
+        .route("/reports", post(handlers::report::create_report_handler))
+        .nest("/user/2fa", two_factor_routes)
+        .nest("/user/oidc", oidc_routes)
+        .nest("/user/me", account_routes)
+        .route(
+            "/user/login-history",
+            get(handlers::login_history::list_login_history_handler),
+        )
+        .layer(api_rate_limit)
+        .nest("/user", user_routes);
+    Router::new()
         .route("/", get(health_check_handler))
+        // Probes are kept outside of the rate limited routes
+        .route("/healthz", get(handlers::health::liveness_handler))
+        .route("/readyz", get(handlers::health::readiness_handler))
+        .route("/metrics", get(handlers::metrics::metrics_handler))
+        .route("/.well-known/jwks.json", get(handlers::jwks::jwks_handler))
         .nest("/api/v1", api_version_one_routes)
         .layer(service)
         .with_state(server_state)
-        .into_make_service();
-
-    let web_server_host = var("WEB_SERVER_HOST");
-    let web_server_port = var("WEB_SERVER_PORT");
-
-    match web_server_host {
-        Ok(host) => match web_server_port {
-            Ok(port) => {
-                info!(
-                    "{}",
-                    format!("web server is listening at {}:{}", host, port)
-                );
-                Server::bind(&format!("{}:{}", host, port).parse::<SocketAddr>().unwrap())
-                    .serve(server)
-                    .await
-                    .unwrap();
+}
+
+// Serve on the listener until shutdown resolves, then wait for in-flight requests and background tasks
+// Stops waiting once the grace period is over, returns the end of the grace period so that the caller
+// releases what outlives the server within the same period
+pub async fn serve(
+    listener: TcpListener,
+    server_state: Arc<ServerState>,
+    rate_limits: &RateLimits,
+    shutdown: impl Future<Output = ()> + Send + 'static,
+    grace_period: Duration,
+) -> Result<Instant, anyhow::Error> {
+    let address = listener.local_addr()?;
+    // Notified as soon as the shutdown signal arrives so that the grace period can start counting
+    let shutdown_started = Arc::new(Notify::new());
+    let deadline = Arc::new(OnceLock::new());
+    // Notify only keeps one permit, so the purge task gets its own
+    let purge_stopping = Arc::new(Notify::new());
+    let purge_task =
+        account::spawn_purge_task(server_state.accounts.clone(), purge_stopping.clone());
+
+    let server = build_router(server_state, rate_limits)
+        // Connection info is needed by the rate limiter to key requests by client IP
+        .into_make_service_with_connect_info::<SocketAddr>();
+    let server = Server::from_tcp(listener)?
+        .serve(server)
+        .with_graceful_shutdown({
+            let shutdown_started = shutdown_started.clone();
+            let deadline = deadline.clone();
+            async move {
+                shutdown.await;
+                let _ = deadline.set(Instant::now() + grace_period);
+                shutdown_started.notify_one();
+                purge_stopping.notify_one();
             }
-            Err(e) => panic!(
-                "Missing config for environment variable WEB_SERVER_PORT. {}",
-                e
-            ),
-        },
-        Err(e) => panic!(
+        });
+    info!("web server is listening at {}", address);
+
+    // The server stops accepting new connections on shutdown and waits for in-flight requests to finish
+    tokio::select! {
+        result = async {
+            server.await?;
+            purge_task.await?;
+            Ok::<(), anyhow::Error>(())
+        } => result?,
+        _ = async {
+            shutdown_started.notified().await;
+            tokio::time::sleep(grace_period).await;
+        } => warn!("in-flight requests did not finish within {:?}, stopping anyway", grace_period),
+    }
+    info!("web server stopped");
+    // The server only stops after the shutdown started, so the deadline is always set by now
+    Ok(deadline
+        .get()
+        .copied()
+        .unwrap_or_else(|| Instant::now() + grace_period))
+}
+
+// Initialize an axum web server instance and serve until a shutdown signal is received
+#[tracing::instrument]
+pub async fn init(db_client: Pool<Postgres>) -> Result<(), anyhow::Error> {
+    let host = var("WEB_SERVER_HOST").map_err(|error| {
+        anyhow::anyhow!(
             "Missing config for environment variable WEB_SERVER_HOST. {}",
-            e
-        ),
+            error
+        )
+    })?;
+    let port = var("WEB_SERVER_PORT").map_err(|error| {
+        anyhow::anyhow!(
+            "Missing config for environment variable WEB_SERVER_PORT. {}",
+            error
+        )
+    })?;
+    let address = format!("{}:{}", host, port)
+        .parse::<SocketAddr>()
+        .map_err(|error| {
+            anyhow::anyhow!("Invalid web server address {}:{}. {}", host, port, error)
+        })?;
+    let grace_period = shutdown::grace_period_from_env()?;
+    let config = ServerConfig::from_env()?;
+    // Load the keys up front so that a broken key directory stops the start instead of the first login
+    signing_keys::init(SigningKeys::from_env()?);
+    auth::init(TokenConfig::from_env()?);
+    let mailer = mail::from_env()?;
+    let rate_limiter = RateLimiter::from_env().await?;
+    let shutdown = shutdown::signal()?;
+
+    let repository = Arc::new(PgRepository::new(db_client.clone()));
+    let server_state = Arc::new(ServerState::new(
+        db_client.clone(),
+        repository,
+        rate_limiter,
+        mailer,
+        &config,
+    ));
+    let listener = TcpListener::bind(address)?;
+    let deadline = serve(
+        listener,
+        server_state,
+        &config.rate_limits,
+        shutdown,
+        grace_period,
+    )
+    .await?;
+
+    // Whatever is left of the grace period, the process has to exit within it
+    if tokio::time::timeout_at(deadline, db_client.close())
+        .await
+        .is_err()
+    {
+        warn!(
+            "database connections did not close within the grace period of {:?}",
+            grace_period
+        );
+    } else {
+        info!("database client closed");
     }
+    Ok(())
 }
@@ -0,0 +1,489 @@
+use super::auth::token_config;
+use super::handlers::{error_response, internal_server_error, ErrorResponse};
+use crate::logger::Secret;
+use axum::http::StatusCode;
+use axum::Json;
+use axum_extra::extract::cookie::{Cookie, SameSite};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use dotenvy::var;
+use jsonwebtoken::jwk::JwkSet;
+use jsonwebtoken::{decode, decode_header, encode, Algorithm, DecodingKey, EncodingKey, Header};
+use jsonwebtoken::{TokenData, Validation};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::ops::Add;
+use std::time::{Duration, Instant};
+use time::OffsetDateTime;
+use tokio::sync::RwLock;
+use tracing::{debug, error, warn};
+use uuid::Uuid;
+
+// Name of the cookie tying the callback to the browser that started the sign-in
+pub const STATE_COOKIE: &str = "oidc_state";
+// Discovery documents and signing keys rarely change, unknown key ids trigger a refresh anyway
+const METADATA_CACHE_TTL: Duration = Duration::from_secs(3600);
+const HTTP_TIMEOUT: Duration = Duration::from_secs(10);
+const STATE_LIFETIME_MINUTES: i64 = 10;
+// Symmetric algorithms are never accepted as the provider would have to share the client secret as key
+const ID_TOKEN_ALGORITHMS: &[Algorithm] = &[
+    Algorithm::RS256,
+    Algorithm::RS384,
+    Algorithm::RS512,
+    Algorithm::PS256,
+    Algorithm::PS384,
+    Algorithm::PS512,
+    Algorithm::ES256,
+    Algorithm::ES384,
+    Algorithm::EdDSA,
+];
+
+fn provider_unavailable() -> (StatusCode, Json<ErrorResponse>) {
+    error_response(StatusCode::BAD_GATEWAY, "Identity provider is unavailable.")
+}
+
+fn sign_in_failed() -> (StatusCode, Json<ErrorResponse>) {
+    error_response(
+        StatusCode::UNAUTHORIZED,
+        "Sign-in with the identity provider failed.",
+    )
+}
+
+// Random value encoded for use in urls, 32 bytes give the 43 characters PKCE asks for
+fn random_token() -> String {
+    let bytes: [u8; 32] = rand::thread_rng().gen();
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+// PKCE 'S256' code challenge sent with the authorization request
+pub fn code_challenge(code_verifier: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(code_verifier.as_bytes()))
+}
+
+// Client registration at a provider, read from 'OIDC_<NAME>_*' environment variables
+#[derive(Clone, Debug)]
+pub struct ProviderConfig {
+    pub name: String,
+    issuer: String,
+    client_id: String,
+    client_secret: Secret<String>,
+    redirect_uri: String,
+    scopes: String,
+}
+
+fn read_provider_var(name: &str) -> Result<String, anyhow::Error> {
+    var(name).map_err(|_| anyhow::anyhow!("Missing config for environment variable {}.", name))
+}
+
+impl ProviderConfig {
+    pub fn new(
+        name: &str,
+        issuer: &str,
+        client_id: String,
+        client_secret: Secret<String>,
+        redirect_uri: String,
+    ) -> Self {
+        Self {
+            name: name.to_lowercase(),
+            issuer: issuer.trim_end_matches('/').to_string(),
+            client_id,
+            client_secret,
+            redirect_uri,
+            scopes: "openid email profile".to_string(),
+        }
+    }
+
+    pub fn from_env(name: &str) -> Result<Self, anyhow::Error> {
+        let prefix = format!("OIDC_{}", name.to_uppercase());
+        let mut config = Self::new(
+            name,
+            &read_provider_var(&format!("{}_ISSUER", prefix))?,
+            read_provider_var(&format!("{}_CLIENT_ID", prefix))?,
+            Secret::new(read_provider_var(&format!("{}_CLIENT_SECRET", prefix))?),
+            read_provider_var(&format!("{}_REDIRECT_URI", prefix))?,
+        );
+        if let Ok(scopes) = var(format!("{}_SCOPES", prefix)) {
+            config.scopes = scopes;
+        }
+        Ok(config)
+    }
+}
+
+// Only providers listed in the comma separated 'OIDC_PROVIDERS' can be used
+pub fn providers_from_env() -> Result<Vec<ProviderConfig>, anyhow::Error> {
+    var("OIDC_PROVIDERS")
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(ProviderConfig::from_env)
+        .collect()
+}
+
+// Subset of the discovery document at '<issuer>/.well-known/openid-configuration'
+#[derive(Clone, Debug, Deserialize)]
+struct ProviderMetadata {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+    jwks_uri: String,
+}
+
+#[derive(Clone, Debug)]
+struct CachedProvider {
+    metadata: ProviderMetadata,
+    keys: JwkSet,
+    fetched_at: Instant,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    id_token: String,
+}
+
+// Claims of the id token the user is identified by
+#[derive(Clone, Debug, Deserialize)]
+pub struct IdTokenClaims {
+    pub sub: String,
+    pub email: Option<String>,
+    #[serde(default)]
+    pub email_verified: bool,
+    pub name: Option<String>,
+    nonce: Option<String>,
+}
+
+// Values generated for one sign-in, kept in the state cookie until the provider redirects back
+// Not Debug so that the nonce and code verifier cannot end up in the logs
+#[derive(Serialize, Deserialize)]
+pub struct LoginState {
+    iss: String,
+    iat: u64,
+    exp: u64,
+    pub provider: String,
+    pub state: String,
+    nonce: String,
+    code_verifier: String,
+    // Set when a signed in user links the provider instead of signing in with it
+    pub link_user_id: Option<Uuid>,
+}
+
+impl LoginState {
+    pub fn new(provider: &str, link_user_id: Option<Uuid>) -> Self {
+        let now = OffsetDateTime::now_utc();
+        Self {
+            iss: state_issuer(),
+            iat: now.unix_timestamp().unsigned_abs(),
+            exp: now
+                .add(time::Duration::minutes(STATE_LIFETIME_MINUTES))
+                .unix_timestamp()
+                .unsigned_abs(),
+            provider: provider.to_string(),
+            state: random_token(),
+            nonce: random_token(),
+            code_verifier: random_token(),
+            link_user_id,
+        }
+    }
+}
+
+// The state cookie is signed with its own issuer so that it is never accepted as any other token
+fn state_issuer() -> String {
+    format!("{}:oidc", token_config().issuer)
+}
+
+fn state_secret() -> &'static [u8] {
+    token_config().secret.expose().as_bytes()
+}
+
+pub fn state_cookie(
+    login_state: &LoginState,
+) -> Result<Cookie<'static>, (StatusCode, Json<ErrorResponse>)> {
+    let value = encode(
+        &Header::default(),
+        login_state,
+        &EncodingKey::from_secret(state_secret()),
+    )
+    .map_err(|error| {
+        error!("jwt oidc state construction error. {}", error);
+        internal_server_error()
+    })?;
+    // Lax is required as the provider redirects back with a cross-site top level navigation
+    Ok(Cookie::build(STATE_COOKIE, value)
+        .path("/api/v1/user/oidc")
+        .http_only(true)
+        .same_site(SameSite::Lax)
+        .max_age(time::Duration::minutes(STATE_LIFETIME_MINUTES))
+        .finish())
+}
+
+// Expire the state cookie once the callback has been handled so that it cannot be used twice
+pub fn removal_state_cookie() -> Cookie<'static> {
+    let mut cookie = Cookie::build(STATE_COOKIE, "")
+        .path("/api/v1/user/oidc")
+        .http_only(true)
+        .same_site(SameSite::Lax)
+        .finish();
+    cookie.make_removal();
+    cookie
+}
+
+pub fn decode_state_cookie(value: &str) -> Result<LoginState, (StatusCode, Json<ErrorResponse>)> {
+    let mut validation = Validation::new(Algorithm::HS256);
+    validation.set_issuer(&[state_issuer()]);
+    decode::<LoginState>(
+        value,
+        &DecodingKey::from_secret(state_secret()),
+        &validation,
+    )
+    .map(|token_data| token_data.claims)
+    .map_err(|error| {
+        debug!("invalid oidc state cookie. {}", error);
+        error_response(
+            StatusCode::UNAUTHORIZED,
+            "Invalid or expired sign-in state.",
+        )
+    })
+}
+
+// Client for the authorization code flow with PKCE against any provider supporting OIDC discovery
+#[derive(Debug)]
+pub struct OidcClient {
+    http: reqwest::Client,
+    // Providers enabled on start
+    configs: Vec<ProviderConfig>,
+    // Keyed by issuer
+    providers: RwLock<HashMap<String, CachedProvider>>,
+}
+
+impl OidcClient {
+    pub fn new(configs: Vec<ProviderConfig>) -> Self {
+        let http = reqwest::Client::builder()
+            .timeout(HTTP_TIMEOUT)
+            .build()
+            .expect("http client should build with static configuration");
+        Self {
+            http,
+            configs,
+            providers: RwLock::new(HashMap::new()),
+        }
+    }
+
+    pub fn provider_config(
+        &self,
+        name: &str,
+    ) -> Result<&ProviderConfig, (StatusCode, Json<ErrorResponse>)> {
+        self.configs
+            .iter()
+            .find(|config| config.name.eq_ignore_ascii_case(name))
+            .ok_or_else(|| {
+                debug!("identity provider {} is not enabled", name);
+                error_response(StatusCode::NOT_FOUND, "Unknown identity provider.")
+            })
+    }
+
+    async fn get_json<T: serde::de::DeserializeOwned>(
+        &self,
+        url: &str,
+    ) -> Result<T, (StatusCode, Json<ErrorResponse>)> {
+        let response = self
+            .http
+            .get(url)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|error| {
+                error!("identity provider request to {} failed. {}", url, error);
+                provider_unavailable()
+            })?;
+        response.json::<T>().await.map_err(|error| {
+            error!("invalid identity provider response from {}. {}", url, error);
+            provider_unavailable()
+        })
+    }
+
+    // Discovery document and signing keys, fetched again once expired or when asked to
+    async fn provider(
+        &self,
+        config: &ProviderConfig,
+        refresh: bool,
+    ) -> Result<CachedProvider, (StatusCode, Json<ErrorResponse>)> {
+        if !refresh {
+            let providers = self.providers.read().await;
+            if let Some(provider) = providers.get(&config.issuer) {
+                if provider.fetched_at.elapsed() < METADATA_CACHE_TTL {
+                    return Ok(provider.clone());
+                }
+            }
+        }
+
+        debug!("going to fetch discovery document of {}", config.issuer);
+        let metadata: ProviderMetadata = self
+            .get_json(&format!(
+                "{}/.well-known/openid-configuration",
+                config.issuer
+            ))
+            .await?;
+        // A discovery document claiming another issuer could otherwise hand out tokens of that issuer
+        if metadata.issuer.trim_end_matches('/') != config.issuer {
+            error!(
+                "discovery document issuer {} does not match {}",
+                metadata.issuer, config.issuer
+            );
+            return Err(provider_unavailable());
+        }
+        let keys: JwkSet = self.get_json(&metadata.jwks_uri).await?;
+
+        let provider = CachedProvider {
+            metadata,
+            keys,
+            fetched_at: Instant::now(),
+        };
+        self.providers
+            .write()
+            .await
+            .insert(config.issuer.clone(), provider.clone());
+        Ok(provider)
+    }
+
+    // Url of the provider's consent page the browser is redirected to
+    pub async fn authorization_url(
+        &self,
+        config: &ProviderConfig,
+        login_state: &LoginState,
+    ) -> Result<String, (StatusCode, Json<ErrorResponse>)> {
+        let provider = self.provider(config, false).await?;
+        let query = form_urlencoded::Serializer::new(String::new())
+            .append_pair("response_type", "code")
+            .append_pair("client_id", &config.client_id)
+            .append_pair("redirect_uri", &config.redirect_uri)
+            .append_pair("scope", &config.scopes)
+            .append_pair("state", &login_state.state)
+            .append_pair("nonce", &login_state.nonce)
+            .append_pair(
+                "code_challenge",
+                &code_challenge(&login_state.code_verifier),
+            )
+            .append_pair("code_challenge_method", "S256")
+            .finish();
+        let separator = if provider.metadata.authorization_endpoint.contains('?') {
+            '&'
+        } else {
+            '?'
+        };
+        Ok(format!(
+            "{}{}{}",
+            provider.metadata.authorization_endpoint, separator, query
+        ))
+    }
+
+    // Exchange the authorization code and return the claims of the verified id token
+    #[tracing::instrument(skip_all, fields(provider = %config.name))]
+    pub async fn authenticate(
+        &self,
+        config: &ProviderConfig,
+        code: &str,
+        login_state: &LoginState,
+    ) -> Result<IdTokenClaims, (StatusCode, Json<ErrorResponse>)> {
+        let provider = self.provider(config, false).await?;
+
+        debug!("going to exchange authorization code");
+        let response = self
+            .http
+            .post(&provider.metadata.token_endpoint)
+            .form(&[
+                ("grant_type", "authorization_code"),
+                ("code", code),
+                ("redirect_uri", &config.redirect_uri),
+                ("client_id", &config.client_id),
+                ("client_secret", config.client_secret.expose()),
+                ("code_verifier", &login_state.code_verifier),
+            ])
+            .send()
+            .await
+            .map_err(|error| {
+                error!("identity provider token request failed. {}", error);
+                provider_unavailable()
+            })?;
+        // An invalid or reused code is rejected with a client error
+        if response.status().is_client_error() {
+            warn!("identity provider rejected the authorization code");
+            return Err(sign_in_failed());
+        }
+        let token_response = response
+            .error_for_status()
+            .map_err(|error| {
+                error!("identity provider token request failed. {}", error);
+                provider_unavailable()
+            })?
+            .json::<TokenResponse>()
+            .await
+            .map_err(|error| {
+                error!("invalid identity provider token response. {}", error);
+                provider_unavailable()
+            })?;
+
+        let claims = self
+            .verify_id_token(config, provider, &token_response.id_token)
+            .await?;
+        // The nonce binds the id token to the sign-in started by this browser
+        if claims.nonce.as_deref() != Some(login_state.nonce.as_str()) {
+            warn!("id token nonce does not match");
+            return Err(sign_in_failed());
+        }
+        Ok(claims)
+    }
+
+    async fn verify_id_token(
+        &self,
+        config: &ProviderConfig,
+        mut provider: CachedProvider,
+        id_token: &str,
+    ) -> Result<IdTokenClaims, (StatusCode, Json<ErrorResponse>)> {
+        let header = decode_header(id_token).map_err(|error| {
+            warn!("invalid id token header. {}", error);
+            sign_in_failed()
+        })?;
+        if !ID_TOKEN_ALGORITHMS.contains(&header.alg) {
+            warn!(
+                "id token signed with unsupported algorithm {:?}",
+                header.alg
+            );
+            return Err(sign_in_failed());
+        }
+
+        // Keys are looked up by id, a provider with a single key may leave the id out
+        let find_key = |keys: &JwkSet| match &header.kid {
+            Some(kid) => keys.find(kid).cloned(),
+            None if keys.keys.len() == 1 => keys.keys.first().cloned(),
+            None => None,
+        };
+        let jwk = match find_key(&provider.keys) {
+            Some(jwk) => jwk,
+            None => {
+                // The provider may have rotated its keys since they were cached
+                debug!("signing key not found, going to refresh provider keys");
+                provider = self.provider(config, true).await?;
+                find_key(&provider.keys).ok_or_else(|| {
+                    warn!("id token signing key {:?} is unknown", header.kid);
+                    sign_in_failed()
+                })?
+            }
+        };
+        let key = DecodingKey::from_jwk(&jwk).map_err(|error| {
+            error!("invalid identity provider signing key. {}", error);
+            provider_unavailable()
+        })?;
+
+        let mut validation = Validation::new(header.alg);
+        validation.set_audience(&[&config.client_id]);
+        validation.set_issuer(&[&provider.metadata.issuer]);
+        decode::<IdTokenClaims>(id_token, &key, &validation)
+            .map(|TokenData { claims, .. }| claims)
+            .map_err(|error| {
+                warn!("invalid id token. {}", error);
+                sign_in_failed()
+            })
+    }
+}
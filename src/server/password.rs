@@ -0,0 +1,185 @@
+use crate::config::{invalid_config, parse_env};
+use crate::logger::Secret;
+use crate::server::metrics::metrics;
+use argon2::password_hash::rand_core::OsRng;
+use argon2::password_hash::{
+    self, PasswordHash, PasswordHasher as _, PasswordVerifier, SaltString,
+};
+use argon2::{Argon2, Params, Version};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use bcrypt::HashParts;
+use dotenvy::var;
+
+// Algorithm used for new password hashes, existing hashes of the other one are still verified
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PasswordAlgorithm {
+    Bcrypt,
+    Argon2id,
+}
+
+// Hashing is CPU bound and takes a long time on purpose, so it runs on the blocking thread pool
+#[derive(Clone, Debug)]
+pub struct PasswordHasher {
+    algorithm: PasswordAlgorithm,
+    bcrypt_cost: u32,
+    argon2_params: Params,
+    // Hash of a random password with the configured parameters, checked when there is no real hash
+    dummy_hash: Secret<String>,
+}
+
+impl PasswordHasher {
+    pub fn new(
+        algorithm: PasswordAlgorithm,
+        bcrypt_cost: u32,
+        argon2_params: Params,
+    ) -> Result<Self, anyhow::Error> {
+        let mut hasher = Self {
+            algorithm,
+            bcrypt_cost,
+            argon2_params,
+            dummy_hash: Secret::new(String::new()),
+        };
+        let dummy_password: [u8; 16] = rand::random();
+        hasher.dummy_hash =
+            Secret::new(hasher.hash_blocking(&URL_SAFE_NO_PAD.encode(dummy_password))?);
+        Ok(hasher)
+    }
+
+    // Argon2id defaults follow the OWASP recommendation of 19 MiB memory, 2 iterations and 1 lane
+    pub fn from_env() -> Result<Self, anyhow::Error> {
+        let algorithm = match var("PASSWORD_HASH_ALGORITHM")
+            .unwrap_or_else(|_| "argon2id".to_string())
+            .trim()
+        {
+            "argon2id" => PasswordAlgorithm::Argon2id,
+            "bcrypt" => PasswordAlgorithm::Bcrypt,
+            _ => {
+                return Err(invalid_config(
+                    "PASSWORD_HASH_ALGORITHM",
+                    "'argon2id' or 'bcrypt'",
+                ))
+            }
+        };
+        let bcrypt_cost = parse_env(
+            "PASSWORD_BCRYPT_COST",
+            bcrypt::DEFAULT_COST,
+            "a cost between 4 and 31",
+        )?;
+        if !(4..=31).contains(&bcrypt_cost) {
+            return Err(invalid_config(
+                "PASSWORD_BCRYPT_COST",
+                "a cost between 4 and 31",
+            ));
+        }
+        let argon2_params = Params::new(
+            parse_env(
+                "PASSWORD_ARGON2_MEMORY_KIB",
+                19 * 1024,
+                "a memory size in KiB",
+            )?,
+            parse_env("PASSWORD_ARGON2_ITERATIONS", 2, "a number of iterations")?,
+            parse_env("PASSWORD_ARGON2_PARALLELISM", 1, "a number of lanes")?,
+            None,
+        )
+        .map_err(|error| {
+            anyhow::anyhow!(
+                "Invalid config for environment variables PASSWORD_ARGON2_*. {}.",
+                error
+            )
+        })?;
+
+        Self::new(algorithm, bcrypt_cost, argon2_params)
+    }
+
+    fn argon2(&self) -> Argon2<'static> {
+        Argon2::new(
+            argon2::Algorithm::Argon2id,
+            Version::V0x13,
+            self.argon2_params.clone(),
+        )
+    }
+
+    fn hash_blocking(&self, password: &str) -> Result<String, anyhow::Error> {
+        match self.algorithm {
+            PasswordAlgorithm::Bcrypt => Ok(bcrypt::hash(password, self.bcrypt_cost)?),
+            PasswordAlgorithm::Argon2id => {
+                let salt = SaltString::generate(&mut OsRng);
+                self.argon2()
+                    .hash_password(password.as_bytes(), &salt)
+                    .map(|hash| hash.to_string())
+                    .map_err(|error| anyhow::anyhow!("argon2 hashing failed. {}", error))
+            }
+        }
+    }
+
+    // The algorithm and its parameters are read from the stored hash, not from the config
+    fn verify_blocking(password: &str, password_hash: &str) -> Result<bool, anyhow::Error> {
+        if password_hash.starts_with("$argon2") {
+            let parsed = PasswordHash::new(password_hash)
+                .map_err(|error| anyhow::anyhow!("invalid argon2 hash. {}", error))?;
+            match Argon2::default().verify_password(password.as_bytes(), &parsed) {
+                Ok(()) => Ok(true),
+                Err(password_hash::Error::Password) => Ok(false),
+                Err(error) => Err(anyhow::anyhow!("argon2 verification failed. {}", error)),
+            }
+        } else {
+            Ok(bcrypt::verify(password, password_hash)?)
+        }
+    }
+
+    pub async fn hash(&self, password: &Secret<String>) -> Result<Secret<String>, anyhow::Error> {
+        let hasher = self.clone();
+        let password = password.clone();
+        tokio::task::spawn_blocking(move || {
+            metrics().observe_password_hashing("hash", || hasher.hash_blocking(password.expose()))
+        })
+        .await?
+        .map(Secret::new)
+    }
+
+    pub async fn verify(
+        &self,
+        password: &Secret<String>,
+        password_hash: &Secret<String>,
+    ) -> Result<bool, anyhow::Error> {
+        let password = password.clone();
+        let password_hash = password_hash.clone();
+        tokio::task::spawn_blocking(move || {
+            metrics().observe_password_hashing("verify", || {
+                Self::verify_blocking(password.expose(), password_hash.expose())
+            })
+        })
+        .await?
+    }
+
+    // Take as long as a real verification when there is no hash to check, so that the response time
+    // does not tell whether a user exists. Always false
+    pub async fn verify_dummy(&self, password: &Secret<String>) -> Result<bool, anyhow::Error> {
+        let dummy_hash = self.dummy_hash.clone();
+        self.verify(password, &dummy_hash).await?;
+        Ok(false)
+    }
+
+    // Hashes from another algorithm or with other parameters than configured are replaced on login
+    pub fn needs_rehash(&self, password_hash: &Secret<String>) -> bool {
+        let password_hash = password_hash.expose();
+        match self.algorithm {
+            PasswordAlgorithm::Bcrypt => password_hash
+                .parse::<HashParts>()
+                .map_or(true, |parts| parts.get_cost() != self.bcrypt_cost),
+            PasswordAlgorithm::Argon2id => {
+                let Ok(parsed) = PasswordHash::new(password_hash) else {
+                    return true;
+                };
+                parsed.algorithm != argon2::ARGON2ID_IDENT
+                    || parsed.version != Some(Version::V0x13.into())
+                    || Params::try_from(&parsed).map_or(true, |params| {
+                        params.m_cost() != self.argon2_params.m_cost()
+                            || params.t_cost() != self.argon2_params.t_cost()
+                            || params.p_cost() != self.argon2_params.p_cost()
+                    })
+            }
+        }
+    }
+}
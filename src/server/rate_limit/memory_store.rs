@@ -0,0 +1,184 @@
+use super::{RateLimitDecision, RateLimitPolicy, RateLimitStore};
+use axum::async_trait;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// Prune idle buckets once the map grows beyond this size
+const PRUNE_THRESHOLD: usize = 10_000;
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated_at: Instant,
+    // Groups share the map, so every bucket remembers how long it takes to fill up again
+    period: Duration,
+}
+
+// Token buckets kept in process memory, only suitable for a single server instance
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+impl MemoryStore {
+    fn acquire_at(
+        &self,
+        key: &str,
+        policy: &RateLimitPolicy,
+        now: Instant,
+    ) -> Result<RateLimitDecision, anyhow::Error> {
+        let capacity = policy.capacity as f64;
+        let refill_rate = policy.refill_rate();
+        let mut buckets = self
+            .buckets
+            .lock()
+            .map_err(|_| anyhow::anyhow!("rate limit memory store lock poisoned"))?;
+
+        // A bucket idle for a whole period of its own policy is full again, so it is safe to forget it
+        if buckets.len() > PRUNE_THRESHOLD {
+            buckets.retain(|_, bucket| now.duration_since(bucket.updated_at) < bucket.period);
+        }
+
+        let bucket = buckets.entry(key.to_string()).or_insert(Bucket {
+            tokens: capacity,
+            updated_at: now,
+            period: policy.period,
+        });
+        let elapsed = now.duration_since(bucket.updated_at).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * refill_rate).min(capacity);
+        bucket.updated_at = now;
+        bucket.period = policy.period;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(RateLimitDecision::Allowed)
+        } else {
+            Ok(RateLimitDecision::Limited {
+                retry_after: Duration::from_secs_f64((1.0 - bucket.tokens) / refill_rate),
+            })
+        }
+    }
+}
+
+#[async_trait]
+impl RateLimitStore for MemoryStore {
+    async fn acquire(
+        &self,
+        key: &str,
+        policy: &RateLimitPolicy,
+    ) -> Result<RateLimitDecision, anyhow::Error> {
+        self.acquire_at(key, policy, Instant::now())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(capacity: u32, seconds: u64) -> RateLimitPolicy {
+        RateLimitPolicy {
+            capacity,
+            period: Duration::from_secs(seconds),
+        }
+    }
+
+    fn is_allowed(decision: RateLimitDecision) -> bool {
+        decision == RateLimitDecision::Allowed
+    }
+
+    #[test]
+    fn allows_up_to_capacity_then_limits() {
+        let store = MemoryStore::default();
+        let policy = policy(3, 60);
+        let now = Instant::now();
+
+        for _ in 0..3 {
+            assert!(is_allowed(store.acquire_at("key", &policy, now).unwrap()));
+        }
+        // One token comes back every 20 seconds
+        assert_eq!(
+            store.acquire_at("key", &policy, now).unwrap(),
+            RateLimitDecision::Limited {
+                retry_after: Duration::from_secs(20)
+            }
+        );
+    }
+
+    #[test]
+    fn refills_over_time_without_exceeding_capacity() {
+        let store = MemoryStore::default();
+        let policy = policy(2, 10);
+        let now = Instant::now();
+        store.acquire_at("key", &policy, now).unwrap();
+        store.acquire_at("key", &policy, now).unwrap();
+
+        let later = now + Duration::from_secs(5);
+        assert!(is_allowed(store.acquire_at("key", &policy, later).unwrap()));
+        assert!(!is_allowed(
+            store.acquire_at("key", &policy, later).unwrap()
+        ));
+
+        // Idle for far longer than the period, the bucket holds no more than its capacity
+        let much_later = later + Duration::from_secs(600);
+        assert!(is_allowed(
+            store.acquire_at("key", &policy, much_later).unwrap()
+        ));
+        assert!(is_allowed(
+            store.acquire_at("key", &policy, much_later).unwrap()
+        ));
+        assert!(!is_allowed(
+            store.acquire_at("key", &policy, much_later).unwrap()
+        ));
+    }
+
+    #[test]
+    fn keys_are_limited_independently() {
+        let store = MemoryStore::default();
+        let policy = policy(1, 60);
+        let now = Instant::now();
+
+        assert!(is_allowed(store.acquire_at("first", &policy, now).unwrap()));
+        assert!(!is_allowed(
+            store.acquire_at("first", &policy, now).unwrap()
+        ));
+        assert!(is_allowed(
+            store.acquire_at("second", &policy, now).unwrap()
+        ));
+    }
+
+    #[test]
+    fn pruning_keeps_buckets_of_longer_periods() {
+        let store = MemoryStore::default();
+        let long = policy(1, 300);
+        let short = policy(10, 1);
+        let now = Instant::now();
+        store.acquire_at("long", &long, now).unwrap();
+
+        // Pruning is triggered by a group with a much shorter period
+        let later = now + Duration::from_secs(10);
+        for index in 0..=PRUNE_THRESHOLD {
+            store
+                .acquire_at(&format!("short:{}", index), &short, later)
+                .unwrap();
+        }
+        assert!(!is_allowed(store.acquire_at("long", &long, later).unwrap()));
+    }
+
+    #[test]
+    fn pruning_forgets_buckets_idle_for_their_period() {
+        let store = MemoryStore::default();
+        let short = policy(1, 1);
+        let now = Instant::now();
+        for index in 0..=PRUNE_THRESHOLD {
+            store
+                .acquire_at(&format!("short:{}", index), &short, now)
+                .unwrap();
+        }
+
+        store
+            .acquire_at("trigger", &short, now + Duration::from_secs(2))
+            .unwrap();
+        assert_eq!(store.buckets.lock().unwrap().len(), 1);
+    }
+}
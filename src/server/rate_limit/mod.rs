@@ -0,0 +1,366 @@
+mod memory_store;
+mod redis_store;
+
+use super::handlers::ErrorResponse;
+use super::metrics::metrics;
+use super::{auth, two_factor};
+use axum::async_trait;
+use axum::extract::ConnectInfo;
+use axum::http::{header, Extensions, HeaderMap, HeaderValue, Request, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use dotenvy::var;
+pub use memory_store::MemoryStore;
+use redis_store::RedisStore;
+use std::fmt::Debug;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tower::{Layer, Service};
+use tracing::{error, info, warn};
+
+// Token bucket holding `capacity` tokens which are fully refilled every `period`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RateLimitPolicy {
+    pub capacity: u32,
+    pub period: Duration,
+}
+
+impl RateLimitPolicy {
+    // Parse policy in the format of '<requests>/<seconds>', e.g. '10/60'
+    fn parse(value: &str) -> Option<Self> {
+        let (capacity, seconds) = value.trim().split_once('/')?;
+        let capacity = capacity.trim().parse::<u32>().ok()?;
+        let seconds = seconds.trim().parse::<u64>().ok()?;
+        if capacity == 0 || seconds == 0 {
+            return None;
+        }
+        Some(Self {
+            capacity,
+            period: Duration::from_secs(seconds),
+        })
+    }
+
+    fn from_env(name: &str, default: RateLimitPolicy) -> Result<Self, anyhow::Error> {
+        match var(name) {
+            Ok(value) => Self::parse(&value).ok_or_else(|| {
+                anyhow::anyhow!(
+                    "Invalid config for environment variable {}. Expected '<requests>/<seconds>'.",
+                    name
+                )
+            }),
+            Err(_) => Ok(default),
+        }
+    }
+
+    // Number of tokens added back to the bucket per second
+    fn refill_rate(&self) -> f64 {
+        self.capacity as f64 / self.period.as_secs_f64()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RateLimitDecision {
+    Allowed,
+    Limited { retry_after: Duration },
+}
+
+#[async_trait]
+pub trait RateLimitStore: Debug + Send + Sync {
+    // Take one token from the bucket identified by key
+    async fn acquire(
+        &self,
+        key: &str,
+        policy: &RateLimitPolicy,
+    ) -> Result<RateLimitDecision, anyhow::Error>;
+
+    // Name of the external service backing the store, checked by the readiness probe
+    fn service(&self) -> Option<&'static str> {
+        None
+    }
+
+    async fn ping(&self) -> Result<(), anyhow::Error> {
+        Ok(())
+    }
+}
+
+// Limits applied to one group of routes, per client IP and per authenticated user
+#[derive(Clone, Debug)]
+pub struct RouteGroupLimits {
+    pub name: &'static str,
+    pub per_ip: RateLimitPolicy,
+    pub per_user: RateLimitPolicy,
+}
+
+impl RouteGroupLimits {
+    // Read 'RATE_LIMIT_<GROUP>_IP' and 'RATE_LIMIT_<GROUP>_USER' with fallback to the given defaults
+    pub fn from_env(defaults: Self) -> Result<Self, anyhow::Error> {
+        let prefix = format!("RATE_LIMIT_{}", defaults.name.to_uppercase());
+        Ok(Self {
+            name: defaults.name,
+            per_ip: RateLimitPolicy::from_env(&format!("{}_IP", prefix), defaults.per_ip)?,
+            per_user: RateLimitPolicy::from_env(&format!("{}_USER", prefix), defaults.per_user)?,
+        })
+    }
+}
+
+// Limits of every rate limited route group, parsed once on start
+#[derive(Clone, Debug)]
+pub struct RateLimits {
+    // Registration, activation and login get much tighter limits as they are brute force targets
+    pub auth: RouteGroupLimits,
+    pub api: RouteGroupLimits,
+    // Code attempts share one group, so switching between the login and disable endpoints doesn't help
+    pub two_factor: RouteGroupLimits,
+}
+
+impl Default for RateLimits {
+    fn default() -> Self {
+        Self {
+            auth: RouteGroupLimits {
+                name: "auth",
+                per_ip: RateLimitPolicy {
+                    capacity: 10,
+                    period: Duration::from_secs(60),
+                },
+                per_user: RateLimitPolicy {
+                    capacity: 10,
+                    period: Duration::from_secs(60),
+                },
+            },
+            api: RouteGroupLimits {
+                name: "api",
+                per_ip: RateLimitPolicy {
+                    capacity: 300,
+                    period: Duration::from_secs(60),
+                },
+                per_user: RateLimitPolicy {
+                    capacity: 120,
+                    period: Duration::from_secs(60),
+                },
+            },
+            two_factor: two_factor::rate_limits(),
+        }
+    }
+}
+
+impl RateLimits {
+    pub fn from_env() -> Result<Self, anyhow::Error> {
+        let defaults = Self::default();
+        Ok(Self {
+            auth: RouteGroupLimits::from_env(defaults.auth)?,
+            api: RouteGroupLimits::from_env(defaults.api)?,
+            two_factor: RouteGroupLimits::from_env(defaults.two_factor)?,
+        })
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct RateLimiter {
+    store: Arc<dyn RateLimitStore>,
+    // Only trust 'X-Forwarded-For' when the server sits behind a reverse proxy
+    trust_proxy: bool,
+}
+
+impl RateLimiter {
+    pub fn new(store: Arc<dyn RateLimitStore>, trust_proxy: bool) -> Self {
+        Self { store, trust_proxy }
+    }
+
+    // Build the rate limiter from 'RATE_LIMIT_STORE' (memory or redis) and related environment variables
+    pub async fn from_env() -> Result<Self, anyhow::Error> {
+        let trust_proxy = var("RATE_LIMIT_TRUST_PROXY")
+            .map(|value| value == "true")
+            .unwrap_or(false);
+
+        match var("RATE_LIMIT_STORE").as_deref() {
+            Ok("redis") => {
+                let url = var("REDIS").map_err(|e| {
+                    anyhow::anyhow!("Missing config for environment variable REDIS. {}", e)
+                })?;
+                let store = RedisStore::connect(&url)
+                    .await
+                    .map_err(|e| anyhow::anyhow!("Cannot initiate redis connection. {}", e))?;
+                info!("rate limiter is using redis store");
+                Ok(Self::new(Arc::new(store), trust_proxy))
+            }
+            Ok("memory") | Err(_) => {
+                info!("rate limiter is using in-memory store");
+                Ok(Self::new(Arc::new(MemoryStore::default()), trust_proxy))
+            }
+            Ok(other) => Err(anyhow::anyhow!(
+                "Invalid config for environment variable RATE_LIMIT_STORE. Unknown store {}.",
+                other
+            )),
+        }
+    }
+
+    pub fn service(&self) -> Option<&'static str> {
+        self.store.service()
+    }
+
+    pub async fn ping(&self) -> Result<(), anyhow::Error> {
+        self.store.ping().await
+    }
+
+    pub fn layer(&self, limits: RouteGroupLimits) -> RateLimitLayer {
+        RateLimitLayer {
+            limiter: self.clone(),
+            limits: Arc::new(limits),
+        }
+    }
+
+    // The forwarded header is only trusted behind a proxy, otherwise the peer address is used
+    pub fn client_ip(&self, headers: &HeaderMap, extensions: &Extensions) -> String {
+        if self.trust_proxy {
+            let forwarded_ip = headers
+                .get("x-forwarded-for")
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.split(',').next())
+                .map(|ip| ip.trim().to_string());
+            if let Some(ip) = forwarded_ip {
+                return ip;
+            }
+        }
+        extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(address)| address.ip().to_string())
+            .unwrap_or_else(|| "unknown".to_string())
+    }
+
+    // Only requests with a valid access token count against the per-user bucket
+    fn authenticated_user(headers: &HeaderMap) -> Option<String> {
+        auth::extract_access_token(headers)
+            .and_then(|access_token| auth::decode_access_token(&access_token).ok())
+            .map(|claims| claims.sub.to_string())
+    }
+
+    async fn check(
+        &self,
+        limits: &RouteGroupLimits,
+        ip: String,
+        user: Option<String>,
+    ) -> RateLimitDecision {
+        let mut buckets = vec![(
+            "ip",
+            format!("rate_limit:{}:ip:{}", limits.name, ip),
+            limits.per_ip,
+        )];
+        if let Some(user) = user {
+            buckets.push((
+                "user",
+                format!("rate_limit:{}:user:{}", limits.name, user),
+                limits.per_user,
+            ));
+        }
+
+        self.acquire_buckets(limits, buckets).await
+    }
+
+    // Check only the per-user bucket, for requests that identify the user by other means than an access token
+    pub async fn check_user(&self, limits: &RouteGroupLimits, user: &str) -> RateLimitDecision {
+        let bucket = (
+            "user",
+            format!("rate_limit:{}:user:{}", limits.name, user),
+            limits.per_user,
+        );
+        self.acquire_buckets(limits, vec![bucket]).await
+    }
+
+    async fn acquire_buckets(
+        &self,
+        limits: &RouteGroupLimits,
+        buckets: Vec<(&'static str, String, RateLimitPolicy)>,
+    ) -> RateLimitDecision {
+        for (scope, key, policy) in buckets {
+            match self.store.acquire(&key, &policy).await {
+                Ok(RateLimitDecision::Allowed) => {}
+                Ok(decision) => {
+                    warn!("rate limit exceeded for {}", key);
+                    metrics().record_rate_limit_rejection(limits.name, scope);
+                    return decision;
+                }
+                // Fail open so that an unavailable store doesn't take the whole API down
+                Err(e) => error!("rate limit store error. {}", e),
+            }
+        }
+        RateLimitDecision::Allowed
+    }
+}
+
+pub fn too_many_requests(retry_after: Duration) -> Response {
+    // Retry-After only accepts whole seconds
+    let retry_after_seconds = retry_after.as_secs_f64().ceil().max(1.0) as u64;
+    let mut response = (
+        StatusCode::TOO_MANY_REQUESTS,
+        Json(ErrorResponse {
+            success: false,
+            error: "Too many requests. Please try again later.".to_string(),
+        }),
+    )
+        .into_response();
+    response
+        .headers_mut()
+        .insert(header::RETRY_AFTER, HeaderValue::from(retry_after_seconds));
+    response
+}
+
+#[derive(Clone, Debug)]
+pub struct RateLimitLayer {
+    limiter: RateLimiter,
+    limits: Arc<RouteGroupLimits>,
+}
+
+impl<S> Layer<S> for RateLimitLayer {
+    type Service = RateLimit<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RateLimit {
+            inner,
+            limiter: self.limiter.clone(),
+            limits: self.limits.clone(),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct RateLimit<S> {
+    inner: S,
+    limiter: RateLimiter,
+    limits: Arc<RouteGroupLimits>,
+}
+
+impl<S, B> Service<Request<B>> for RateLimit<S>
+where
+    S: Service<Request<B>, Response = Response> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+    B: Send + 'static,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<B>) -> Self::Future {
+        // The service that has been driven to readiness is the one that should handle the request
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let limiter = self.limiter.clone();
+        let limits = self.limits.clone();
+        let ip = limiter.client_ip(request.headers(), request.extensions());
+        let user = RateLimiter::authenticated_user(request.headers());
+
+        Box::pin(async move {
+            match limiter.check(&limits, ip, user).await {
+                RateLimitDecision::Allowed => inner.call(request).await,
+                RateLimitDecision::Limited { retry_after } => Ok(too_many_requests(retry_after)),
+            }
+        })
+    }
+}
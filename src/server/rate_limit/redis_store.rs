@@ -0,0 +1,88 @@
+use super::{RateLimitDecision, RateLimitPolicy, RateLimitStore};
+use axum::async_trait;
+use redis::aio::ConnectionManager;
+use redis::Script;
+use std::fmt;
+use std::time::Duration;
+
+// Refill and take one token atomically, using redis server time so that all instances share one clock
+const TOKEN_BUCKET_SCRIPT: &str = r#"
+local capacity = tonumber(ARGV[1])
+local refill_per_ms = tonumber(ARGV[2])
+local time = redis.call('TIME')
+local now = tonumber(time[1]) * 1000 + math.floor(tonumber(time[2]) / 1000)
+local bucket = redis.call('HMGET', KEYS[1], 'tokens', 'updated_at')
+local tokens = tonumber(bucket[1]) or capacity
+local updated_at = tonumber(bucket[2]) or now
+tokens = math.min(capacity, tokens + math.max(0, now - updated_at) * refill_per_ms)
+local retry_after_ms = 0
+if tokens >= 1 then
+  tokens = tokens - 1
+else
+  retry_after_ms = math.ceil((1 - tokens) / refill_per_ms)
+end
+redis.call('HSET', KEYS[1], 'tokens', tostring(tokens), 'updated_at', now)
+redis.call('PEXPIRE', KEYS[1], math.ceil(capacity / refill_per_ms))
+return retry_after_ms
+"#;
+
+// Token buckets shared by every server instance through redis
+pub struct RedisStore {
+    connection: ConnectionManager,
+    script: Script,
+}
+
+impl RedisStore {
+    pub async fn connect(url: &str) -> Result<Self, redis::RedisError> {
+        let client = redis::Client::open(url)?;
+        let connection = ConnectionManager::new(client).await?;
+        Ok(Self {
+            connection,
+            script: Script::new(TOKEN_BUCKET_SCRIPT),
+        })
+    }
+}
+
+impl fmt::Debug for RedisStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RedisStore").finish_non_exhaustive()
+    }
+}
+
+#[async_trait]
+impl RateLimitStore for RedisStore {
+    async fn acquire(
+        &self,
+        key: &str,
+        policy: &RateLimitPolicy,
+    ) -> Result<RateLimitDecision, anyhow::Error> {
+        let refill_per_ms = policy.refill_rate() / 1000.0;
+        let mut connection = self.connection.clone();
+        let retry_after_ms: u64 = self
+            .script
+            .key(key)
+            .arg(policy.capacity)
+            .arg(refill_per_ms)
+            .invoke_async(&mut connection)
+            .await?;
+
+        if retry_after_ms == 0 {
+            Ok(RateLimitDecision::Allowed)
+        } else {
+            Ok(RateLimitDecision::Limited {
+                retry_after: Duration::from_millis(retry_after_ms),
+            })
+        }
+    }
+    fn service(&self) -> Option<&'static str> {
+        Some("redis")
+    }
+
+    async fn ping(&self) -> Result<(), anyhow::Error> {
+        let mut connection = self.connection.clone();
+        redis::cmd("PING")
+            .query_async::<_, String>(&mut connection)
+            .await?;
+        Ok(())
+    }
+}
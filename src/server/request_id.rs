@@ -0,0 +1,214 @@
+use axum::http::{HeaderMap, HeaderName, HeaderValue, Request};
+use axum::response::Response;
+use opentelemetry::propagation::Extractor;
+use opentelemetry::trace::TraceContextExt;
+use rand::RngCore;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tower::{Layer, Service};
+use tracing::Span;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use uuid::Uuid;
+
+pub const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+pub const TRACEPARENT_HEADER: HeaderName = HeaderName::from_static("traceparent");
+
+// Longer or unusual request IDs from clients are replaced so they can't pollute the logs
+const MAX_REQUEST_ID_LENGTH: usize = 128;
+
+tokio::task_local! {
+    static REQUEST_CONTEXT: RequestContext;
+}
+
+// Identifiers correlating a request across logs, error responses and other services
+#[derive(Clone, Debug)]
+pub struct RequestContext {
+    pub request_id: String,
+    pub trace_id: String,
+    pub span_id: String,
+    pub sampled: bool,
+}
+
+impl RequestContext {
+    fn from_headers(headers: &HeaderMap) -> Self {
+        let request_id = headers
+            .get(&REQUEST_ID_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .filter(|value| is_valid_request_id(value))
+            .map(|value| value.to_string())
+            .unwrap_or_else(|| Uuid::new_v4().to_string());
+
+        // Spans exported through OpenTelemetry already carry the ids, including the remote parent
+        let span_context = Span::current().context().span().span_context().clone();
+        if span_context.is_valid() {
+            return Self {
+                request_id,
+                trace_id: span_context.trace_id().to_string(),
+                span_id: span_context.span_id().to_string(),
+                sampled: span_context.is_sampled(),
+            };
+        }
+
+        // Otherwise continue the caller's trace when there is one and start a new one if not
+        let (trace_id, sampled) = headers
+            .get(&TRACEPARENT_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(parse_traceparent)
+            .unwrap_or_else(|| (random_hex::<16>(), true));
+        Self {
+            request_id,
+            trace_id,
+            span_id: random_hex::<8>(),
+            sampled,
+        }
+    }
+
+    // W3C trace context pointing at this server as the parent of any downstream call
+    pub fn traceparent(&self) -> String {
+        format!(
+            "00-{}-{}-{}",
+            self.trace_id,
+            self.span_id,
+            if self.sampled { "01" } else { "00" }
+        )
+    }
+
+    // Context of the request currently being handled, None outside of a request
+    pub fn current() -> Option<Self> {
+        REQUEST_CONTEXT.try_with(|context| context.clone()).ok()
+    }
+}
+
+fn is_valid_request_id(value: &str) -> bool {
+    !value.is_empty()
+        && value.len() <= MAX_REQUEST_ID_LENGTH
+        && value
+            .chars()
+            .all(|char| char.is_ascii_alphanumeric() || matches!(char, '-' | '_' | '.'))
+}
+
+fn is_lower_hex(value: &str, length: usize) -> bool {
+    value.len() == length
+        && value
+            .chars()
+            .all(|char| matches!(char, '0'..='9' | 'a'..='f'))
+}
+
+// Parse 'version-trace_id-parent_id-flags' and return the trace id and sampled flag
+fn parse_traceparent(value: &str) -> Option<(String, bool)> {
+    let mut parts = value.trim().split('-');
+    let (version, trace_id, parent_id, flags) =
+        (parts.next()?, parts.next()?, parts.next()?, parts.next()?);
+    // Version 00 has exactly four parts, later versions may append more
+    if version == "ff" || !is_lower_hex(version, 2) || (version == "00" && parts.next().is_some()) {
+        return None;
+    }
+    if !is_lower_hex(trace_id, 32)
+        || trace_id.chars().all(|char| char == '0')
+        || !is_lower_hex(parent_id, 16)
+        || parent_id.chars().all(|char| char == '0')
+        || !is_lower_hex(flags, 2)
+    {
+        return None;
+    }
+    let sampled = u8::from_str_radix(flags, 16).ok()? & 1 == 1;
+    Some((trace_id.to_string(), sampled))
+}
+
+fn random_hex<const N: usize>() -> String {
+    let mut bytes = [0u8; N];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+struct HeaderExtractor<'a>(&'a HeaderMap);
+
+impl<'a> Extractor for HeaderExtractor<'a> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|value| value.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(|key| key.as_str()).collect()
+    }
+}
+
+// Span for every request, request_id, trace_id and the response status are recorded by the RequestIdLayer
+pub fn make_request_span<B>(request: &Request<B>) -> Span {
+    let span = tracing::debug_span!(
+        "request",
+        method = %request.method(),
+        uri = %request.uri(),
+        version = ?request.version(),
+        request_id = tracing::field::Empty,
+        trace_id = tracing::field::Empty,
+        status = tracing::field::Empty,
+    );
+    // Exported spans continue the caller's trace, this is a no-op unless OpenTelemetry is enabled
+    let parent = opentelemetry::global::get_text_map_propagator(|propagator| {
+        propagator.extract(&HeaderExtractor(request.headers()))
+    });
+    span.set_parent(parent);
+    span
+}
+
+// Must be applied inside the TraceLayer so that the ids are recorded on the request span
+#[derive(Clone, Debug, Default)]
+pub struct RequestIdLayer;
+
+impl<S> Layer<S> for RequestIdLayer {
+    type Service = RequestId<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RequestId { inner }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct RequestId<S> {
+    inner: S,
+}
+
+impl<S, B> Service<Request<B>> for RequestId<S>
+where
+    S: Service<Request<B>, Response = Response> + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut request: Request<B>) -> Self::Future {
+        let context = RequestContext::from_headers(request.headers());
+        let span = Span::current();
+        span.record("request_id", context.request_id.as_str());
+        span.record("trace_id", context.trace_id.as_str());
+
+        // Handlers further down can read the generated id from the request as well
+        if let Ok(value) = HeaderValue::from_str(&context.request_id) {
+            request.headers_mut().insert(REQUEST_ID_HEADER, value);
+        }
+        request.extensions_mut().insert(context.clone());
+        let future = self.inner.call(request);
+
+        // Error responses built while the request is handled pick the ids up from the task local
+        Box::pin(REQUEST_CONTEXT.scope(context.clone(), async move {
+            let mut response = future.await?;
+            // Logged with the elapsed time once the request span closes
+            span.record("status", response.status().as_u16());
+            let headers = response.headers_mut();
+            if let Ok(value) = HeaderValue::from_str(&context.request_id) {
+                headers.insert(REQUEST_ID_HEADER, value);
+            }
+            if let Ok(value) = HeaderValue::from_str(&context.traceparent()) {
+                headers.insert(TRACEPARENT_HEADER, value);
+            }
+            Ok(response)
+        }))
+    }
+}
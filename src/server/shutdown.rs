@@ -0,0 +1,51 @@
+use dotenvy::var;
+use std::future::Future;
+use std::time::Duration;
+use tokio::signal;
+use tracing::info;
+
+// Time from the shutdown signal until the process exits, shared by draining in-flight requests
+// and closing the database pool
+const DEFAULT_GRACE_PERIOD: Duration = Duration::from_secs(30);
+
+pub fn grace_period_from_env() -> Result<Duration, anyhow::Error> {
+    match var("SHUTDOWN_GRACE_PERIOD_SECONDS") {
+        Ok(value) => value.parse::<u64>().map(Duration::from_secs).map_err(|_| {
+            anyhow::anyhow!(
+                "Invalid config for environment variable SHUTDOWN_GRACE_PERIOD_SECONDS. Expected a number of seconds."
+            )
+        }),
+        Err(_) => Ok(DEFAULT_GRACE_PERIOD),
+    }
+}
+
+// Install the SIGINT and SIGTERM handlers up front, the returned future resolves once either arrives
+#[cfg(unix)]
+pub fn signal() -> Result<impl Future<Output = ()>, anyhow::Error> {
+    use signal::unix::SignalKind;
+
+    let mut interrupt = signal::unix::signal(SignalKind::interrupt())
+        .map_err(|error| anyhow::anyhow!("Failed to install SIGINT handler. {}", error))?;
+    let mut terminate = signal::unix::signal(SignalKind::terminate())
+        .map_err(|error| anyhow::anyhow!("Failed to install SIGTERM handler. {}", error))?;
+    Ok(async move {
+        tokio::select! {
+            _ = interrupt.recv() => info!("received SIGINT, shutting down"),
+            _ = terminate.recv() => info!("received SIGTERM, shutting down"),
+        }
+    })
+}
+
+// Only Ctrl-C exists outside of unix, its handler can only be installed by waiting on it
+#[cfg(not(unix))]
+pub fn signal() -> Result<impl Future<Output = ()>, anyhow::Error> {
+    Ok(async {
+        match signal::ctrl_c().await {
+            Ok(()) => info!("received SIGINT, shutting down"),
+            Err(error) => {
+                tracing::error!("failed to install SIGINT handler. {}", error);
+                std::future::pending::<()>().await
+            }
+        }
+    })
+}
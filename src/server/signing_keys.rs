@@ -0,0 +1,199 @@
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use dotenvy::var;
+use jsonwebtoken::jwk::{
+    AlgorithmParameters, CommonParameters, EllipticCurve, Jwk, JwkSet, OctetKeyPairParameters,
+    OctetKeyPairType, PublicKeyUse, RSAKeyParameters, RSAKeyType,
+};
+use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey};
+use ring::signature::{Ed25519KeyPair, KeyPair, RsaKeyPair};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::sync::OnceLock;
+use tracing::info;
+
+// Private key of the key pair used to sign and verify access tokens
+pub struct SigningKey {
+    pub id: String,
+    pub algorithm: Algorithm,
+    pub encoding_key: EncodingKey,
+    pub decoding_key: DecodingKey,
+    jwk: Jwk,
+}
+
+// Every key in 'JWT_KEYS_DIR' verifies access tokens and is published, only one of them signs new ones
+// A key is rotated by adding the new key, switching 'JWT_SIGNING_KEY_ID' to it
+// and removing the old key once the tokens it signed have expired
+pub struct SigningKeys {
+    signing_key_id: String,
+    keys: BTreeMap<String, SigningKey>,
+}
+
+fn public_jwk(id: &str, algorithm: Algorithm, parameters: AlgorithmParameters) -> Jwk {
+    Jwk {
+        common: CommonParameters {
+            public_key_use: Some(PublicKeyUse::Signature),
+            algorithm: Some(algorithm),
+            key_id: Some(id.to_string()),
+            ..Default::default()
+        },
+        algorithm: parameters,
+    }
+}
+
+fn rsa_key(id: String, key_pair: RsaKeyPair, pem: &[u8]) -> Result<SigningKey, String> {
+    let public_key = key_pair.public_key();
+    let jwk = public_jwk(
+        &id,
+        Algorithm::RS256,
+        AlgorithmParameters::RSA(RSAKeyParameters {
+            key_type: RSAKeyType::RSA,
+            n: URL_SAFE_NO_PAD.encode(public_key.modulus().big_endian_without_leading_zero()),
+            e: URL_SAFE_NO_PAD.encode(public_key.exponent().big_endian_without_leading_zero()),
+        }),
+    );
+    Ok(SigningKey {
+        id,
+        algorithm: Algorithm::RS256,
+        encoding_key: EncodingKey::from_rsa_pem(pem).map_err(|error| error.to_string())?,
+        decoding_key: DecodingKey::from_jwk(&jwk).map_err(|error| error.to_string())?,
+        jwk,
+    })
+}
+
+fn ed25519_key(id: String, key_pair: Ed25519KeyPair, pem: &[u8]) -> Result<SigningKey, String> {
+    let jwk = public_jwk(
+        &id,
+        Algorithm::EdDSA,
+        AlgorithmParameters::OctetKeyPair(OctetKeyPairParameters {
+            key_type: OctetKeyPairType::OctetKeyPair,
+            curve: EllipticCurve::Ed25519,
+            x: URL_SAFE_NO_PAD.encode(key_pair.public_key().as_ref()),
+        }),
+    );
+    Ok(SigningKey {
+        id,
+        algorithm: Algorithm::EdDSA,
+        encoding_key: EncodingKey::from_ed_pem(pem).map_err(|error| error.to_string())?,
+        decoding_key: DecodingKey::from_jwk(&jwk).map_err(|error| error.to_string())?,
+        jwk,
+    })
+}
+
+// RSA keys are accepted as PKCS#1 or PKCS#8, Ed25519 keys as PKCS#8
+fn parse_key(id: String, pem: &[u8]) -> Result<SigningKey, String> {
+    let block = pem::parse(pem).map_err(|error| error.to_string())?;
+    match block.tag.as_str() {
+        "RSA PRIVATE KEY" => {
+            let key_pair =
+                RsaKeyPair::from_der(&block.contents).map_err(|error| error.to_string())?;
+            rsa_key(id, key_pair, pem)
+        }
+        "PRIVATE KEY" => match Ed25519KeyPair::from_pkcs8_maybe_unchecked(&block.contents) {
+            Ok(key_pair) => ed25519_key(id, key_pair, pem),
+            Err(_) => {
+                let key_pair =
+                    RsaKeyPair::from_pkcs8(&block.contents).map_err(|error| error.to_string())?;
+                rsa_key(id, key_pair, pem)
+            }
+        },
+        tag => Err(format!("Unsupported pem block {}", tag)),
+    }
+}
+
+impl SigningKeys {
+    // Load every '<kid>.pem' file of the directory, the file name becomes the key id
+    pub fn load(directory: &Path, signing_key_id: Option<String>) -> Result<Self, String> {
+        let entries = fs::read_dir(directory)
+            .map_err(|error| format!("Cannot read {}. {}", directory.display(), error))?;
+        let mut keys = BTreeMap::new();
+        for entry in entries {
+            let path = entry.map_err(|error| error.to_string())?.path();
+            if path.extension().and_then(|extension| extension.to_str()) != Some("pem") {
+                continue;
+            }
+            let Some(id) = path.file_stem().and_then(|stem| stem.to_str()) else {
+                continue;
+            };
+            let pem = fs::read(&path)
+                .map_err(|error| format!("Cannot read {}. {}", path.display(), error))?;
+            let key = parse_key(id.to_string(), &pem)
+                .map_err(|error| format!("Invalid key {}. {}", path.display(), error))?;
+            keys.insert(key.id.clone(), key);
+        }
+
+        if keys.is_empty() {
+            return Err(format!(
+                "No '<kid>.pem' key found in {}",
+                directory.display()
+            ));
+        }
+        let signing_key_id = match signing_key_id {
+            Some(id) if keys.contains_key(&id) => id,
+            Some(id) => return Err(format!("Signing key {} does not exist", id)),
+            // Without rotation in progress there is no doubt which key signs
+            None if keys.len() == 1 => keys.keys().next().unwrap().clone(),
+            None => {
+                return Err(
+                    "JWT_SIGNING_KEY_ID is required when there is more than one key".to_string(),
+                )
+            }
+        };
+        Ok(Self {
+            signing_key_id,
+            keys,
+        })
+    }
+
+    pub fn from_env() -> Result<Self, anyhow::Error> {
+        let directory = var("JWT_KEYS_DIR").map_err(|_| {
+            anyhow::anyhow!("Invalid config for environment variable JWT_KEYS_DIR. Expected a directory of '<kid>.pem' private keys.")
+        })?;
+        let signing_key_id = var("JWT_SIGNING_KEY_ID")
+            .ok()
+            .map(|id| id.trim().to_string())
+            .filter(|id| !id.is_empty());
+        let signing_keys = Self::load(Path::new(&directory), signing_key_id).map_err(|error| {
+            anyhow::anyhow!(
+                "Invalid config for environment variable JWT_KEYS_DIR. {}.",
+                error
+            )
+        })?;
+        info!(
+            "loaded {} jwt keys, signing with {}",
+            signing_keys.keys.len(),
+            signing_keys.signing_key_id
+        );
+        Ok(signing_keys)
+    }
+
+    pub fn signing_key(&self) -> &SigningKey {
+        &self.keys[&self.signing_key_id]
+    }
+
+    pub fn get(&self, id: &str) -> Option<&SigningKey> {
+        self.keys.get(id)
+    }
+
+    // Public keys only, for other services to verify access tokens
+    pub fn jwk_set(&self) -> JwkSet {
+        JwkSet {
+            keys: self.keys.values().map(|key| key.jwk.clone()).collect(),
+        }
+    }
+}
+
+static SIGNING_KEYS: OnceLock<SigningKeys> = OnceLock::new();
+
+// Keys are installed once on start, changing them requires a restart
+// Returns false when keys were already installed, those stay in use
+pub fn init(signing_keys: SigningKeys) -> bool {
+    SIGNING_KEYS.set(signing_keys).is_ok()
+}
+
+pub fn signing_keys() -> &'static SigningKeys {
+    SIGNING_KEYS
+        .get()
+        .expect("signing keys should be installed before serving requests")
+}
@@ -0,0 +1,220 @@
+use super::auth::{token_config, Claims};
+use super::handlers::{error_response, internal_server_error, ErrorResponse};
+use super::rate_limit::{RateLimitPolicy, RouteGroupLimits};
+use crate::logger::Secret;
+use axum::http::StatusCode;
+use axum::Json;
+use image::{ImageFormat, Luma};
+use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
+use qrcode::render::svg;
+use qrcode::QrCode;
+use rand::Rng;
+use sha2::{Digest, Sha256};
+use std::io::Cursor;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use totp_rs::{Algorithm, TOTP};
+use tracing::{debug, error};
+use uuid::Uuid;
+
+// Codes are valid for 30 seconds, one step before and after is accepted to allow for clock drift
+const TOTP_STEP_SECONDS: u64 = 30;
+const TOTP_DIGITS: usize = 6;
+// RFC 4226 recommends a shared secret of 160 bits
+const TOTP_SECRET_BYTES: usize = 20;
+const RECOVERY_CODE_COUNT: usize = 10;
+// Lowercase letters and digits without the easily confused 0, 1, l and o
+const RECOVERY_CODE_ALPHABET: &[u8] = b"abcdefghijkmnpqrstuvwxyz23456789";
+const QR_CODE_SIZE: u32 = 256;
+
+// Code attempts get their own limits on top of the auth limits as there are only a million codes
+pub fn rate_limits() -> RouteGroupLimits {
+    RouteGroupLimits {
+        name: "two_factor",
+        per_ip: RateLimitPolicy {
+            capacity: 10,
+            period: Duration::from_secs(60),
+        },
+        per_user: RateLimitPolicy {
+            capacity: 5,
+            period: Duration::from_secs(300),
+        },
+    }
+}
+
+// Generate a random secret, base32 encoded as expected by authenticator apps
+pub fn generate_secret() -> Secret<String> {
+    let bytes: [u8; TOTP_SECRET_BYTES] = rand::thread_rng().gen();
+    match totp_rs::Secret::Raw(bytes.to_vec()).to_encoded() {
+        totp_rs::Secret::Encoded(secret) => Secret::new(secret),
+        totp_rs::Secret::Raw(_) => unreachable!("secret is always encoded"),
+    }
+}
+
+fn totp(
+    secret: &Secret<String>,
+    account_name: &str,
+) -> Result<TOTP, (StatusCode, Json<ErrorResponse>)> {
+    let issuer = token_config().totp_issuer.clone();
+    let bytes = totp_rs::Secret::Encoded(secret.expose().clone())
+        .to_bytes()
+        .map_err(|error| {
+            error!("invalid totp secret. {:?}", error);
+            internal_server_error()
+        })?;
+    // Skew is handled by verify_code so that the matching time step is known
+    TOTP::new(
+        Algorithm::SHA1,
+        TOTP_DIGITS,
+        0,
+        TOTP_STEP_SECONDS,
+        bytes,
+        Some(issuer),
+        account_name.to_string(),
+    )
+    .map_err(|error| {
+        error!("totp construction error. {}", error);
+        internal_server_error()
+    })
+}
+
+// URI in the 'otpauth://totp/...' format understood by authenticator apps
+pub fn otpauth_uri(
+    secret: &Secret<String>,
+    account_name: &str,
+) -> Result<String, (StatusCode, Json<ErrorResponse>)> {
+    Ok(totp(secret, account_name)?.get_url())
+}
+
+fn qr_code(uri: &str) -> Result<QrCode, (StatusCode, Json<ErrorResponse>)> {
+    QrCode::new(uri.as_bytes()).map_err(|error| {
+        error!("qr code construction error. {}", error);
+        internal_server_error()
+    })
+}
+
+pub fn qr_code_svg(uri: &str) -> Result<String, (StatusCode, Json<ErrorResponse>)> {
+    Ok(qr_code(uri)?
+        .render::<svg::Color>()
+        .min_dimensions(QR_CODE_SIZE, QR_CODE_SIZE)
+        .build())
+}
+
+pub fn qr_code_png(uri: &str) -> Result<Vec<u8>, (StatusCode, Json<ErrorResponse>)> {
+    let image = qr_code(uri)?
+        .render::<Luma<u8>>()
+        .min_dimensions(QR_CODE_SIZE, QR_CODE_SIZE)
+        .build();
+    let mut bytes = Vec::new();
+    image
+        .write_to(&mut Cursor::new(&mut bytes), ImageFormat::Png)
+        .map_err(|error| {
+            error!("qr code png encoding error. {}", error);
+            internal_server_error()
+        })?;
+    Ok(bytes)
+}
+
+// Check the code against the previous, current and next time step and return the matching step
+// Steps up to last_used_step are skipped so that a code cannot be used twice
+pub fn verify_code(
+    secret: &Secret<String>,
+    code: &str,
+    last_used_step: Option<i64>,
+) -> Result<Option<i64>, (StatusCode, Json<ErrorResponse>)> {
+    let totp = totp(secret, "")?;
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_err(|error| {
+            error!("system time error. {}", error);
+            internal_server_error()
+        })?
+        .as_secs();
+    let current_step = (now / TOTP_STEP_SECONDS) as i64;
+
+    let step = (current_step - 1..=current_step + 1)
+        .filter(|step| last_used_step.is_none_or(|last| *step > last))
+        .find(|step| totp.check(code, *step as u64 * TOTP_STEP_SECONDS));
+    if step.is_none() {
+        debug!("invalid totp code");
+    }
+    Ok(step)
+}
+
+// TOTP codes are digits only, anything else is treated as a recovery code
+pub fn is_totp_code(code: &str) -> bool {
+    code.len() == TOTP_DIGITS && code.chars().all(|char| char.is_ascii_digit())
+}
+
+// Recovery codes are shown once in the format 'xxxxx-xxxxx', only their hashes are stored
+pub fn generate_recovery_codes() -> Vec<String> {
+    let mut rng = rand::thread_rng();
+    (0..RECOVERY_CODE_COUNT)
+        .map(|_| {
+            let code: String = (0..10)
+                .map(|_| {
+                    RECOVERY_CODE_ALPHABET[rng.gen_range(0..RECOVERY_CODE_ALPHABET.len())] as char
+                })
+                .collect();
+            format!("{}-{}", &code[..5], &code[5..])
+        })
+        .collect()
+}
+
+// Case, spaces and dashes don't matter when a recovery code is typed in
+pub fn hash_recovery_code(code: &str) -> String {
+    let normalized: String = code
+        .chars()
+        .filter(|char| !char.is_whitespace() && *char != '-')
+        .flat_map(|char| char.to_lowercase())
+        .collect();
+    Sha256::digest(normalized.as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+// Challenge tokens use their own issuer so that they are never accepted as access tokens and vice versa
+fn challenge_token_issuer() -> String {
+    format!("{}:two_factor", token_config().issuer)
+}
+
+fn access_token_secret() -> &'static [u8] {
+    token_config().secret.expose().as_bytes()
+}
+
+// Proves that the password was already checked, it is exchanged for an access token with a valid code
+pub fn encode_challenge_token(user_id: Uuid) -> Result<String, (StatusCode, Json<ErrorResponse>)> {
+    encode(
+        &Header::default(),
+        &Claims::new(
+            user_id,
+            challenge_token_issuer(),
+            time::Duration::minutes(5),
+        ),
+        &EncodingKey::from_secret(access_token_secret()),
+    )
+    .map_err(|error| {
+        error!("jwt challenge token construction error. {}", error);
+        internal_server_error()
+    })
+}
+
+pub fn decode_challenge_token(
+    challenge_token: &str,
+) -> Result<Claims, (StatusCode, Json<ErrorResponse>)> {
+    let mut validation = Validation::new(jsonwebtoken::Algorithm::HS256);
+    validation.set_issuer(&[challenge_token_issuer()]);
+    decode::<Claims>(
+        challenge_token,
+        &DecodingKey::from_secret(access_token_secret()),
+        &validation,
+    )
+    .map(|token_data| token_data.claims)
+    .map_err(|error| {
+        debug!("invalid jwt challenge token. {}", error);
+        error_response(
+            StatusCode::UNAUTHORIZED,
+            "Invalid or expired challenge token.",
+        )
+    })
+}
@@ -0,0 +1,65 @@
+use crate::logger::Secret;
+use serde::{Deserialize, Deserializer};
+use std::borrow::Cow;
+use validator::{HasLen, ValidationError};
+
+// bcrypt silently ignores everything after the first 72 bytes of a password, and it can still be configured
+pub const PASSWORD_MAX_BYTES: usize = 72;
+
+// Password must mix lowercase letters, uppercase letters and digits and fit into a bcrypt hash
+pub fn validate_password_strength(password: &Secret<String>) -> Result<(), ValidationError> {
+    let password = password.expose();
+    if password.len() > PASSWORD_MAX_BYTES {
+        let mut error = ValidationError::new("password_too_long");
+        error.message = Some(Cow::from(format!(
+            "Password must not exceed {} bytes.",
+            PASSWORD_MAX_BYTES
+        )));
+        return Err(error);
+    }
+
+    let has_lowercase = password.chars().any(|c| c.is_lowercase());
+    let has_uppercase = password.chars().any(|c| c.is_uppercase());
+    let has_digit = password.chars().any(|c| c.is_ascii_digit());
+    if !(has_lowercase && has_uppercase && has_digit) {
+        let mut error = ValidationError::new("password_too_weak");
+        error.message = Some(Cow::from(
+            "Password must contain at least one lowercase letter, one uppercase letter and one digit.",
+        ));
+        return Err(error);
+    }
+    Ok(())
+}
+
+// Reject values made of whitespace only, which pass a plain length check
+pub fn validate_not_blank(value: &str) -> Result<(), ValidationError> {
+    if value.trim().is_empty() {
+        let mut error = ValidationError::new("blank");
+        error.message = Some(Cow::from("Value must not be blank."));
+        return Err(error);
+    }
+    Ok(())
+}
+
+// Emails are compared case-insensitively, so they are always stored and looked up in this form
+pub fn normalize_email(email: &str) -> String {
+    email.trim().to_lowercase()
+}
+
+// Normalize the email while deserializing so that validation runs against the normalized value
+pub fn deserialize_email<'de, D>(deserializer: D) -> Result<String, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    String::deserialize(deserializer).map(|email| normalize_email(&email))
+}
+
+// Allows length validation of secret fields without exposing them
+impl<'a, T> HasLen for &'a Secret<T>
+where
+    &'a T: HasLen,
+{
+    fn length(&self) -> u64 {
+        self.expose().length()
+    }
+}
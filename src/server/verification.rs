@@ -0,0 +1,23 @@
+use crate::logger::Secret;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use rand::Rng;
+use sha2::{Digest, Sha256};
+use time::Duration;
+
+// How long the user has to activate the account with the token
+pub const TOKEN_LIFETIME: Duration = Duration::minutes(5);
+
+// Opaque token carrying nothing but 32 random bytes, the user is found through its hash alone
+pub fn generate_token() -> Secret<String> {
+    let bytes: [u8; 32] = rand::thread_rng().gen();
+    URL_SAFE_NO_PAD.encode(bytes).into()
+}
+
+// Tokens are random so a fast hash is enough, a database leak still doesn't reveal usable tokens
+pub fn hash_token(token: &str) -> String {
+    Sha256::digest(token.as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
@@ -0,0 +1,318 @@
+mod common;
+
+use axum::http::{header, Method, StatusCode};
+use chat_rs::external::db::models::User;
+use chat_rs::external::db::repository::{
+    AccountRepository, LoginSecurityRepository, UserRepository, EXPORTED_LOGIN_HISTORY_LIMIT,
+};
+use chat_rs::external::db::user_login::NewUserLoginHistory;
+use chat_rs::server::account;
+use common::{TestApp, PASSWORD};
+use serde_json::{json, Value};
+use std::time::Duration;
+use time::OffsetDateTime;
+
+async fn get_user(app: &TestApp, email: &str) -> User {
+    app.repository
+        .get_user_by_email(email)
+        .await
+        .unwrap()
+        .expect("user should exist")
+}
+
+async fn delete_account(app: &TestApp, cookie: &str, body: Value) -> common::TestResponse {
+    app.request(Method::DELETE, "/api/v1/user/me", Some(body), Some(cookie))
+        .await
+}
+
+// Wait for the background job to finish the export
+async fn wait_for_export(app: &TestApp, cookie: &str, export_id: &str) -> Value {
+    for _ in 0..100 {
+        let export = app
+            .request(
+                Method::GET,
+                &format!("/api/v1/user/me/export/{}", export_id),
+                None,
+                Some(cookie),
+            )
+            .await
+            .success(StatusCode::OK)
+            .clone();
+        if export["status"] != "pending" {
+            return export;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    panic!("data export {} did not finish", export_id);
+}
+
+#[tokio::test]
+async fn delete_account_requires_password() {
+    let app = TestApp::new().await;
+    let cookie = app.login_new_user("confirm@example.com").await;
+
+    let response = delete_account(&app, &cookie, json!({})).await;
+    assert_eq!(
+        response.error(StatusCode::BAD_REQUEST),
+        "Password is required to delete the account."
+    );
+    let response = delete_account(&app, &cookie, json!({ "password": "Wr0ngPassword" })).await;
+    assert_eq!(
+        response.error(StatusCode::UNAUTHORIZED),
+        "Invalid password."
+    );
+
+    let user = get_user(&app, "confirm@example.com").await;
+    assert!(user.deletion_scheduled_at.is_none());
+}
+
+#[tokio::test]
+async fn delete_account_logs_out_and_can_be_cancelled() {
+    let app = TestApp::new().await;
+    let email = "cancel@example.com";
+    let cookie = app.login_new_user(email).await;
+
+    let response = delete_account(&app, &cookie, json!({ "password": PASSWORD })).await;
+    let result = response.success(StatusCode::ACCEPTED);
+    assert_eq!(result["message"], "Account deletion scheduled.");
+    let user = get_user(&app, email).await;
+    let scheduled_at = user.deletion_scheduled_at.unwrap();
+    assert!(scheduled_at > OffsetDateTime::now_utc() + time::Duration::days(29));
+    let sent = app.mailer.sent();
+    assert_eq!(sent.len(), 1);
+    assert_eq!(sent[0].to, email);
+    assert_eq!(sent[0].subject, "Your account will be deleted");
+
+    // The session used for the deletion no longer works
+    app.request(Method::POST, "/api/v1/user/me/restore", None, Some(&cookie))
+        .await
+        .error(StatusCode::UNAUTHORIZED);
+
+    // Revocation has a granularity of seconds, tokens issued in the same second are rejected too
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+    let response = app.login(email, PASSWORD).await;
+    response.success(StatusCode::OK);
+    let cookie = response.cookie.unwrap();
+    let response = app
+        .request(Method::POST, "/api/v1/user/me/restore", None, Some(&cookie))
+        .await;
+    assert_eq!(
+        response.success(StatusCode::OK)["message"],
+        "Account deletion cancelled."
+    );
+    assert!(get_user(&app, email).await.deletion_scheduled_at.is_none());
+
+    let response = app
+        .request(Method::POST, "/api/v1/user/me/restore", None, Some(&cookie))
+        .await;
+    assert_eq!(
+        response.error(StatusCode::BAD_REQUEST),
+        "Account is not scheduled for deletion."
+    );
+}
+
+#[tokio::test]
+async fn purge_anonymizes_user_after_grace_period() {
+    let app = TestApp::new().await;
+    let email = "purge@example.com";
+    let cookie = app.login_new_user(email).await;
+    delete_account(&app, &cookie, json!({ "password": PASSWORD }))
+        .await
+        .success(StatusCode::ACCEPTED);
+    let user = get_user(&app, email).await;
+
+    // Nothing happens while the grace period lasts
+    account::purge(app.repository.as_ref()).await;
+    assert!(get_user(&app, email).await.deleted_at.is_none());
+
+    app.repository
+        .schedule_deletion(&user.id, OffsetDateTime::now_utc())
+        .await
+        .unwrap();
+    account::purge(app.repository.as_ref()).await;
+
+    assert!(app
+        .repository
+        .get_user_by_email(email)
+        .await
+        .unwrap()
+        .is_none());
+    let deleted_user = app
+        .repository
+        .get_user_by_id(&user.id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        deleted_user.email,
+        format!("deleted-{}@deleted.invalid", user.id)
+    );
+    assert_eq!(deleted_user.name.as_deref(), Some("Deleted user"));
+    assert!(deleted_user.password.is_none());
+    assert!(deleted_user.deleted_at.is_some());
+    assert!(deleted_user.deletion_scheduled_at.is_none());
+
+    let archive = app
+        .repository
+        .export_user_data(&user.id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(archive.login_history.is_empty());
+    assert!(archive.identities.is_empty());
+
+    // The email is free again and the old account cannot be logged into
+    app.login(email, PASSWORD)
+        .await
+        .error(StatusCode::UNAUTHORIZED);
+    app.register(email, PASSWORD).await.success(StatusCode::OK);
+}
+
+#[tokio::test]
+async fn export_is_built_in_background_and_downloadable() {
+    let app = TestApp::new().await;
+    let email = "export@example.com";
+    let cookie = app.login_new_user(email).await;
+
+    let response = app
+        .request(Method::POST, "/api/v1/user/me/export", None, Some(&cookie))
+        .await;
+    let export = response.success(StatusCode::ACCEPTED);
+    assert_eq!(export["status"], "pending");
+    let export_id = export["id"].as_str().unwrap().to_string();
+
+    let export = wait_for_export(&app, &cookie, &export_id).await;
+    assert_eq!(export["status"], "completed");
+    assert!(export["expires_at"].is_string());
+
+    let response = app
+        .request(
+            Method::GET,
+            &format!("/api/v1/user/me/export/{}/download", export_id),
+            None,
+            Some(&cookie),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK);
+    let disposition = response.headers[header::CONTENT_DISPOSITION]
+        .to_str()
+        .unwrap();
+    assert!(disposition.starts_with("attachment;"));
+    let archive = response.body;
+    assert_eq!(archive["profile"]["email"], email);
+    assert!(archive["profile"].get("password").is_none());
+    assert_eq!(archive["two_factor_enabled"], false);
+    assert_eq!(archive["login_history"].as_array().unwrap().len(), 1);
+
+    let sent = app.mailer.sent();
+    assert_eq!(sent.len(), 1);
+    assert_eq!(sent[0].subject, "Your data export is ready");
+}
+
+#[tokio::test]
+async fn export_is_private_to_its_user() {
+    let app = TestApp::new().await;
+    let cookie = app.login_new_user("owner@example.com").await;
+    let other_cookie = app.login_new_user("other@example.com").await;
+
+    let response = app
+        .request(Method::POST, "/api/v1/user/me/export", None, Some(&cookie))
+        .await;
+    let export_id = response.success(StatusCode::ACCEPTED)["id"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    wait_for_export(&app, &cookie, &export_id).await;
+
+    for uri in [
+        format!("/api/v1/user/me/export/{}", export_id),
+        format!("/api/v1/user/me/export/{}/download", export_id),
+    ] {
+        let response = app
+            .request(Method::GET, &uri, None, Some(&other_cookie))
+            .await;
+        assert_eq!(
+            response.error(StatusCode::NOT_FOUND),
+            "Data export not found."
+        );
+    }
+    app.request(Method::POST, "/api/v1/user/me/export", None, None)
+        .await
+        .error(StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn pending_export_is_returned_instead_of_starting_another() {
+    let app = TestApp::new().await;
+    let email = "export@example.com";
+    let cookie = app.login_new_user(email).await;
+    let user = get_user(&app, email).await;
+    // Created without the background job, so it stays pending
+    let pending = app.repository.create_data_export(&user.id).await.unwrap();
+
+    let response = app
+        .request(Method::POST, "/api/v1/user/me/export", None, Some(&cookie))
+        .await;
+
+    let export = response.success(StatusCode::ACCEPTED);
+    assert_eq!(export["id"], pending.id.to_string());
+    assert_eq!(export["status"], "pending");
+}
+
+#[tokio::test]
+async fn export_keeps_latest_logins_only() {
+    let app = TestApp::new().await;
+    let email = "export@example.com";
+    app.login_new_user(email).await;
+    let user = get_user(&app, email).await;
+    for _ in 0..EXPORTED_LOGIN_HISTORY_LIMIT {
+        app.repository
+            .insert_login_history(NewUserLoginHistory {
+                user_id: user.id,
+                success: true,
+                failure_reason: None,
+                ip: "unknown".to_string(),
+                user_agent: None,
+                is_new_device: false,
+            })
+            .await
+            .unwrap();
+    }
+
+    let archive = app
+        .repository
+        .export_user_data(&user.id)
+        .await
+        .unwrap()
+        .unwrap();
+
+    // The first login from a new device is the oldest one and left out
+    assert_eq!(
+        archive.login_history.len(),
+        EXPORTED_LOGIN_HISTORY_LIMIT as usize
+    );
+    assert!(archive
+        .login_history
+        .iter()
+        .all(|login| !login.is_new_device));
+}
+
+#[tokio::test]
+async fn export_id_must_be_uuid() {
+    let app = TestApp::new().await;
+    let cookie = app.login_new_user("owner@example.com").await;
+
+    let response = app
+        .request(
+            Method::GET,
+            "/api/v1/user/me/export/not-a-uuid",
+            None,
+            Some(&cookie),
+        )
+        .await;
+
+    assert_eq!(
+        response.error(StatusCode::BAD_REQUEST),
+        "Invalid path parameter: UUID parsing failed: invalid character: found `n` at 0."
+    );
+}
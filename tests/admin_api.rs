@@ -0,0 +1,300 @@
+mod common;
+
+use axum::http::{Method, StatusCode};
+use chat_rs::external::db::admin_audit_log::NewAdminAuditLog;
+use chat_rs::external::db::models::UserRole;
+use chat_rs::external::db::repository::{AdminRepository, UserRepository};
+use common::{test_config, TestApp, PASSWORD};
+use serde_json::{json, Value};
+use std::time::Duration;
+
+const ADMIN: &str = "admin@example.com";
+
+// Login as a new admin and return the access token cookie
+async fn login_admin(app: &TestApp) -> String {
+    app.login_new_user(ADMIN).await;
+    app.set_role(ADMIN, UserRole::Admin).await;
+    // The role is read on every request, the cookie from before the promotion works as well
+    let response = app.login(ADMIN, PASSWORD).await;
+    response.success(StatusCode::OK);
+    response.cookie.unwrap()
+}
+
+async fn audit_logs(app: &TestApp, cookie: &str, email: &str) -> Vec<Value> {
+    let user_id = app.user_id(email).await;
+    app.request(
+        Method::GET,
+        &format!("/api/v1/admin/audit-logs?user_id={}", user_id),
+        None,
+        Some(cookie),
+    )
+    .await
+    .success(StatusCode::OK)
+    .as_array()
+    .unwrap()
+    .clone()
+}
+
+#[tokio::test]
+async fn admin_changes_role_of_user() {
+    let app = TestApp::new().await;
+    let cookie = login_admin(&app).await;
+    let moderator_cookie = app.login_new_user("moderator@example.com").await;
+    let user_id = app.user_id("moderator@example.com").await;
+
+    app.request(
+        Method::GET,
+        "/api/v1/moderation/reports",
+        None,
+        Some(&moderator_cookie),
+    )
+    .await
+    .error(StatusCode::FORBIDDEN);
+    let response = app
+        .request(
+            Method::POST,
+            &format!("/api/v1/admin/users/{}/role", user_id),
+            Some(json!({ "role": "moderator" })),
+            Some(&cookie),
+        )
+        .await;
+    assert_eq!(
+        response.success(StatusCode::OK)["message"],
+        "User role updated."
+    );
+
+    let user = app
+        .request(
+            Method::GET,
+            &format!("/api/v1/admin/users/{}", user_id),
+            None,
+            Some(&cookie),
+        )
+        .await
+        .success(StatusCode::OK)
+        .clone();
+    assert_eq!(user["role"], "moderator");
+    app.request(
+        Method::GET,
+        "/api/v1/moderation/reports",
+        None,
+        Some(&moderator_cookie),
+    )
+    .await
+    .success(StatusCode::OK);
+
+    let logs = audit_logs(&app, &cookie, "moderator@example.com").await;
+    assert_eq!(logs.len(), 1);
+    assert_eq!(logs[0]["action"], "update_role");
+    assert_eq!(logs[0]["actor_id"], app.user_id(ADMIN).await.to_string());
+    assert_eq!(
+        logs[0]["details"],
+        json!({ "previous_role": "user", "role": "moderator" })
+    );
+}
+
+#[tokio::test]
+async fn admin_cannot_change_own_role() {
+    let app = TestApp::new().await;
+    let cookie = login_admin(&app).await;
+    let admin_id = app.user_id(ADMIN).await;
+
+    let response = app
+        .request(
+            Method::POST,
+            &format!("/api/v1/admin/users/{}/role", admin_id),
+            Some(json!({ "role": "user" })),
+            Some(&cookie),
+        )
+        .await;
+    assert_eq!(
+        response.error(StatusCode::BAD_REQUEST),
+        "Admin cannot change their own role."
+    );
+    let admin = app.repository.get_user_by_id(&admin_id).await.unwrap();
+    assert_eq!(admin.unwrap().role, UserRole::Admin);
+}
+
+#[tokio::test]
+async fn last_admin_cannot_be_demoted() {
+    let app = TestApp::new().await;
+    login_admin(&app).await;
+    let admin_id = app.user_id(ADMIN).await;
+    let audit_log = || NewAdminAuditLog {
+        actor_id: admin_id,
+        action: "update_role".to_string(),
+        target_user_id: Some(admin_id),
+        details: json!({}),
+    };
+
+    // Only reachable through concurrent demotions, the endpoint refuses changes of the own role
+    let is_updated = app
+        .repository
+        .update_role(&admin_id, UserRole::Moderator, audit_log())
+        .await
+        .unwrap();
+    assert!(!is_updated);
+
+    app.login_new_user("second@example.com").await;
+    app.set_role("second@example.com", UserRole::Admin).await;
+    let is_updated = app
+        .repository
+        .update_role(&admin_id, UserRole::Moderator, audit_log())
+        .await
+        .unwrap();
+    assert!(is_updated);
+}
+
+#[tokio::test]
+async fn suspension_revokes_tokens_and_is_audited() {
+    let app = TestApp::new().await;
+    let cookie = login_admin(&app).await;
+    let email = "suspended@example.com";
+    let user_cookie = app.login_new_user(email).await;
+    let user_id = app.user_id(email).await;
+
+    let response = app
+        .request(
+            Method::POST,
+            &format!("/api/v1/admin/users/{}/suspend", user_id),
+            Some(json!({ "reason": "Spamming" })),
+            Some(&cookie),
+        )
+        .await;
+    assert_eq!(
+        response.success(StatusCode::OK)["message"],
+        "User suspended."
+    );
+
+    let user = app.repository.get_user_by_id(&user_id).await.unwrap();
+    assert!(user.unwrap().tokens_revoked_at.is_some());
+    let response = app
+        .request(Method::GET, "/api/v1/user/2fa", None, Some(&user_cookie))
+        .await;
+    assert_eq!(
+        response.error(StatusCode::UNAUTHORIZED),
+        "Invalid access token."
+    );
+    // Revocation has a granularity of seconds, a login in the same second would be rejected anyway
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+    assert_eq!(
+        app.login(email, PASSWORD)
+            .await
+            .error(StatusCode::FORBIDDEN),
+        "User is suspended."
+    );
+
+    let logs = audit_logs(&app, &cookie, email).await;
+    assert_eq!(logs.len(), 1);
+    assert_eq!(logs[0]["action"], "suspend_user");
+    assert_eq!(logs[0]["details"]["reason"], "Spamming");
+}
+
+#[tokio::test]
+async fn admin_cannot_restrict_themselves() {
+    let app = TestApp::new().await;
+    let cookie = login_admin(&app).await;
+    let admin_id = app.user_id(ADMIN).await;
+
+    for action in ["suspend", "ban"] {
+        let response = app
+            .request(
+                Method::POST,
+                &format!("/api/v1/admin/users/{}/{}", admin_id, action),
+                Some(json!({ "reason": "Testing" })),
+                Some(&cookie),
+            )
+            .await;
+        assert_eq!(
+            response.error(StatusCode::BAD_REQUEST),
+            "Admin cannot restrict themselves."
+        );
+    }
+}
+
+#[tokio::test]
+async fn force_logout_and_verify_are_audited() {
+    let app = TestApp::new().await;
+    let cookie = login_admin(&app).await;
+    let email = "unverified@example.com";
+    app.register(email, PASSWORD).await.success(StatusCode::OK);
+    let user_id = app.user_id(email).await;
+
+    for (action, message) in [("verify", "User verified."), ("logout", "User logged out.")] {
+        let response = app
+            .request(
+                Method::POST,
+                &format!("/api/v1/admin/users/{}/{}", user_id, action),
+                None,
+                Some(&cookie),
+            )
+            .await;
+        assert_eq!(response.success(StatusCode::OK)["message"], message);
+    }
+
+    let user = app
+        .repository
+        .get_user_by_id(&user_id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(user.verified);
+    assert!(user.tokens_revoked_at.is_some());
+    let logs = audit_logs(&app, &cookie, email).await;
+    let actions: Vec<&str> = logs
+        .iter()
+        .map(|log| log["action"].as_str().unwrap())
+        .collect();
+    // Newest first
+    assert_eq!(actions, ["force_logout", "verify_user"]);
+    assert_eq!(logs[1]["details"], json!({ "previously_verified": false }));
+}
+
+#[tokio::test]
+async fn list_users_is_paginated_within_bounds() {
+    // More registrations than the auth group allows from one address
+    let mut config = test_config();
+    config.rate_limits.auth.per_ip.capacity = 100;
+    let app = TestApp::with_config(config).await;
+    let cookie = login_admin(&app).await;
+    for index in 0..51 {
+        app.register(&format!("page{}@example.com", index), PASSWORD)
+            .await
+            .success(StatusCode::OK);
+    }
+    let list = |query: &'static str| {
+        let app = &app;
+        let cookie = &cookie;
+        async move {
+            app.request(
+                Method::GET,
+                &format!("/api/v1/admin/users{}", query),
+                None,
+                Some(cookie),
+            )
+            .await
+        }
+    };
+
+    // 50 users per page unless asked otherwise
+    let users = list("?email=page").await;
+    assert_eq!(users.success(StatusCode::OK).as_array().unwrap().len(), 50);
+    let users = list("?email=page&limit=20&offset=40").await;
+    assert_eq!(users.success(StatusCode::OK).as_array().unwrap().len(), 11);
+    let users = list("?email=page&offset=100").await;
+    assert!(users.success(StatusCode::OK).as_array().unwrap().is_empty());
+
+    for (query, field, message) in [
+        ("?limit=0", "limit", "Limit must be between 1 and 200."),
+        ("?limit=201", "limit", "Limit must be between 1 and 200."),
+        ("?offset=-1", "offset", "Offset must not be negative."),
+    ] {
+        let response = list(query).await;
+        assert_eq!(
+            response.error(StatusCode::UNPROCESSABLE_ENTITY),
+            "Request validation failed."
+        );
+        assert_eq!(response.body["fields"][0]["field"], field);
+        assert_eq!(response.body["fields"][0]["message"], message);
+    }
+}
@@ -0,0 +1,31 @@
+mod common;
+
+use axum::http::{Method, StatusCode};
+use common::TestApp;
+
+#[tokio::test]
+async fn liveness_does_not_depend_on_database() {
+    let app = TestApp::new().await;
+
+    let response = app.request(Method::GET, "/healthz", None, None).await;
+
+    let result = response.success(StatusCode::OK);
+    assert_eq!(result["status"], "ok");
+    assert_eq!(result["version"], env!("CARGO_PKG_VERSION"));
+}
+
+#[tokio::test]
+async fn readiness_reports_unreachable_database() {
+    // The test pool points at a closed port
+    let app = TestApp::new().await;
+
+    let response = app.request(Method::GET, "/readyz", None, None).await;
+
+    assert_eq!(response.status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(response.body["success"], false);
+    let result = &response.body["result"];
+    assert_eq!(result["status"], "degraded");
+    assert_eq!(result["dependencies"][0]["name"], "database");
+    assert_eq!(result["dependencies"][0]["status"], "down");
+    assert_eq!(result["dependencies"][0]["error"], "unavailable");
+}
@@ -0,0 +1,166 @@
+mod common;
+
+use axum::body::Body;
+use axum::http::{header, Method, Request, StatusCode};
+use chat_rs::server::auth::Claims;
+use common::{TestApp, JWT_KEYS_DIR, PREVIOUS_KEY_ID, SIGNING_KEY_ID};
+use jsonwebtoken::jwk::JwkSet;
+use jsonwebtoken::{
+    decode, decode_header, encode, Algorithm, DecodingKey, EncodingKey, Header, Validation,
+};
+use tower::ServiceExt;
+
+async fn jwk_set(app: &TestApp) -> JwkSet {
+    let request = Request::builder()
+        .uri("/.well-known/jwks.json")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.router.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.headers()[header::CACHE_CONTROL],
+        "public, max-age=300"
+    );
+    let bytes = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    serde_json::from_slice(&bytes).unwrap()
+}
+
+// Verify the access token of the cookie the way another service would, with the published keys only
+async fn verify_with_jwks(app: &TestApp, cookie: &str) -> (Header, Claims) {
+    let access_token = cookie.split_once('=').unwrap().1;
+    let header = decode_header(access_token).unwrap();
+    let jwk_set = jwk_set(app).await;
+    let jwk = jwk_set.find(header.kid.as_deref().unwrap()).unwrap();
+    let mut validation = Validation::new(header.alg);
+    validation.set_issuer(&["test"]);
+    let claims = decode::<Claims>(
+        access_token,
+        &DecodingKey::from_jwk(jwk).unwrap(),
+        &validation,
+    )
+    .unwrap()
+    .claims;
+    (header, claims)
+}
+
+fn private_key(kid: &str) -> Vec<u8> {
+    std::fs::read(format!("{}/{}.pem", JWT_KEYS_DIR, kid)).unwrap()
+}
+
+fn sign(header: Header, claims: &Claims, key: &EncodingKey) -> String {
+    encode(&header, claims, key).unwrap()
+}
+
+fn header(algorithm: Algorithm, kid: Option<&str>) -> Header {
+    let mut header = Header::new(algorithm);
+    header.kid = kid.map(|kid| kid.to_string());
+    header
+}
+
+async fn two_factor_status(app: &TestApp, access_token: &str) -> common::TestResponse {
+    app.request(
+        Method::GET,
+        "/api/v1/user/2fa",
+        None,
+        Some(&format!("token={}", access_token)),
+    )
+    .await
+}
+
+#[tokio::test]
+async fn jwks_publishes_public_keys_only() {
+    let app = TestApp::new().await;
+    let jwk_set = jwk_set(&app).await;
+
+    let kids: Vec<_> = jwk_set
+        .keys
+        .iter()
+        .map(|jwk| jwk.common.key_id.clone().unwrap())
+        .collect();
+    assert_eq!(kids, [PREVIOUS_KEY_ID, SIGNING_KEY_ID]);
+    let algorithms: Vec<_> = jwk_set
+        .keys
+        .iter()
+        .map(|jwk| jwk.common.algorithm.unwrap())
+        .collect();
+    assert_eq!(algorithms, [Algorithm::RS256, Algorithm::EdDSA]);
+    // Private parts like 'd' must never show up
+    let value = serde_json::to_value(&jwk_set).unwrap();
+    for key in value["keys"].as_array().unwrap() {
+        assert!(key.get("d").is_none());
+        assert_eq!(key["use"], "sig");
+    }
+}
+
+#[tokio::test]
+async fn access_token_verifies_with_published_key() {
+    let app = TestApp::new().await;
+    let cookie = app.login_new_user("jwks@example.com").await;
+
+    let (header, _) = verify_with_jwks(&app, &cookie).await;
+    assert_eq!(header.alg, Algorithm::EdDSA);
+    assert_eq!(header.kid.as_deref(), Some(SIGNING_KEY_ID));
+}
+
+#[tokio::test]
+async fn token_of_previous_key_is_accepted_during_rotation() {
+    let app = TestApp::new().await;
+    let cookie = app.login_new_user("rotation@example.com").await;
+    let (_, claims) = verify_with_jwks(&app, &cookie).await;
+
+    let access_token = sign(
+        header(Algorithm::RS256, Some(PREVIOUS_KEY_ID)),
+        &claims,
+        &EncodingKey::from_rsa_pem(&private_key(PREVIOUS_KEY_ID)).unwrap(),
+    );
+    two_factor_status(&app, &access_token)
+        .await
+        .success(StatusCode::OK);
+}
+
+#[tokio::test]
+async fn token_without_matching_key_is_rejected() {
+    let app = TestApp::new().await;
+    let cookie = app.login_new_user("forged@example.com").await;
+    let (_, claims) = verify_with_jwks(&app, &cookie).await;
+    let rsa_key = EncodingKey::from_rsa_pem(&private_key(PREVIOUS_KEY_ID)).unwrap();
+
+    let access_tokens = [
+        // Tokens signed with the former shared secret
+        sign(
+            header(Algorithm::HS256, None),
+            &claims,
+            &EncodingKey::from_secret(b"test"),
+        ),
+        sign(
+            header(Algorithm::HS256, Some(SIGNING_KEY_ID)),
+            &claims,
+            &EncodingKey::from_secret(b"test"),
+        ),
+        // Key ids that are not or no longer published
+        sign(header(Algorithm::RS256, None), &claims, &rsa_key),
+        sign(
+            header(Algorithm::RS256, Some("2023-07-rsa")),
+            &claims,
+            &rsa_key,
+        ),
+        // The algorithm has to be the one of the key
+        sign(
+            header(Algorithm::RS512, Some(PREVIOUS_KEY_ID)),
+            &claims,
+            &rsa_key,
+        ),
+        sign(
+            header(Algorithm::RS256, Some(SIGNING_KEY_ID)),
+            &claims,
+            &rsa_key,
+        ),
+    ];
+    for access_token in access_tokens {
+        let response = two_factor_status(&app, &access_token).await;
+        assert_eq!(
+            response.error(StatusCode::UNAUTHORIZED),
+            "Invalid access token."
+        );
+    }
+}
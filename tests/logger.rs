@@ -0,0 +1,307 @@
+mod common;
+
+use axum::http::StatusCode;
+use chat_rs::external::db::repository::UserRepository;
+use chat_rs::logger::{CustomLayer, LogFormat, Redaction, SizeRollingWriter, REDACTED};
+use common::{TestApp, PASSWORD};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::prelude::__tracing_subscriber_SubscriberExt;
+
+// Collects everything the layer writes so that the lines can be inspected
+#[derive(Clone, Default)]
+struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+impl Buffer {
+    fn lines(&self) -> Vec<String> {
+        String::from_utf8(self.0.lock().unwrap().clone())
+            .unwrap()
+            .lines()
+            .map(|line| line.to_string())
+            .collect()
+    }
+}
+
+impl Write for Buffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<'a> MakeWriter<'a> for Buffer {
+    type Writer = Buffer;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        self.clone()
+    }
+}
+
+fn log_with_format(format: LogFormat) -> Vec<String> {
+    let buffer = Buffer::default();
+    let subscriber = tracing_subscriber::registry().with(CustomLayer::new(
+        format,
+        buffer.clone(),
+        Redaction::default(),
+    ));
+    tracing::subscriber::with_default(subscriber, || {
+        let span = tracing::info_span!("login", email = "user@example.com");
+        let _guard = span.enter();
+        tracing::info!("received request");
+    });
+    buffer.lines()
+}
+
+fn redacted_lines(redaction: Redaction, log: impl FnOnce()) -> Vec<String> {
+    let buffer = Buffer::default();
+    let subscriber = tracing_subscriber::registry().with(CustomLayer::new(
+        LogFormat::Json,
+        buffer.clone(),
+        redaction,
+    ));
+    tracing::subscriber::with_default(subscriber, log);
+    buffer.lines()
+}
+
+fn temp_dir() -> PathBuf {
+    std::env::temp_dir().join(format!("chat-rs-logs-{}", uuid::Uuid::new_v4()))
+}
+
+#[test]
+fn json_format_writes_one_object_per_line() {
+    let lines = log_with_format(LogFormat::Json);
+
+    // The event followed by the span closing
+    assert_eq!(lines.len(), 2);
+    let line: serde_json::Value = serde_json::from_str(&lines[0]).unwrap();
+    assert_eq!(line["level"], "info");
+    assert_eq!(line["function"], "login");
+    assert_eq!(line["message"], "received request");
+    assert_eq!(line["params"]["email"], "user@example.com");
+}
+
+#[test]
+fn compact_format_is_human_readable() {
+    let lines = log_with_format(LogFormat::Compact);
+
+    assert_eq!(lines.len(), 2);
+    assert!(lines[0].contains(" INFO logger login: received request"));
+    assert!(lines[0].ends_with(r#"params={"email":"user@example.com"}"#));
+}
+
+#[test]
+fn closed_spans_log_elapsed_time_and_span_chain() {
+    let lines = redacted_lines(Redaction::default(), || {
+        let outer = tracing::info_span!(
+            "request",
+            uri = "/api/v1/user/login",
+            status = tracing::field::Empty
+        );
+        let _outer = outer.enter();
+        {
+            let inner = tracing::info_span!("login_handler");
+            let _inner = inner.enter();
+            std::thread::sleep(std::time::Duration::from_millis(20));
+            tracing::info!("received request");
+        }
+        outer.record("status", 200);
+    });
+
+    let lines: Vec<serde_json::Value> = lines
+        .iter()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(lines.len(), 3);
+    assert_eq!(lines[0]["message"], "received request");
+    assert_eq!(
+        lines[0]["spans"],
+        serde_json::json!(["request", "login_handler"])
+    );
+    assert_eq!(lines[0]["path"], "/api/v1/user/login");
+
+    assert_eq!(lines[1]["message"], "span closed");
+    assert_eq!(lines[1]["function"], "login_handler");
+    let elapsed = lines[1]["elapsed_ms"].as_f64().unwrap();
+    let busy = lines[1]["busy_ms"].as_f64().unwrap();
+    assert!(busy >= 20.0, "busy {}ms", busy);
+    assert!(elapsed >= busy, "elapsed {}ms busy {}ms", elapsed, busy);
+
+    // Fields recorded after creation, like the status, are logged when the span closes
+    assert_eq!(lines[2]["function"], "request");
+    assert_eq!(lines[2]["spans"], serde_json::json!(["request"]));
+    assert_eq!(lines[2]["status"], 200);
+    assert!(lines[2]["elapsed_ms"].as_f64().unwrap() >= elapsed);
+}
+
+#[test]
+fn errors_recorded_on_spans_are_logged() {
+    let lines = redacted_lines(Redaction::default(), || {
+        let span = tracing::info_span!("insert_new_user", error = tracing::field::Empty);
+        let error = std::io::Error::other("connection reset");
+        span.record("error", &error as &(dyn std::error::Error + 'static));
+    });
+
+    let line: serde_json::Value = serde_json::from_str(&lines[0]).unwrap();
+    assert_eq!(line["message"], "span closed");
+    assert_eq!(line["error"], "connection reset");
+}
+
+#[tokio::test]
+async fn request_span_logs_status_and_latency() {
+    let app = TestApp::new().await;
+    let buffer = Buffer::default();
+    let subscriber = tracing_subscriber::registry().with(CustomLayer::new(
+        LogFormat::Json,
+        buffer.clone(),
+        Redaction::default(),
+    ));
+    let _guard = tracing::subscriber::set_default(subscriber);
+
+    app.login("nobody@example.com", PASSWORD)
+        .await
+        .error(StatusCode::UNAUTHORIZED);
+
+    let lines: Vec<serde_json::Value> = buffer
+        .lines()
+        .iter()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    let handler = lines
+        .iter()
+        .find(|line| line["message"] == "span closed" && line["function"] == "login_handler")
+        .expect("handler span should be closed");
+    assert_eq!(
+        handler["spans"],
+        serde_json::json!(["request", "login_handler"])
+    );
+    let request = lines
+        .iter()
+        .find(|line| line["message"] == "span closed" && line["function"] == "request")
+        .expect("request span should be closed");
+    assert_eq!(request["status"], 401);
+    assert_eq!(request["path"], "/api/v1/user/login");
+    assert!(request["elapsed_ms"].as_f64().is_some());
+}
+
+#[test]
+fn size_rolling_writer_rotates_and_keeps_max_files() {
+    let directory = temp_dir();
+    let mut writer = SizeRollingWriter::new(&directory, "app.log", 10, 2).unwrap();
+
+    for line in ["first\n", "second\n", "third\n", "fourth\n"] {
+        writer.write_all(line.as_bytes()).unwrap();
+    }
+    writer.flush().unwrap();
+
+    let read = |name: &str| std::fs::read_to_string(directory.join(name)).ok();
+    assert_eq!(read("app.log").as_deref(), Some("fourth\n"));
+    assert_eq!(read("app.log.1").as_deref(), Some("third\n"));
+    assert_eq!(read("app.log.2").as_deref(), Some("second\n"));
+    assert_eq!(read("app.log.3"), None);
+    std::fs::remove_dir_all(directory).unwrap();
+}
+
+#[test]
+fn size_rolling_writer_continues_existing_file() {
+    let directory = temp_dir();
+    std::fs::create_dir_all(&directory).unwrap();
+    std::fs::write(directory.join("app.log"), "before restart\n").unwrap();
+
+    let mut writer = SizeRollingWriter::new(&directory, "app.log", 20, 1).unwrap();
+    writer.write_all(b"after\n").unwrap();
+    writer.flush().unwrap();
+
+    let read = |name: &str| std::fs::read_to_string(directory.join(name)).ok();
+    assert_eq!(read("app.log").as_deref(), Some("after\n"));
+    assert_eq!(read("app.log.1").as_deref(), Some("before restart\n"));
+    std::fs::remove_dir_all(directory).unwrap();
+}
+
+#[test]
+fn redacts_deny_listed_fields_and_values_in_text() {
+    let lines = redacted_lines(Redaction::new(["api_key"]), || {
+        let span = tracing::info_span!("call", uri = "/activate?token=abc&next=home");
+        let _guard = span.enter();
+        tracing::info!(
+            api_key = "key",
+            access_token = "jwt",
+            body = "Schema { email: \"user@example.com\", password: \"hunter2\" }",
+            callback = "/api/v1/user/oidc/google/callback?code=abc&state=xyz",
+            status_code = 401,
+            response = "Response { status_code: \"401\" }",
+            "received request"
+        );
+    });
+
+    let line: serde_json::Value = serde_json::from_str(&lines[0]).unwrap();
+    assert_eq!(line["api_key"], REDACTED);
+    assert_eq!(line["access_token"], REDACTED);
+    assert_eq!(
+        line["body"],
+        "Schema { email: \"user@example.com\", password: \"***\" }"
+    );
+    assert_eq!(line["path"], "/activate?token=***&next=home");
+    assert_eq!(
+        line["callback"],
+        "/api/v1/user/oidc/google/callback?code=***&state=xyz"
+    );
+    // 'code' is only redacted as a whole name
+    assert_eq!(line["status_code"], 401);
+    assert_eq!(line["response"], "Response { status_code: \"401\" }");
+}
+
+// Runs the whole registration flow with every log level enabled and fails if any secret reaches the output
+#[tokio::test]
+async fn secrets_never_reach_log_output() {
+    let app = TestApp::new().await;
+    let buffer = Buffer::default();
+    let subscriber = tracing_subscriber::registry().with(CustomLayer::new(
+        LogFormat::Json,
+        buffer.clone(),
+        Redaction::default(),
+    ));
+    let _guard = tracing::subscriber::set_default(subscriber);
+
+    let email = "audit@example.com";
+    let response = app.register(email, PASSWORD).await;
+    let verification_token = response.success(StatusCode::OK)["verification_token"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    app.activate(&verification_token)
+        .await
+        .success(StatusCode::OK);
+    let response = app.login(email, PASSWORD).await;
+    response.success(StatusCode::OK);
+    app.login(email, "WrongPassw0rd")
+        .await
+        .error(StatusCode::UNAUTHORIZED);
+
+    let user = app
+        .repository
+        .get_user_by_email(email)
+        .await
+        .unwrap()
+        .unwrap();
+    let access_token = response.cookie.unwrap();
+    let access_token = access_token.split_once('=').unwrap().1.to_string();
+    let secrets = [
+        ("password", PASSWORD.to_string()),
+        ("wrong password", "WrongPassw0rd".to_string()),
+        ("password hash", user.password.unwrap().expose().clone()),
+        ("verification token", verification_token),
+        ("access token", access_token),
+    ];
+
+    let output = buffer.lines().join("\n");
+    assert!(output.contains("received request"));
+    for (name, secret) in secrets {
+        assert!(!output.contains(&secret), "{} found in log output", name);
+    }
+}
@@ -0,0 +1,200 @@
+mod common;
+
+use axum::http::{header, Method, StatusCode};
+use chat_rs::external::db::repository::{LoginSecurityRepository, UserRepository};
+use common::{TestApp, LOCKOUT_SECONDS, LOCKOUT_THRESHOLD, PASSWORD};
+use serde_json::Value;
+use std::time::Duration;
+use time::OffsetDateTime;
+
+const WRONG_PASSWORD: &str = "Wr0ngPassword";
+
+// Register and activate a user, then return the access token cookie from registration
+async fn register_user(app: &TestApp, email: &str) -> String {
+    let response = app.register(email, PASSWORD).await;
+    let token = response.success(StatusCode::OK)["verification_token"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    app.activate(&token).await.success(StatusCode::OK);
+    response
+        .cookie
+        .expect("register should set the access token cookie")
+}
+
+async fn fail_logins(app: &TestApp, email: &str, count: usize) {
+    for _ in 0..count {
+        app.login(email, WRONG_PASSWORD)
+            .await
+            .error(StatusCode::UNAUTHORIZED);
+    }
+}
+
+async fn login_history(app: &TestApp, cookie: &str, query: &str) -> Vec<Value> {
+    app.request(
+        Method::GET,
+        &format!("/api/v1/user/login-history{}", query),
+        None,
+        Some(cookie),
+    )
+    .await
+    .success(StatusCode::OK)
+    .as_array()
+    .unwrap()
+    .clone()
+}
+
+// Time left until the lock of the user expires, zero when not locked
+async fn lock_remaining(app: &TestApp, email: &str) -> Duration {
+    let user = app
+        .repository
+        .get_user_by_email(email)
+        .await
+        .unwrap()
+        .unwrap();
+    app.repository
+        .get_locked_until(&user.id)
+        .await
+        .unwrap()
+        .map_or(Duration::ZERO, |locked_until| {
+            (locked_until - OffsetDateTime::now_utc()).unsigned_abs()
+        })
+}
+
+#[tokio::test]
+async fn account_is_locked_after_repeated_failures() {
+    let app = TestApp::new().await;
+    let email = "locked@example.com";
+    let cookie = register_user(&app, email).await;
+    fail_logins(&app, email, LOCKOUT_THRESHOLD).await;
+
+    // Even the right password is rejected while the lock lasts
+    let response = app.login(email, PASSWORD).await;
+    assert_eq!(
+        response.error(StatusCode::UNAUTHORIZED),
+        "Invalid email or password."
+    );
+    assert!(response.cookie.is_none());
+    assert!(lock_remaining(&app, email).await <= Duration::from_secs(LOCKOUT_SECONDS));
+
+    let history = login_history(&app, &cookie, "").await;
+    assert_eq!(history.len(), LOCKOUT_THRESHOLD + 1);
+    assert_eq!(history[0]["success"], false);
+    assert_eq!(history[0]["failure_reason"], "locked");
+    assert!(history[1..]
+        .iter()
+        .all(|login| login["failure_reason"] == "invalid_password"));
+}
+
+#[tokio::test]
+async fn lock_expires_and_success_resets_failures() {
+    let app = TestApp::new().await;
+    let email = "unlocked@example.com";
+    register_user(&app, email).await;
+    fail_logins(&app, email, LOCKOUT_THRESHOLD).await;
+
+    tokio::time::sleep(Duration::from_millis(LOCKOUT_SECONDS * 1000 + 100)).await;
+    app.login(email, PASSWORD).await.success(StatusCode::OK);
+
+    // Failures before the successful login no longer count towards the next lock
+    fail_logins(&app, email, LOCKOUT_THRESHOLD - 1).await;
+    app.login(email, PASSWORD).await.success(StatusCode::OK);
+}
+
+#[tokio::test]
+async fn lock_duration_doubles_with_every_further_failure() {
+    let app = TestApp::new().await;
+    let email = "backoff@example.com";
+    register_user(&app, email).await;
+    fail_logins(&app, email, LOCKOUT_THRESHOLD).await;
+
+    tokio::time::sleep(Duration::from_millis(LOCKOUT_SECONDS * 1000 + 100)).await;
+    fail_logins(&app, email, 1).await;
+
+    app.login(email, PASSWORD)
+        .await
+        .error(StatusCode::UNAUTHORIZED);
+    let remaining = lock_remaining(&app, email).await;
+    assert!(remaining > Duration::from_secs(LOCKOUT_SECONDS));
+    assert!(remaining <= Duration::from_secs(LOCKOUT_SECONDS * 2));
+}
+
+#[tokio::test]
+async fn unknown_email_is_not_locked() {
+    let app = TestApp::new().await;
+    fail_logins(&app, "nobody@example.com", LOCKOUT_THRESHOLD + 1).await;
+}
+
+#[tokio::test]
+async fn locked_account_looks_like_unknown_email() {
+    let app = TestApp::new().await;
+    let email = "hidden@example.com";
+    register_user(&app, email).await;
+    fail_logins(&app, email, LOCKOUT_THRESHOLD).await;
+
+    let locked = app.login(email, PASSWORD).await;
+    let unknown = app.login("nobody@example.com", PASSWORD).await;
+    assert_eq!(locked.status, unknown.status);
+    assert_eq!(
+        locked.error(StatusCode::UNAUTHORIZED),
+        unknown.error(StatusCode::UNAUTHORIZED)
+    );
+    assert!(locked.headers.get(header::RETRY_AFTER).is_none());
+}
+
+#[tokio::test]
+async fn login_from_new_device_sends_mail() {
+    let app = TestApp::new().await;
+    let email = "device@example.com";
+    let cookie = app.login_new_user(email).await;
+
+    // Neither the very first login nor one from a known device is reported
+    app.login(email, PASSWORD).await.success(StatusCode::OK);
+    assert!(app.mailer.sent().is_empty());
+
+    app.login_with_user_agent(email, PASSWORD, "Firefox/118.0")
+        .await
+        .success(StatusCode::OK);
+    let sent = app.mailer.sent();
+    assert_eq!(sent.len(), 1);
+    assert_eq!(sent[0].to, email);
+    assert!(sent[0].body.contains("Firefox/118.0"));
+
+    // Once seen, the device is known
+    app.login_with_user_agent(email, PASSWORD, "Firefox/118.0")
+        .await
+        .success(StatusCode::OK);
+    assert_eq!(app.mailer.sent().len(), 1);
+
+    let history = login_history(&app, &cookie, "").await;
+    let is_new_device = history
+        .iter()
+        .map(|login| login["is_new_device"].as_bool().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(is_new_device, [false, true, false, false]);
+    assert_eq!(history[1]["user_agent"], "Firefox/118.0");
+    assert_eq!(history[1]["ip"], "unknown");
+}
+
+#[tokio::test]
+async fn login_history_is_paginated_and_private() {
+    let app = TestApp::new().await;
+    let cookie = app.login_new_user("history@example.com").await;
+    fail_logins(&app, "history@example.com", 1).await;
+    let other_cookie = app.login_new_user("other@example.com").await;
+
+    let history = login_history(&app, &cookie, "?limit=1").await;
+    assert_eq!(history.len(), 1);
+    assert_eq!(history[0]["success"], false);
+    assert_eq!(history[0]["failure_reason"], "invalid_password");
+
+    let history = login_history(&app, &cookie, "?limit=1&offset=1").await;
+    assert_eq!(history[0]["success"], true);
+    assert!(history[0]["failure_reason"].is_null());
+
+    assert_eq!(login_history(&app, &other_cookie, "").await.len(), 1);
+
+    app.request(Method::GET, "/api/v1/user/login-history", None, None)
+        .await
+        .error(StatusCode::UNAUTHORIZED);
+}
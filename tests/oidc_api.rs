@@ -0,0 +1,428 @@
+mod common;
+
+use axum::body::Body;
+use axum::extract::{Form, State};
+use axum::http::{header, Method, Request, StatusCode};
+use axum::response::IntoResponse;
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use chat_rs::external::db::repository::UserRepository;
+use chat_rs::logger::Secret;
+use chat_rs::server::oidc::{code_challenge, ProviderConfig};
+use common::{TestApp, TestResponse, PASSWORD};
+use jsonwebtoken::{encode, EncodingKey, Header};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex, OnceLock};
+use tower::ServiceExt;
+
+const CLIENT_ID: &str = "chat-rs";
+const CLIENT_SECRET: &str = "client-secret";
+const KEY_ID: &str = "test-key";
+// Modulus of tests/fixtures/oidc_private_key.pem
+const KEY_MODULUS: &str = "2z0DITFykFrM00u5WX-sMRp4YEs3nZsAD2gMin_-mwPqYSYEGneprKIQyAj6NKS-DfKsPoLSXA0tpniP_6Yb29Ak6radLDB87cqb2CHa_gUKNzET1OowAG9nZrMTnCljKPAiwmKPIudMywKPJh8UCgafedo8PT-IkQ3jpNV9XyhbaV0jzO-GS3p4KhoW9vygWobOhoPg2wjUij8NtKYlv5C7gAvx42IpajfumeTpUgKLmmdsBnOFf5Jtyalt2elUAuRRZembRbWxsJbAoTsRYDbeA0MQ_nY8Jo0MIsjoP1ak9ngu-MBvvDk12dW4W61F9_Z7bg2Sklze9a0M5wHyow";
+
+// Account the user signs in with at the mock provider
+#[derive(Clone)]
+struct Identity {
+    sub: &'static str,
+    email: &'static str,
+    email_verified: bool,
+}
+
+// Authorization the mock provider granted, redeemed once at the token endpoint
+struct Grant {
+    identity: Identity,
+    nonce: String,
+    code_challenge: String,
+}
+
+#[derive(Clone)]
+struct MockIdp {
+    issuer: String,
+    grants: Arc<Mutex<HashMap<String, Grant>>>,
+}
+
+impl MockIdp {
+    // Approve the authorization request as if the user had signed in at the provider
+    fn approve(&self, params: &HashMap<String, String>, identity: Identity) -> String {
+        assert_eq!(params["client_id"], CLIENT_ID);
+        assert_eq!(params["code_challenge_method"], "S256");
+        let code = uuid::Uuid::new_v4().to_string();
+        self.grants.lock().unwrap().insert(
+            code.clone(),
+            Grant {
+                identity,
+                nonce: params["nonce"].clone(),
+                code_challenge: params["code_challenge"].clone(),
+            },
+        );
+        code
+    }
+}
+
+async fn discovery(State(idp): State<MockIdp>) -> Json<Value> {
+    Json(json!({
+        "issuer": idp.issuer,
+        "authorization_endpoint": format!("{}/authorize", idp.issuer),
+        "token_endpoint": format!("{}/token", idp.issuer),
+        "jwks_uri": format!("{}/jwks", idp.issuer),
+    }))
+}
+
+async fn jwks() -> Json<Value> {
+    Json(json!({
+        "keys": [{ "kty": "RSA", "kid": KEY_ID, "alg": "RS256", "use": "sig", "n": KEY_MODULUS, "e": "AQAB" }]
+    }))
+}
+
+async fn token(
+    State(idp): State<MockIdp>,
+    Form(form): Form<HashMap<String, String>>,
+) -> impl IntoResponse {
+    let invalid_grant = (
+        StatusCode::BAD_REQUEST,
+        Json(json!({ "error": "invalid_grant" })),
+    );
+    if form["client_id"] != CLIENT_ID || form["client_secret"] != CLIENT_SECRET {
+        return invalid_grant.into_response();
+    }
+    let Some(grant) = idp.grants.lock().unwrap().remove(&form["code"]) else {
+        return invalid_grant.into_response();
+    };
+    if code_challenge(&form["code_verifier"]) != grant.code_challenge {
+        return invalid_grant.into_response();
+    }
+
+    let now = time::OffsetDateTime::now_utc().unix_timestamp();
+    let mut header = Header::new(jsonwebtoken::Algorithm::RS256);
+    header.kid = Some(KEY_ID.to_string());
+    let id_token = encode(
+        &header,
+        &json!({
+            "iss": idp.issuer,
+            "aud": CLIENT_ID,
+            "sub": grant.identity.sub,
+            "email": grant.identity.email,
+            "email_verified": grant.identity.email_verified,
+            "name": "Provider User",
+            "nonce": grant.nonce,
+            "iat": now,
+            "exp": now + 300,
+        }),
+        &EncodingKey::from_rsa_pem(include_bytes!("fixtures/oidc_private_key.pem")).unwrap(),
+    )
+    .unwrap();
+    Json(json!({ "access_token": "unused", "token_type": "Bearer", "id_token": id_token }))
+        .into_response()
+}
+
+// Every test shares one provider running on its own thread as each test has its own runtime
+fn idp() -> &'static MockIdp {
+    static IDP: OnceLock<MockIdp> = OnceLock::new();
+    IDP.get_or_init(|| {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address: SocketAddr = listener.local_addr().unwrap();
+        let idp = MockIdp {
+            issuer: format!("http://{}", address),
+            grants: Arc::default(),
+        };
+        let router = Router::new()
+            .route("/.well-known/openid-configuration", get(discovery))
+            .route("/jwks", get(jwks))
+            .route("/token", post(token))
+            .with_state(idp.clone());
+        std::thread::spawn(move || {
+            tokio::runtime::Runtime::new()
+                .unwrap()
+                .block_on(async move {
+                    axum::Server::from_tcp(listener)
+                        .unwrap()
+                        .serve(router.into_make_service())
+                        .await
+                        .unwrap();
+                })
+        });
+        idp
+    })
+}
+
+// Test app with the mock provider enabled
+async fn test_app() -> TestApp {
+    let mut config = common::test_config();
+    config.oidc_providers = vec![ProviderConfig::new(
+        "mock",
+        &idp().issuer,
+        CLIENT_ID.to_string(),
+        Secret::new(CLIENT_SECRET.to_string()),
+        "http://localhost:3000/api/v1/user/oidc/mock/callback".to_string(),
+    )];
+    TestApp::with_config(config).await
+}
+
+// Follow the redirect to the provider, returns the state cookie and the authorization request parameters
+async fn start(
+    app: &TestApp,
+    uri: &str,
+    cookie: Option<&str>,
+) -> (String, HashMap<String, String>) {
+    let mut request = Request::builder().uri(uri);
+    if let Some(cookie) = cookie {
+        request = request.header(header::COOKIE, cookie);
+    }
+    let response = app
+        .router
+        .clone()
+        .oneshot(request.body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::FOUND);
+
+    let location = response.headers()[header::LOCATION].to_str().unwrap();
+    let (endpoint, query) = location.split_once('?').unwrap();
+    assert_eq!(endpoint, format!("{}/authorize", idp().issuer));
+    let params = form_urlencoded::parse(query.as_bytes())
+        .into_owned()
+        .collect();
+    let state_cookie = response.headers()[header::SET_COOKIE]
+        .to_str()
+        .unwrap()
+        .split(';')
+        .next()
+        .unwrap()
+        .to_string();
+    (state_cookie, params)
+}
+
+async fn callback(app: &TestApp, cookie: &str, code: &str, state: &str) -> TestResponse {
+    app.request(
+        Method::GET,
+        &format!(
+            "/api/v1/user/oidc/mock/callback?code={}&state={}",
+            code, state
+        ),
+        None,
+        Some(cookie),
+    )
+    .await
+}
+
+// Run the whole sign-in flow with the provider account
+async fn sign_in(app: &TestApp, identity: Identity) -> TestResponse {
+    let (state_cookie, params) = start(app, "/api/v1/user/oidc/mock/authorize", None).await;
+    let code = idp().approve(&params, identity);
+    callback(app, &state_cookie, &code, &params["state"]).await
+}
+
+const ALICE: Identity = Identity {
+    sub: "alice-subject",
+    email: "alice@example.com",
+    email_verified: true,
+};
+
+#[tokio::test]
+async fn sign_in_creates_verified_user() {
+    let app = test_app().await;
+
+    let response = sign_in(&app, ALICE).await;
+    assert_eq!(
+        response.success(StatusCode::OK)["message"],
+        "Login success."
+    );
+    let cookie = response.cookie.unwrap();
+    let user = app
+        .repository
+        .get_user_by_email(ALICE.email)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(user.verified);
+    assert!(user.password.is_none());
+    assert_eq!(user.name.as_deref(), Some("Provider User"));
+
+    let response = app
+        .request(Method::GET, "/api/v1/user/oidc", None, Some(&cookie))
+        .await;
+    let identities = response.success(StatusCode::OK);
+    assert_eq!(identities[0]["provider"], "mock");
+    assert_eq!(identities[0]["email"], ALICE.email);
+
+    // Signing in again finds the linked user instead of creating another one
+    sign_in(&app, ALICE).await.success(StatusCode::OK);
+    let again = app
+        .repository
+        .get_user_by_email(ALICE.email)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(again.id, user.id);
+}
+
+#[tokio::test]
+async fn sign_in_links_existing_user_by_verified_email() {
+    let app = test_app().await;
+    app.login_new_user(ALICE.email).await;
+
+    sign_in(&app, ALICE).await.success(StatusCode::OK);
+
+    // The password keeps working for a user who activated the account
+    app.login(ALICE.email, PASSWORD)
+        .await
+        .success(StatusCode::OK);
+}
+
+#[tokio::test]
+async fn sign_in_claims_unverified_user_and_drops_its_password() {
+    let app = test_app().await;
+    app.register(ALICE.email, PASSWORD)
+        .await
+        .success(StatusCode::OK);
+
+    sign_in(&app, ALICE).await.success(StatusCode::OK);
+
+    let user = app
+        .repository
+        .get_user_by_email(ALICE.email)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(user.verified);
+    assert!(user.password.is_none());
+    app.login(ALICE.email, PASSWORD)
+        .await
+        .error(StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn sign_in_rejects_unverified_provider_email() {
+    let app = test_app().await;
+
+    let response = sign_in(
+        &app,
+        Identity {
+            email_verified: false,
+            ..ALICE
+        },
+    )
+    .await;
+    assert_eq!(
+        response.error(StatusCode::FORBIDDEN),
+        "Email address is not verified by the identity provider."
+    );
+}
+
+#[tokio::test]
+async fn callback_requires_matching_state() {
+    let app = test_app().await;
+    let (state_cookie, params) = start(&app, "/api/v1/user/oidc/mock/authorize", None).await;
+    let code = idp().approve(&params, ALICE);
+
+    for (cookie, state) in [
+        (state_cookie.as_str(), "other-state"),
+        ("oidc_state=invalid", params["state"].as_str()),
+    ] {
+        let response = callback(&app, cookie, &code, state).await;
+        assert_eq!(
+            response.error(StatusCode::UNAUTHORIZED),
+            "Invalid or expired sign-in state."
+        );
+    }
+}
+
+#[tokio::test]
+async fn authorization_code_cannot_be_reused() {
+    let app = test_app().await;
+    let (state_cookie, params) = start(&app, "/api/v1/user/oidc/mock/authorize", None).await;
+    let code = idp().approve(&params, ALICE);
+
+    callback(&app, &state_cookie, &code, &params["state"])
+        .await
+        .success(StatusCode::OK);
+    let response = callback(&app, &state_cookie, &code, &params["state"]).await;
+    assert_eq!(
+        response.error(StatusCode::UNAUTHORIZED),
+        "Sign-in with the identity provider failed."
+    );
+}
+
+#[tokio::test]
+async fn unknown_provider_is_not_found() {
+    let app = test_app().await;
+
+    let response = app
+        .request(
+            Method::GET,
+            "/api/v1/user/oidc/unknown/authorize",
+            None,
+            None,
+        )
+        .await;
+    assert_eq!(
+        response.error(StatusCode::NOT_FOUND),
+        "Unknown identity provider."
+    );
+}
+
+#[tokio::test]
+async fn signed_in_user_links_and_unlinks_provider() {
+    let app = test_app().await;
+    let cookie = app.login_new_user("bob@example.com").await;
+
+    let (state_cookie, params) = start(&app, "/api/v1/user/oidc/mock/link", Some(&cookie)).await;
+    // The provider account may use another email than the user
+    let code = idp().approve(
+        &params,
+        Identity {
+            sub: "bob-subject",
+            email: "bob@provider.example.com",
+            email_verified: true,
+        },
+    );
+    let response = callback(&app, &state_cookie, &code, &params["state"]).await;
+    assert_eq!(
+        response.success(StatusCode::OK)["message"],
+        "Identity provider linked."
+    );
+
+    let response = app
+        .request(Method::GET, "/api/v1/user/oidc", None, Some(&cookie))
+        .await;
+    assert_eq!(
+        response.success(StatusCode::OK)[0]["email"],
+        "bob@provider.example.com"
+    );
+
+    let unlink = || {
+        app.request(
+            Method::DELETE,
+            "/api/v1/user/oidc/mock",
+            None,
+            Some(&cookie),
+        )
+    };
+    unlink().await.success(StatusCode::OK);
+    assert_eq!(
+        unlink().await.error(StatusCode::NOT_FOUND),
+        "Identity provider is not linked."
+    );
+}
+
+#[tokio::test]
+async fn only_sign_in_method_cannot_be_unlinked() {
+    let app = test_app().await;
+    let cookie = sign_in(&app, ALICE).await.cookie.unwrap();
+
+    let response = app
+        .request(
+            Method::DELETE,
+            "/api/v1/user/oidc/mock",
+            None,
+            Some(&cookie),
+        )
+        .await;
+    assert_eq!(
+        response.error(StatusCode::CONFLICT),
+        "The only sign-in method of the user cannot be unlinked."
+    );
+}
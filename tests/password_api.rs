@@ -0,0 +1,100 @@
+mod common;
+
+use argon2::password_hash::rand_core::OsRng;
+use argon2::password_hash::{PasswordHasher, SaltString};
+use argon2::{Argon2, Params};
+use axum::http::StatusCode;
+use chat_rs::external::db::repository::{UserRepository, VerificationRepository};
+use chat_rs::external::db::user::NewUser;
+use chat_rs::logger::Secret;
+use common::{TestApp, ARGON2_MEMORY_KIB, PASSWORD};
+use time::{Duration, OffsetDateTime};
+
+// Create an activated user whose password was hashed with older settings
+async fn insert_user(app: &TestApp, email: &str, password_hash: String) {
+    app.repository
+        .register_user(
+            NewUser {
+                email: email.to_string(),
+                password: Secret::new(password_hash),
+            },
+            // The email doubles as the verification token hash, the token is never sent anywhere
+            email.to_string(),
+            OffsetDateTime::now_utc() + Duration::minutes(5),
+        )
+        .await
+        .unwrap();
+    assert!(app.repository.activate_user(email).await.unwrap());
+}
+
+async fn stored_password(app: &TestApp, email: &str) -> String {
+    let user = app
+        .repository
+        .get_user_by_email(email)
+        .await
+        .unwrap()
+        .unwrap();
+    user.password.unwrap().into_inner()
+}
+
+fn expected_argon2_prefix() -> String {
+    format!("$argon2id$v=19$m={},t=1,p=1$", ARGON2_MEMORY_KIB)
+}
+
+#[tokio::test]
+async fn register_stores_argon2id_hash() {
+    let app = TestApp::new().await;
+    app.login_new_user("argon2@example.com").await;
+
+    let password_hash = stored_password(&app, "argon2@example.com").await;
+    assert!(password_hash.starts_with(&expected_argon2_prefix()));
+    assert!(!password_hash.contains(PASSWORD));
+}
+
+#[tokio::test]
+async fn bcrypt_hash_is_upgraded_on_login() {
+    let app = TestApp::new().await;
+    let email = "bcrypt@example.com";
+    insert_user(&app, email, bcrypt::hash(PASSWORD, 4).unwrap()).await;
+
+    app.login(email, PASSWORD).await.success(StatusCode::OK);
+    let password_hash = stored_password(&app, email).await;
+    assert!(password_hash.starts_with(&expected_argon2_prefix()));
+    // The upgraded hash keeps working
+    app.login(email, PASSWORD).await.success(StatusCode::OK);
+    assert_eq!(stored_password(&app, email).await, password_hash);
+}
+
+#[tokio::test]
+async fn argon2_hash_with_outdated_parameters_is_upgraded_on_login() {
+    let app = TestApp::new().await;
+    let email = "outdated@example.com";
+    let argon2 = Argon2::new(
+        argon2::Algorithm::Argon2id,
+        argon2::Version::V0x13,
+        Params::new(512, 1, 1, None).unwrap(),
+    );
+    let previous_hash = argon2
+        .hash_password(PASSWORD.as_bytes(), &SaltString::generate(&mut OsRng))
+        .unwrap()
+        .to_string();
+    insert_user(&app, email, previous_hash.clone()).await;
+
+    app.login(email, PASSWORD).await.success(StatusCode::OK);
+    let password_hash = stored_password(&app, email).await;
+    assert_ne!(password_hash, previous_hash);
+    assert!(password_hash.starts_with(&expected_argon2_prefix()));
+}
+
+#[tokio::test]
+async fn failed_login_keeps_outdated_hash() {
+    let app = TestApp::new().await;
+    let email = "wrong@example.com";
+    let previous_hash = bcrypt::hash(PASSWORD, 4).unwrap();
+    insert_user(&app, email, previous_hash.clone()).await;
+
+    app.login(email, "WrongPassw0rd")
+        .await
+        .error(StatusCode::UNAUTHORIZED);
+    assert_eq!(stored_password(&app, email).await, previous_hash);
+}
@@ -0,0 +1,311 @@
+// Same flows as the in-memory tests, run against Postgres to cover the queries and constraints
+#![cfg(postgres_tests)]
+
+mod common;
+
+use axum::http::{Method, StatusCode};
+use chat_rs::external::db::admin_audit_log::NewAdminAuditLog;
+use chat_rs::external::db::models::UserRole;
+use chat_rs::external::db::repository::{
+    AccountRepository, AdminRepository, UserRepository, EXPORTED_LOGIN_HISTORY_LIMIT,
+};
+use chat_rs::external::db::user::UserFilter;
+use chat_rs::server::account;
+use common::{TestApp, PASSWORD};
+use serde_json::json;
+use sqlx::PgPool;
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+#[sqlx::test(migrations = "./migrations")]
+async fn register_activate_and_login(db_client: PgPool) {
+    let app = TestApp::with_postgres(db_client);
+    let email = "alice@example.com";
+
+    let response = app.register(email, PASSWORD).await;
+    let token = response.success(StatusCode::OK)["verification_token"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    assert_eq!(
+        app.login(email, PASSWORD)
+            .await
+            .error(StatusCode::FORBIDDEN),
+        "User is not activated."
+    );
+
+    app.activate(&token).await.success(StatusCode::OK);
+    // The token is stored hashed and deleted once used
+    app.activate(&token).await.error(StatusCode::BAD_REQUEST);
+    let response = app.login(email, PASSWORD).await;
+    response.success(StatusCode::OK);
+    assert!(response.cookie.is_some());
+
+    let user = app
+        .repository
+        .get_user_by_email(email)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(user.verified);
+    assert!(user.password.unwrap().expose().starts_with("$argon2id$"));
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn duplicate_email_is_conflict(db_client: PgPool) {
+    let app = TestApp::with_postgres(db_client);
+    app.register("bob@example.com", PASSWORD)
+        .await
+        .success(StatusCode::OK);
+
+    // Emails are unique regardless of case
+    for email in ["bob@example.com", "Bob@Example.com"] {
+        assert_eq!(
+            app.register(email, PASSWORD)
+                .await
+                .error(StatusCode::CONFLICT),
+            "User already exists."
+        );
+    }
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn purge_anonymizes_user_and_deletes_related_rows(db_client: PgPool) {
+    let app = TestApp::with_postgres(db_client.clone());
+    let email = "purge@example.com";
+    app.login_new_user(email).await;
+    let user = app
+        .repository
+        .get_user_by_email(email)
+        .await
+        .unwrap()
+        .unwrap();
+    let kept_email = "kept@example.com";
+    app.login_new_user(kept_email).await;
+
+    app.repository
+        .schedule_deletion(&user.id, OffsetDateTime::now_utc())
+        .await
+        .unwrap();
+    account::purge(app.repository.as_ref()).await;
+
+    let deleted_user = app
+        .repository
+        .get_user_by_id(&user.id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        deleted_user.email,
+        format!("deleted-{}@deleted.invalid", user.id)
+    );
+    assert!(deleted_user.password.is_none());
+    assert!(deleted_user.deleted_at.is_some());
+    let login_count: i64 =
+        sqlx::query_scalar("SELECT COUNT(*) FROM user_login_history WHERE user_id = $1")
+            .bind(user.id)
+            .fetch_one(&db_client)
+            .await
+            .unwrap();
+    assert_eq!(login_count, 0);
+
+    // Other users are left alone and the email can be registered again
+    let kept_user = app
+        .repository
+        .get_user_by_email(kept_email)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(kept_user.deleted_at.is_none());
+    app.register(email, PASSWORD).await.success(StatusCode::OK);
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn admin_action_is_rolled_back_with_its_audit_log(db_client: PgPool) {
+    let app = TestApp::with_postgres(db_client.clone());
+    let email = "unverified@example.com";
+    app.register(email, PASSWORD).await.success(StatusCode::OK);
+    let user_id = app.user_id(email).await;
+    // The unknown actor violates the foreign key, so the audit log insert fails last
+    let audit_log = NewAdminAuditLog {
+        actor_id: Uuid::new_v4(),
+        action: "verify_user".to_string(),
+        target_user_id: Some(user_id),
+        details: json!({}),
+    };
+
+    assert!(app
+        .repository
+        .verify_user(&user_id, audit_log)
+        .await
+        .is_err());
+    let user = app.repository.get_user_by_id(&user_id).await.unwrap();
+    assert!(!user.unwrap().verified);
+    let log_count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM admin_audit_log")
+        .fetch_one(&db_client)
+        .await
+        .unwrap();
+    assert_eq!(log_count, 0);
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn duplicate_pending_report_is_conflict(db_client: PgPool) {
+    let app = TestApp::with_postgres(db_client);
+    let cookie = app.login_new_user("reporter@example.com").await;
+    app.login_new_user("target@example.com").await;
+    let target_id = app.user_id("target@example.com").await;
+    let report = json!({ "target_user_id": target_id, "category": "spam" });
+
+    app.request(
+        Method::POST,
+        "/api/v1/reports",
+        Some(report.clone()),
+        Some(&cookie),
+    )
+    .await
+    .success(StatusCode::CREATED);
+    let response = app
+        .request(Method::POST, "/api/v1/reports", Some(report), Some(&cookie))
+        .await;
+    assert_eq!(
+        response.error(StatusCode::CONFLICT),
+        "User has already been reported."
+    );
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn purge_continues_after_failing_user(db_client: PgPool) {
+    let app = TestApp::with_postgres(db_client.clone());
+    app.login_new_user("failing@example.com").await;
+    app.login_new_user("purged@example.com").await;
+    let failing_id = app.user_id("failing@example.com").await;
+    let purged_id = app.user_id("purged@example.com").await;
+    // Takes the placeholder email, so anonymizing the first user violates the unique constraint
+    app.register(&format!("deleted-{}@deleted.invalid", failing_id), PASSWORD)
+        .await
+        .success(StatusCode::OK);
+    // Users are purged in the order of their deletion, the failing one comes first
+    for (user_id, hours_ago) in [(failing_id, 2), (purged_id, 1)] {
+        app.repository
+            .schedule_deletion(
+                &user_id,
+                OffsetDateTime::now_utc() - time::Duration::hours(hours_ago),
+            )
+            .await
+            .unwrap();
+    }
+
+    let purged_user_ids = app.repository.purge_deleted_users().await.unwrap();
+
+    assert_eq!(purged_user_ids, [purged_id]);
+    let failing_user = app.repository.get_user_by_id(&failing_id).await.unwrap();
+    assert!(failing_user.unwrap().deleted_at.is_none());
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn export_reuses_pending_export_and_caps_login_history(db_client: PgPool) {
+    let app = TestApp::with_postgres(db_client.clone());
+    let email = "export@example.com";
+    let cookie = app.login_new_user(email).await;
+    let user_id = app.user_id(email).await;
+    sqlx::query(
+        "INSERT INTO user_login_history (user_id, success, ip, is_new_device)
+         SELECT $1, true, 'unknown', false FROM generate_series(1, $2)",
+    )
+    .bind(user_id)
+    .bind(EXPORTED_LOGIN_HISTORY_LIMIT as i32)
+    .execute(&db_client)
+    .await
+    .unwrap();
+    // Left behind by a restart while it was being built
+    let stale = app.repository.create_data_export(&user_id).await.unwrap();
+    sqlx::query("UPDATE user_data_export SET created_at = now() - interval '1 hour' WHERE id = $1")
+        .bind(stale.id)
+        .execute(&db_client)
+        .await
+        .unwrap();
+    let pending = app.repository.create_data_export(&user_id).await.unwrap();
+
+    let response = app
+        .request(Method::POST, "/api/v1/user/me/export", None, Some(&cookie))
+        .await;
+    assert_eq!(
+        response.success(StatusCode::ACCEPTED)["id"],
+        pending.id.to_string()
+    );
+
+    let archive = app
+        .repository
+        .export_user_data(&user_id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        archive.login_history.len(),
+        EXPORTED_LOGIN_HISTORY_LIMIT as usize
+    );
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn suspension_from_report_is_audited(db_client: PgPool) {
+    let app = TestApp::with_postgres(db_client.clone());
+    let moderator_cookie = app.login_new_user("moderator@example.com").await;
+    app.set_role("moderator@example.com", UserRole::Moderator)
+        .await;
+    let cookie = app.login_new_user("reporter@example.com").await;
+    app.login_new_user("target@example.com").await;
+    let target_id = app.user_id("target@example.com").await;
+    let response = app
+        .request(
+            Method::POST,
+            "/api/v1/reports",
+            Some(json!({ "target_user_id": target_id, "category": "spam" })),
+            Some(&cookie),
+        )
+        .await;
+    let report_id = response.success(StatusCode::CREATED)["report_id"]
+        .as_str()
+        .unwrap()
+        .to_string();
+
+    app.request(
+        Method::POST,
+        &format!("/api/v1/moderation/reports/{}/resolve", report_id),
+        Some(json!({ "action": "suspend" })),
+        Some(&moderator_cookie),
+    )
+    .await
+    .success(StatusCode::OK);
+
+    let actions: Vec<String> =
+        sqlx::query_scalar("SELECT action FROM admin_audit_log WHERE target_user_id = $1")
+            .bind(target_id)
+            .fetch_all(&db_client)
+            .await
+            .unwrap();
+    assert_eq!(actions, ["suspend_user"]);
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn email_filter_matches_wildcards_literally(db_client: PgPool) {
+    let app = TestApp::with_postgres(db_client);
+    for email in ["first_user@example.com", "second@example.com"] {
+        app.register(email, PASSWORD).await.success(StatusCode::OK);
+    }
+
+    for (query, expected) in [("_", vec!["first_user@example.com"]), ("%", vec![])] {
+        let users = app
+            .repository
+            .list_users(UserFilter {
+                email: Some(query.to_string()),
+                role: None,
+                verified: None,
+                limit: 10,
+                offset: 0,
+            })
+            .await
+            .unwrap();
+        let emails: Vec<&str> = users.iter().map(|user| user.email.as_str()).collect();
+        assert_eq!(emails, expected);
+    }
+}
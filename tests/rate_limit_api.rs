@@ -0,0 +1,42 @@
+mod common;
+
+use axum::http::{header, Method, StatusCode};
+use common::TestApp;
+
+#[tokio::test]
+async fn auth_routes_are_rate_limited_per_ip() {
+    let app = TestApp::new().await;
+    let capacity = app.config.rate_limits.auth.per_ip.capacity;
+    for _ in 0..capacity {
+        app.activate("invalid").await.error(StatusCode::BAD_REQUEST);
+    }
+
+    let response = app.activate("invalid").await;
+    assert_eq!(
+        response.error(StatusCode::TOO_MANY_REQUESTS),
+        "Too many requests. Please try again later."
+    );
+    let retry_after: u64 = response.headers[header::RETRY_AFTER]
+        .to_str()
+        .unwrap()
+        .parse()
+        .unwrap();
+    // One token comes back every period / capacity
+    let period = app.config.rate_limits.auth.per_ip.period.as_secs();
+    assert!((1..=period / capacity as u64).contains(&retry_after));
+
+    // Other route groups have buckets of their own
+    app.request(Method::GET, "/api/v1/user/login-history", None, None)
+        .await
+        .error(StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn probes_are_not_rate_limited() {
+    let app = TestApp::new().await;
+    for _ in 0..app.config.rate_limits.auth.per_ip.capacity * 2 {
+        app.request(Method::GET, "/healthz", None, None)
+            .await
+            .success(StatusCode::OK);
+    }
+}
@@ -0,0 +1,277 @@
+mod common;
+
+use axum::http::{Method, StatusCode};
+use chat_rs::external::db::models::{UserRestriction, UserRole};
+use chat_rs::external::db::repository::{AccountRepository, AdminRepository, UserRepository};
+use common::TestApp;
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+const MODERATOR: &str = "moderator@example.com";
+const REPORTER: &str = "reporter@example.com";
+const TARGET: &str = "target@example.com";
+
+struct Reporting {
+    app: TestApp,
+    moderator_cookie: String,
+    reporter_cookie: String,
+    target_id: Uuid,
+}
+
+async fn reporting() -> Reporting {
+    let app = TestApp::new().await;
+    let moderator_cookie = app.login_new_user(MODERATOR).await;
+    app.set_role(MODERATOR, UserRole::Moderator).await;
+    let reporter_cookie = app.login_new_user(REPORTER).await;
+    app.login_new_user(TARGET).await;
+    let target_id = app.user_id(TARGET).await;
+
+    Reporting {
+        app,
+        moderator_cookie,
+        reporter_cookie,
+        target_id,
+    }
+}
+
+impl Reporting {
+    async fn report(&self, target_id: Uuid) -> common::TestResponse {
+        self.app
+            .request(
+                Method::POST,
+                "/api/v1/reports",
+                Some(json!({
+                    "target_user_id": target_id,
+                    "category": "harassment",
+                    "description": "Keeps sending insults.",
+                })),
+                Some(&self.reporter_cookie),
+            )
+            .await
+    }
+
+    async fn resolve(&self, report_id: &str, body: Value) -> common::TestResponse {
+        self.app
+            .request(
+                Method::POST,
+                &format!("/api/v1/moderation/reports/{}/resolve", report_id),
+                Some(body),
+                Some(&self.moderator_cookie),
+            )
+            .await
+    }
+
+    async fn queue(&self, status: &str) -> Vec<Value> {
+        self.app
+            .request(
+                Method::GET,
+                &format!("/api/v1/moderation/reports?status={}", status),
+                None,
+                Some(&self.moderator_cookie),
+            )
+            .await
+            .success(StatusCode::OK)
+            .as_array()
+            .unwrap()
+            .clone()
+    }
+
+    // File a report against the target and return its id
+    async fn file_report(&self) -> String {
+        let response = self.report(self.target_id).await;
+        let result = response.success(StatusCode::CREATED);
+        assert_eq!(result["message"], "Report submitted.");
+        result["report_id"].as_str().unwrap().to_string()
+    }
+}
+
+#[tokio::test]
+async fn reported_user_appears_in_queue() {
+    let reporting = reporting().await;
+    let report_id = reporting.file_report().await;
+
+    let queue = reporting.queue("pending").await;
+    assert_eq!(queue.len(), 1);
+    assert_eq!(queue[0]["id"], report_id);
+    assert_eq!(queue[0]["target_user_id"], reporting.target_id.to_string());
+    assert_eq!(queue[0]["category"], "harassment");
+    assert_eq!(queue[0]["description"], "Keeps sending insults.");
+}
+
+#[tokio::test]
+async fn queue_requires_moderator() {
+    let reporting = reporting().await;
+    reporting.file_report().await;
+
+    let response = reporting
+        .app
+        .request(
+            Method::GET,
+            "/api/v1/moderation/reports",
+            None,
+            Some(&reporting.reporter_cookie),
+        )
+        .await;
+    assert_eq!(
+        response.error(StatusCode::FORBIDDEN),
+        "Insufficient permission."
+    );
+}
+
+#[tokio::test]
+async fn duplicate_and_self_reports_are_rejected() {
+    let reporting = reporting().await;
+    let report_id = reporting.file_report().await;
+
+    assert_eq!(
+        reporting
+            .report(reporting.target_id)
+            .await
+            .error(StatusCode::CONFLICT),
+        "User has already been reported."
+    );
+    let reporter_id = reporting.app.user_id(REPORTER).await;
+    assert_eq!(
+        reporting
+            .report(reporter_id)
+            .await
+            .error(StatusCode::BAD_REQUEST),
+        "User cannot report themselves."
+    );
+
+    // Once resolved, the same user can be reported again
+    reporting
+        .resolve(&report_id, json!({ "action": "dismiss" }))
+        .await
+        .success(StatusCode::OK);
+    reporting.file_report().await;
+}
+
+#[tokio::test]
+async fn dismiss_leaves_user_untouched() {
+    let reporting = reporting().await;
+    let report_id = reporting.file_report().await;
+
+    let response = reporting
+        .resolve(
+            &report_id,
+            json!({ "action": "dismiss", "note": "Banter." }),
+        )
+        .await;
+    assert_eq!(
+        response.success(StatusCode::OK)["message"],
+        "Report dismissed."
+    );
+
+    assert!(reporting.queue("pending").await.is_empty());
+    let dismissed = reporting.queue("dismissed").await;
+    assert_eq!(dismissed[0]["action"], "dismiss");
+    assert_eq!(dismissed[0]["moderator_note"], "Banter.");
+    let archive = reporting
+        .app
+        .repository
+        .export_user_data(&reporting.target_id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(archive.warnings.is_empty());
+    assert!(archive.profile.restriction.is_none());
+
+    // Resolved reports cannot be resolved again
+    assert_eq!(
+        reporting
+            .resolve(&report_id, json!({ "action": "warn" }))
+            .await
+            .error(StatusCode::CONFLICT),
+        "Report has already been resolved."
+    );
+}
+
+#[tokio::test]
+async fn warn_records_warning() {
+    let reporting = reporting().await;
+    let report_id = reporting.file_report().await;
+
+    let response = reporting
+        .resolve(&report_id, json!({ "action": "warn", "note": "Be nice." }))
+        .await;
+    assert_eq!(response.success(StatusCode::OK)["message"], "User warned.");
+
+    let actioned = reporting.queue("actioned").await;
+    assert_eq!(actioned[0]["action"], "warn");
+    let archive = reporting
+        .app
+        .repository
+        .export_user_data(&reporting.target_id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(archive.warnings.len(), 1);
+    assert_eq!(archive.warnings[0].reason, "Be nice.");
+    assert!(archive.profile.restriction.is_none());
+}
+
+#[tokio::test]
+async fn suspend_restricts_user_and_revokes_tokens() {
+    let reporting = reporting().await;
+    let report_id = reporting.file_report().await;
+
+    let response = reporting
+        .resolve(&report_id, json!({ "action": "suspend" }))
+        .await;
+    assert_eq!(
+        response.success(StatusCode::OK)["message"],
+        "User suspended."
+    );
+
+    let actioned = reporting.queue("actioned").await;
+    assert_eq!(actioned[0]["action"], "suspend");
+    let target = reporting
+        .app
+        .repository
+        .get_user_by_id(&reporting.target_id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(target.restriction, Some(UserRestriction::Suspended));
+    // Without a note the reason falls back to the report category
+    assert_eq!(
+        target.restriction_reason.as_deref(),
+        Some("Reported for Harassment.")
+    );
+    assert!(target.restriction_expires_at.is_none());
+    assert!(target.tokens_revoked_at.is_some());
+
+    // Audited like a suspension through the admin API
+    let logs = reporting
+        .app
+        .repository
+        .list_audit_logs(Some(reporting.target_id), 50, 0)
+        .await
+        .unwrap();
+    assert_eq!(logs.len(), 1);
+    assert_eq!(logs[0].action, "suspend_user");
+    assert_eq!(logs[0].actor_id, reporting.app.user_id(MODERATOR).await);
+    assert_eq!(logs[0].details["reason"], "Reported for Harassment.");
+    assert_eq!(logs[0].details["report_id"], report_id);
+}
+
+#[tokio::test]
+async fn moderator_cannot_act_against_moderator() {
+    let reporting = reporting().await;
+    reporting.app.set_role(TARGET, UserRole::Moderator).await;
+    let report_id = reporting.file_report().await;
+
+    assert_eq!(
+        reporting
+            .resolve(&report_id, json!({ "action": "suspend" }))
+            .await
+            .error(StatusCode::FORBIDDEN),
+        "Insufficient permission."
+    );
+    // Dismissing is always allowed
+    reporting
+        .resolve(&report_id, json!({ "action": "dismiss" }))
+        .await
+        .success(StatusCode::OK);
+}
@@ -0,0 +1,125 @@
+mod common;
+
+use axum::body::Body;
+use axum::http::{HeaderMap, Request, StatusCode};
+use axum::routing::post;
+use axum::Router;
+use common::TestApp;
+use serde_json::Value;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use tower::ServiceExt;
+use tracing_subscriber::prelude::__tracing_subscriber_SubscriberExt;
+
+const TRACE_ID: &str = "4bf92f3577b34da6a3ce929d0e0e4736";
+
+async fn get(app: &TestApp, uri: &str, headers: &[(&str, &str)]) -> (HeaderMap, Value) {
+    let mut request = Request::builder().uri(uri);
+    for (name, value) in headers {
+        request = request.header(*name, *value);
+    }
+    let response = app
+        .router
+        .clone()
+        .oneshot(request.body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    let headers = response.headers().clone();
+    let bytes = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    (headers, serde_json::from_slice(&bytes).unwrap())
+}
+
+#[tokio::test]
+async fn generated_request_id_is_returned_in_header_and_error() {
+    let app = TestApp::new().await;
+
+    let (headers, body) = get(&app, "/api/v1/admin/users", &[]).await;
+
+    let request_id = headers["x-request-id"].to_str().unwrap();
+    assert!(uuid::Uuid::parse_str(request_id).is_ok());
+    assert_eq!(body["request_id"], request_id);
+    assert!(headers.contains_key("traceparent"));
+}
+
+#[tokio::test]
+async fn valid_request_id_is_propagated() {
+    let app = TestApp::new().await;
+
+    let (headers, body) = get(&app, "/api/v1/admin/users", &[("x-request-id", "abc-123")]).await;
+
+    assert_eq!(headers["x-request-id"], "abc-123");
+    assert_eq!(body["request_id"], "abc-123");
+}
+
+#[tokio::test]
+async fn invalid_request_id_is_replaced() {
+    let app = TestApp::new().await;
+
+    let (headers, body) = get(
+        &app,
+        "/api/v1/admin/users",
+        &[("x-request-id", "not valid\"id")],
+    )
+    .await;
+
+    let request_id = headers["x-request-id"].to_str().unwrap();
+    assert!(uuid::Uuid::parse_str(request_id).is_ok());
+    assert_eq!(body["request_id"], request_id);
+}
+
+#[tokio::test]
+async fn incoming_trace_is_continued() {
+    let app = TestApp::new().await;
+
+    let (headers, _) = get(
+        &app,
+        "/api/v1/admin/users",
+        &[(
+            "traceparent",
+            &format!("00-{}-00f067aa0ba902b7-01", TRACE_ID),
+        )],
+    )
+    .await;
+
+    let traceparent = headers["traceparent"].to_str().unwrap();
+    let parts: Vec<&str> = traceparent.split('-').collect();
+    assert_eq!(parts.len(), 4);
+    assert_eq!(parts[1], TRACE_ID);
+    // The server is the parent of downstream calls, not the caller's span
+    assert_ne!(parts[2], "00f067aa0ba902b7");
+    assert_eq!(parts[3], "01");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn spans_are_exported_to_collector() {
+    // Stand-in for an OpenTelemetry collector that only counts the export requests
+    let exports = Arc::new(AtomicUsize::new(0));
+    let collector = Router::new().route(
+        "/v1/traces",
+        post({
+            let exports = exports.clone();
+            move || async move {
+                exports.fetch_add(1, Ordering::SeqCst);
+                StatusCode::OK
+            }
+        }),
+    );
+    let server =
+        axum::Server::bind(&"127.0.0.1:0".parse().unwrap()).serve(collector.into_make_service());
+    let endpoint = format!("http://{}", server.local_addr());
+    tokio::spawn(server);
+
+    let tracer = chat_rs::logger::otlp_tracer(&endpoint, "chat-rs-test").unwrap();
+    let subscriber =
+        tracing_subscriber::registry().with(tracing_opentelemetry::layer().with_tracer(tracer));
+    let app = TestApp::new().await;
+    let _guard = tracing::subscriber::set_default(subscriber);
+    get(&app, "/api/v1/admin/users", &[]).await;
+
+    // Shutting the provider down flushes the batch and blocks until it is sent
+    tokio::task::spawn_blocking(chat_rs::logger::shutdown)
+        .await
+        .unwrap();
+    assert!(exports.load(Ordering::SeqCst) > 0);
+}
@@ -0,0 +1,78 @@
+mod common;
+
+use chat_rs::server::serve;
+use common::{TestApp, PASSWORD};
+use std::net::TcpListener;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::sync::oneshot;
+use tokio::time::Instant;
+
+#[tokio::test]
+async fn server_drains_in_flight_requests_on_shutdown() {
+    let app = TestApp::new().await;
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let (shutdown_sender, shutdown_receiver) = oneshot::channel::<()>();
+    let server = tokio::spawn({
+        let state = app.state.clone();
+        let rate_limits = app.config.rate_limits.clone();
+        async move {
+            serve(
+                listener,
+                state,
+                &rate_limits,
+                async {
+                    let _ = shutdown_receiver.await;
+                },
+                Duration::from_secs(5),
+            )
+            .await
+        }
+    });
+
+    // The request body is only partly sent when the shutdown starts
+    let body = format!(
+        r#"{{"email":"nobody@example.com","password":"{}"}}"#,
+        PASSWORD
+    );
+    let (first_part, second_part) = body.split_at(10);
+    let mut stream = TcpStream::connect(address).await.unwrap();
+    stream
+        .write_all(
+            format!(
+                "POST /api/v1/user/login HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                address,
+                body.len(),
+                first_part
+            )
+            .as_bytes(),
+        )
+        .await
+        .unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    shutdown_sender.send(()).unwrap();
+
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert!(!server.is_finished(), "server should wait for the request");
+
+    stream.write_all(second_part.as_bytes()).await.unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    assert!(
+        response.starts_with("HTTP/1.1 401"),
+        "unexpected response {}",
+        response
+    );
+
+    let deadline = tokio::time::timeout(Duration::from_secs(2), server)
+        .await
+        .expect("server should return once drained")
+        .unwrap()
+        .unwrap();
+    assert!(TcpStream::connect(address).await.is_err());
+    // The grace period counts from the shutdown signal, the rest of it is left for closing the pool
+    let remaining = deadline - Instant::now();
+    assert!(remaining > Duration::from_secs(4) && remaining < Duration::from_secs(5));
+}
@@ -0,0 +1,304 @@
+mod common;
+
+use axum::body::Body;
+use axum::http::{header, Method, Request, StatusCode};
+use common::{TestApp, LOCKOUT_SECONDS, LOCKOUT_THRESHOLD, PASSWORD};
+use serde_json::json;
+use std::time::{SystemTime, UNIX_EPOCH};
+use totp_rs::{Algorithm, Secret, TOTP};
+use tower::ServiceExt;
+
+// Code for the current time step shifted by the given number of steps, as an authenticator app would show it
+fn code(secret: &str, steps: i64) -> String {
+    let totp = TOTP::new(
+        Algorithm::SHA1,
+        6,
+        0,
+        30,
+        Secret::Encoded(secret.to_string()).to_bytes().unwrap(),
+        None,
+        String::new(),
+    )
+    .unwrap();
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as i64;
+    totp.generate((now + steps * 30) as u64)
+}
+
+// Start enrollment for a new user and return the access token cookie and the secret
+async fn enroll(app: &TestApp, email: &str) -> (String, String) {
+    let cookie = app.login_new_user(email).await;
+    let response = app
+        .request(Method::POST, "/api/v1/user/2fa/enroll", None, Some(&cookie))
+        .await;
+    let result = response.success(StatusCode::OK);
+    let secret = result["secret"].as_str().unwrap().to_string();
+    assert!(result["otpauth_uri"]
+        .as_str()
+        .unwrap()
+        .starts_with("otpauth://totp/"));
+    (cookie, secret)
+}
+
+// Enroll and confirm, returns the access token cookie, the secret and the recovery codes
+async fn enable(app: &TestApp, email: &str) -> (String, String, Vec<String>) {
+    let (cookie, secret) = enroll(app, email).await;
+    let response = app
+        .request(
+            Method::POST,
+            "/api/v1/user/2fa/confirm",
+            Some(json!({ "code": code(&secret, 0) })),
+            Some(&cookie),
+        )
+        .await;
+    let recovery_codes = response.success(StatusCode::OK)["recovery_codes"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|code| code.as_str().unwrap().to_string())
+        .collect();
+    (cookie, secret, recovery_codes)
+}
+
+async fn challenge(app: &TestApp, email: &str) -> String {
+    let response = app.login(email, PASSWORD).await;
+    assert!(response.cookie.is_none());
+    let result = response.success(StatusCode::OK);
+    assert_eq!(result["two_factor_required"], true);
+    result["challenge_token"].as_str().unwrap().to_string()
+}
+
+async fn login_two_factor(
+    app: &TestApp,
+    challenge_token: &str,
+    code: &str,
+) -> common::TestResponse {
+    app.request(
+        Method::POST,
+        "/api/v1/user/login/2fa",
+        Some(json!({ "challenge_token": challenge_token, "code": code })),
+        None,
+    )
+    .await
+}
+
+#[tokio::test]
+async fn enrolled_user_logs_in_with_code() {
+    let app = TestApp::new().await;
+    let email = "totp@example.com";
+    let (cookie, secret, recovery_codes) = enable(&app, email).await;
+    assert_eq!(recovery_codes.len(), 10);
+
+    let response = app
+        .request(Method::GET, "/api/v1/user/2fa", None, Some(&cookie))
+        .await;
+    let result = response.success(StatusCode::OK);
+    assert_eq!(result["enabled"], true);
+    assert_eq!(result["recovery_codes_remaining"], 10);
+
+    let challenge_token = challenge(&app, email).await;
+    // The confirmation code's step is already used, the authenticator shows the next one
+    let response = login_two_factor(&app, &challenge_token, &code(&secret, 1)).await;
+    assert_eq!(
+        response.success(StatusCode::OK)["two_factor_required"],
+        false
+    );
+    assert!(response.cookie.is_some());
+}
+
+#[tokio::test]
+async fn login_without_two_factor_is_unchanged() {
+    let app = TestApp::new().await;
+    app.login_new_user("plain@example.com").await;
+
+    let response = app.login("plain@example.com", PASSWORD).await;
+    let result = response.success(StatusCode::OK);
+    assert_eq!(result["two_factor_required"], false);
+    assert!(result.get("challenge_token").is_none());
+}
+
+#[tokio::test]
+async fn qr_code_is_served_as_png_and_svg() {
+    let app = TestApp::new().await;
+    let (cookie, _) = enroll(&app, "qr@example.com").await;
+
+    for (format, content_type) in [("png", "image/png"), ("svg", "image/svg+xml")] {
+        let request = Request::builder()
+            .uri(format!("/api/v1/user/2fa/enroll/qr?format={}", format))
+            .header(header::COOKIE, &cookie)
+            .body(Body::empty())
+            .unwrap();
+        let response = app.router.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], content_type);
+        assert_eq!(response.headers()[header::CACHE_CONTROL], "no-store");
+        let bytes = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        match format {
+            "png" => assert!(bytes.starts_with(b"\x89PNG")),
+            _ => assert!(String::from_utf8_lossy(&bytes).contains("<svg")),
+        }
+    }
+}
+
+#[tokio::test]
+async fn confirm_rejects_wrong_code() {
+    let app = TestApp::new().await;
+    let (cookie, secret) = enroll(&app, "wrong@example.com").await;
+
+    let response = app
+        .request(
+            Method::POST,
+            "/api/v1/user/2fa/confirm",
+            Some(json!({ "code": code(&secret, 10) })),
+            Some(&cookie),
+        )
+        .await;
+    assert_eq!(
+        response.error(StatusCode::UNAUTHORIZED),
+        "Invalid two-factor code."
+    );
+    let response = app
+        .request(Method::GET, "/api/v1/user/2fa", None, Some(&cookie))
+        .await;
+    assert_eq!(response.success(StatusCode::OK)["enabled"], false);
+}
+
+#[tokio::test]
+async fn code_cannot_be_replayed() {
+    let app = TestApp::new().await;
+    let email = "replay@example.com";
+    let (_, secret, _) = enable(&app, email).await;
+    let code = code(&secret, 1);
+
+    let challenge_token = challenge(&app, email).await;
+    login_two_factor(&app, &challenge_token, &code)
+        .await
+        .success(StatusCode::OK);
+    let response = login_two_factor(&app, &challenge_token, &code).await;
+    assert_eq!(
+        response.error(StatusCode::UNAUTHORIZED),
+        "Invalid two-factor code."
+    );
+}
+
+#[tokio::test]
+async fn recovery_code_can_only_be_used_once() {
+    let app = TestApp::new().await;
+    let email = "recovery@example.com";
+    let (cookie, _, recovery_codes) = enable(&app, email).await;
+
+    let challenge_token = challenge(&app, email).await;
+    // Case and dashes are ignored when the code is typed in
+    let typed = recovery_codes[0].to_uppercase().replace('-', "");
+    login_two_factor(&app, &challenge_token, &typed)
+        .await
+        .success(StatusCode::OK);
+    login_two_factor(&app, &challenge_token, &recovery_codes[0])
+        .await
+        .error(StatusCode::UNAUTHORIZED);
+
+    let response = app
+        .request(Method::GET, "/api/v1/user/2fa", None, Some(&cookie))
+        .await;
+    assert_eq!(
+        response.success(StatusCode::OK)["recovery_codes_remaining"],
+        9
+    );
+}
+
+#[tokio::test]
+async fn disable_requires_valid_code() {
+    let app = TestApp::new().await;
+    let email = "disable@example.com";
+    let (cookie, _, recovery_codes) = enable(&app, email).await;
+
+    let disable = |code: String| {
+        app.request(
+            Method::POST,
+            "/api/v1/user/2fa/disable",
+            Some(json!({ "code": code })),
+            Some(&cookie),
+        )
+    };
+    disable("000000".to_string())
+        .await
+        .error(StatusCode::UNAUTHORIZED);
+    disable(recovery_codes[1].clone())
+        .await
+        .success(StatusCode::OK);
+
+    let response = app.login(email, PASSWORD).await;
+    assert_eq!(
+        response.success(StatusCode::OK)["two_factor_required"],
+        false
+    );
+    assert!(response.cookie.is_some());
+}
+
+#[tokio::test]
+async fn invalid_challenge_token_is_rejected() {
+    let app = TestApp::new().await;
+    let cookie = app.login_new_user("challenge@example.com").await;
+    // An access token is a valid token of this server but must not pass as a challenge
+    let access_token = cookie.split_once('=').unwrap().1;
+
+    for challenge_token in ["invalid", access_token] {
+        let response = login_two_factor(&app, challenge_token, "123456").await;
+        assert_eq!(
+            response.error(StatusCode::UNAUTHORIZED),
+            "Invalid or expired challenge token."
+        );
+    }
+}
+
+#[tokio::test]
+async fn code_attempts_are_rate_limited_per_user() {
+    // The lockout would otherwise stop the attempts before the rate limit does
+    let mut config = common::test_config();
+    config.lockout_policy.threshold = 100;
+    let app = TestApp::with_config(config).await;
+    let email = "limited@example.com";
+    enable(&app, email).await;
+    let challenge_token = challenge(&app, email).await;
+
+    // Confirming the enrollment already counted as one attempt of the user
+    for _ in 0..4 {
+        login_two_factor(&app, &challenge_token, "zzzzz-zzzzz")
+            .await
+            .error(StatusCode::UNAUTHORIZED);
+    }
+    let response = login_two_factor(&app, &challenge_token, "zzzzz-zzzzz").await;
+    response.error(StatusCode::TOO_MANY_REQUESTS);
+}
+
+#[tokio::test]
+async fn wrong_codes_lock_the_account() {
+    let app = TestApp::new().await;
+    let email = "guessing@example.com";
+    let (_, secret, _) = enable(&app, email).await;
+    let challenge_token = challenge(&app, email).await;
+
+    for _ in 0..LOCKOUT_THRESHOLD {
+        login_two_factor(&app, &challenge_token, "zzzzz-zzzzz")
+            .await
+            .error(StatusCode::UNAUTHORIZED);
+    }
+    // Even the right code is rejected while the lock lasts
+    let response = login_two_factor(&app, &challenge_token, &code(&secret, 0)).await;
+    assert_eq!(
+        response.error(StatusCode::TOO_MANY_REQUESTS),
+        "Too many failed login attempts. Please try again later."
+    );
+    assert_eq!(
+        response.headers[header::RETRY_AFTER],
+        LOCKOUT_SECONDS.to_string()
+    );
+    assert!(response.cookie.is_none());
+
+    // The password login is locked as well
+    app.login(email, PASSWORD)
+        .await
+        .error(StatusCode::UNAUTHORIZED);
+}
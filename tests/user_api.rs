@@ -0,0 +1,242 @@
+mod common;
+
+use axum::body::Body;
+use axum::http::{header, Method, Request, StatusCode};
+use common::{TestApp, TestResponse, PASSWORD};
+
+#[tokio::test]
+async fn register_returns_verification_token_and_cookie() {
+    let app = TestApp::new().await;
+
+    let response = app.register("alice@example.com", PASSWORD).await;
+
+    let result = response.success(StatusCode::OK);
+    assert_eq!(result["message"], "User registration complete.");
+    assert!(result["verification_token"].is_string());
+    assert!(response.cookie.unwrap().starts_with("token="));
+}
+
+#[tokio::test]
+async fn register_rejects_email_differing_only_by_case() {
+    let app = TestApp::new().await;
+    app.register("Alice@Example.com", PASSWORD)
+        .await
+        .success(StatusCode::OK);
+
+    let response = app.register(" alice@example.COM", PASSWORD).await;
+
+    assert_eq!(response.error(StatusCode::CONFLICT), "User already exists.");
+}
+
+#[tokio::test]
+async fn register_reports_invalid_fields() {
+    let app = TestApp::new().await;
+
+    let response = app.register("not-an-email", "short").await;
+
+    assert_eq!(
+        response.error(StatusCode::UNPROCESSABLE_ENTITY),
+        "Request validation failed."
+    );
+    let fields: Vec<&str> = response.body["fields"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|field| field["field"].as_str().unwrap())
+        .collect();
+    assert!(fields.contains(&"email"));
+    assert!(fields.contains(&"password"));
+}
+
+#[tokio::test]
+async fn register_reports_missing_field() {
+    let app = TestApp::new().await;
+
+    let response = app
+        .request(
+            Method::POST,
+            "/api/v1/user/register",
+            Some(serde_json::json!({ "email": "alice@example.com" })),
+            None,
+        )
+        .await;
+
+    response.error(StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(response.body["fields"][0]["field"], "password");
+    assert_eq!(response.body["fields"][0]["code"], "missing_field");
+}
+
+// Raw body, as the helpers only send valid JSON
+async fn register_with_body(app: &TestApp, content_type: &str, body: Vec<u8>) -> TestResponse {
+    let request = Request::builder()
+        .method(Method::POST)
+        .uri("/api/v1/user/register")
+        .header(header::CONTENT_TYPE, content_type)
+        .body(Body::from(body))
+        .unwrap();
+    app.send(request).await
+}
+
+#[tokio::test]
+async fn register_reports_malformed_json() {
+    let app = TestApp::new().await;
+
+    let response = register_with_body(&app, "application/json", b"{\"email\": ".to_vec()).await;
+
+    assert_eq!(
+        response.error(StatusCode::BAD_REQUEST),
+        "Malformed JSON in request body at line 1 column 10: EOF while parsing a value."
+    );
+}
+
+#[tokio::test]
+async fn register_requires_json_content_type() {
+    let app = TestApp::new().await;
+    let body = serde_json::json!({ "email": "alice@example.com", "password": PASSWORD });
+
+    let response = register_with_body(&app, "text/plain", body.to_string().into_bytes()).await;
+
+    assert_eq!(
+        response.error(StatusCode::UNSUPPORTED_MEDIA_TYPE),
+        "Expected request with Content-Type: application/json."
+    );
+}
+
+#[tokio::test]
+async fn register_rejects_oversized_body() {
+    let app = TestApp::new().await;
+    // Larger than the 2 MB axum accepts by default
+    let password = "a".repeat(3 * 1024 * 1024);
+    let body = serde_json::json!({ "email": "alice@example.com", "password": password });
+
+    let response =
+        register_with_body(&app, "application/json", body.to_string().into_bytes()).await;
+
+    assert_eq!(
+        response.error(StatusCode::PAYLOAD_TOO_LARGE),
+        "Request body is too large."
+    );
+}
+
+#[tokio::test]
+async fn login_requires_activation() {
+    let app = TestApp::new().await;
+    app.register("alice@example.com", PASSWORD)
+        .await
+        .success(StatusCode::OK);
+
+    let response = app.login("alice@example.com", PASSWORD).await;
+
+    assert_eq!(
+        response.error(StatusCode::FORBIDDEN),
+        "User is not activated."
+    );
+}
+
+#[tokio::test]
+async fn activated_user_can_login_with_any_email_case() {
+    let app = TestApp::new().await;
+    let response = app.register("alice@example.com", PASSWORD).await;
+    let token = response.success(StatusCode::OK)["verification_token"]
+        .as_str()
+        .unwrap()
+        .to_string();
+
+    let response = app.activate(&token).await;
+    assert_eq!(
+        response.success(StatusCode::OK)["message"],
+        "User activated."
+    );
+
+    let response = app.login("ALICE@example.com", PASSWORD).await;
+    assert_eq!(
+        response.success(StatusCode::OK)["message"],
+        "Login success."
+    );
+    assert!(response.cookie.unwrap().starts_with("token="));
+}
+
+#[tokio::test]
+async fn login_rejects_wrong_password() {
+    let app = TestApp::new().await;
+    app.login_new_user("alice@example.com").await;
+
+    let response = app.login("alice@example.com", "Wr0ngPassword").await;
+
+    assert_eq!(
+        response.error(StatusCode::UNAUTHORIZED),
+        "Invalid email or password."
+    );
+}
+
+#[tokio::test]
+async fn activate_rejects_invalid_token() {
+    let app = TestApp::new().await;
+
+    let response = app.activate("not-a-token").await;
+
+    assert_eq!(
+        response.error(StatusCode::BAD_REQUEST),
+        "Invalid verification token."
+    );
+}
+
+#[tokio::test]
+async fn verification_token_is_opaque_and_single_use() {
+    let app = TestApp::new().await;
+    let response = app.register("alice@example.com", PASSWORD).await;
+    let token = response.success(StatusCode::OK)["verification_token"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    // Not a JWT, there are no claims a client could read or forge
+    assert_eq!(token.len(), 43);
+    assert!(!token.contains('.'));
+    // Any token carrying the user id, like the access token, is not accepted
+    let access_token = response
+        .cookie
+        .unwrap()
+        .split_once('=')
+        .unwrap()
+        .1
+        .to_string();
+    app.activate(&access_token)
+        .await
+        .error(StatusCode::BAD_REQUEST);
+
+    app.activate(&token).await.success(StatusCode::OK);
+    let response = app.activate(&token).await;
+    assert_eq!(
+        response.error(StatusCode::BAD_REQUEST),
+        "Invalid verification token."
+    );
+}
+
+#[tokio::test]
+async fn admin_routes_require_authentication() {
+    let app = TestApp::new().await;
+
+    let response = app
+        .request(Method::GET, "/api/v1/admin/users", None, None)
+        .await;
+
+    assert_eq!(
+        response.error(StatusCode::UNAUTHORIZED),
+        "Missing access token."
+    );
+}
+
+#[tokio::test]
+async fn admin_routes_reject_regular_users() {
+    let app = TestApp::new().await;
+    let cookie = app.login_new_user("alice@example.com").await;
+
+    let response = app
+        .request(Method::GET, "/api/v1/admin/users", None, Some(&cookie))
+        .await;
+
+    assert_eq!(
+        response.error(StatusCode::FORBIDDEN),
+        "Insufficient permission."
+    );
+}